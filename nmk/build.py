@@ -22,6 +22,11 @@ TARGET_TRIPLE = {
     # arm build use non hard-float to maximize compatibility in one binary
     'arm': 'arm-unknown-linux-musleabi',
     'armv7': 'armv7-unknown-linux-musleabihf',
+    'i686': 'i686-unknown-linux-musl',
+    # musl targets of these architectures are not well supported by rust yet
+    'riscv64': 'riscv64gc-unknown-linux-gnu',
+    'ppc64le': 'powerpc64le-unknown-linux-gnu',
+    's390x': 's390x-unknown-linux-gnu',
 }
 
 
@@ -109,10 +114,14 @@ def build_release(target, strip=False, lto=False, commit_id=None):
     args = ['cross', 'build', '--release', '--target', target, '--target-dir', str(TARGET_DIR)]
     logging.info("Building %s target", target)
     logging.debug("env: %s", env)
-    logging.debug("cmd: %s", " ".join(args))
-    exit_code = subprocess.call(args, env=dict(os.environ.copy(), **env))
-    if exit_code != 0:
-        exit(exit_code)
+    # Entrypoint runs on every shell start, leave out async runtime and TLS stack.
+    # Archive is kept for `nmk backup`.
+    entrypoint = ['--bin', 'nmk', '--no-default-features', '--features', 'archive']
+    for features in (['--bin', 'nmkup'], entrypoint):
+        logging.debug("cmd: %s", " ".join(args + features))
+        exit_code = subprocess.call(args + features, env=dict(os.environ.copy(), **env))
+        if exit_code != 0:
+            exit(exit_code)
 
 
 def get_version_from_manifest(manifest_path):
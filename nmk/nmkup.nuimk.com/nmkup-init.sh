@@ -36,9 +36,15 @@ FLAGS:
     -f, --force        Force install
     -h, --help         Prints help information
         --no-filter    Do not filter items based on /etc/os-release data
+        --system       Install to system-wide root ($NMK_SYSTEM_HOME or /opt/nmk), requires root
     -V, --version      Prints version information
         --vendor       Install vendored files
     -v                 Request verbose logging
+
+OPTIONS:
+        --bundle <bundle>    Install from extracted offline installer instead of remote storage
+        --output <output>    Report format, json report is written to stdout [default: text]
+                             [possible values: text, json]
 EOF
 }
 
@@ -63,20 +69,35 @@ main() {
     esac
 
     local _url
+    # Keep in sync with TARGETS in src/bin/nmkup/build/target.rs
     case "$_arch" in
-        x86_64-unknown-linux-*)
+        x86_64-unknown-linux-gnu | x86_64-unknown-linux-musl)
             _url="${NMKUP_UPDATE_ROOT}/nmkup-x86_64-unknown-linux-musl"
             ;;
-        armv7-unknown-linux-gnueabihf)
-            # use armv7 for raspberry pi 4
-            _url="${NMKUP_UPDATE_ROOT}/nmkup-armv7-unknown-linux-musleabihf"
-            ;;
-        aarch64-unknown-linux-*)
+        aarch64-unknown-linux-gnu | aarch64-unknown-linux-musl)
             _url="${NMKUP_UPDATE_ROOT}/nmkup-aarch64-unknown-linux-musl"
             ;;
-        arm-unknown-linux-* | armv7-unknown-linux-*)
+        arm-unknown-linux-gnueabi | arm-unknown-linux-gnueabihf | arm-unknown-linux-musleabi | arm-unknown-linux-musleabihf)
             _url="${NMKUP_UPDATE_ROOT}/nmkup-arm-unknown-linux-musleabi"
             ;;
+        armv7-unknown-linux-gnueabi | armv7-unknown-linux-musleabi)
+            _url="${NMKUP_UPDATE_ROOT}/nmkup-arm-unknown-linux-musleabi"
+            ;;
+        armv7-unknown-linux-gnueabihf | armv7-unknown-linux-musleabihf)
+            _url="${NMKUP_UPDATE_ROOT}/nmkup-armv7-unknown-linux-musleabihf"
+            ;;
+        i686-unknown-linux-gnu | i686-unknown-linux-musl)
+            _url="${NMKUP_UPDATE_ROOT}/nmkup-i686-unknown-linux-musl"
+            ;;
+        riscv64gc-unknown-linux-gnu)
+            _url="${NMKUP_UPDATE_ROOT}/nmkup-riscv64gc-unknown-linux-gnu"
+            ;;
+        powerpc64le-unknown-linux-gnu)
+            _url="${NMKUP_UPDATE_ROOT}/nmkup-powerpc64le-unknown-linux-gnu"
+            ;;
+        s390x-unknown-linux-gnu)
+            _url="${NMKUP_UPDATE_ROOT}/nmkup-s390x-unknown-linux-gnu"
+            ;;
         *)
             err "Unsupported arch: $_arch"
         ;;
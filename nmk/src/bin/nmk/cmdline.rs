@@ -5,6 +5,8 @@ use once_cell::sync::Lazy;
 use structopt::clap::Shell;
 use structopt::StructOpt;
 
+use nmk::tmux::clipboard::Backend;
+
 use crate::version::get_verbose_version;
 
 static VERSION: Lazy<String> = Lazy::new(|| get_verbose_version().expect("missing version info"));
@@ -43,8 +45,26 @@ pub struct CmdOpt {
     pub usage: bool,
     #[structopt(long, help = "Display Message of The Day")]
     pub motd: bool,
+    #[structopt(
+        long,
+        value_name = "name",
+        help = "Use a colour theme, built-in or ~/.nmk/themes/<name>.toml [env: NMK_THEME]"
+    )]
+    pub theme: Option<String>,
+    #[structopt(
+        long,
+        value_name = "backend",
+        help = "Copy to clipboard with pbcopy, wl-copy, xclip, xsel, win32yank, clip.exe, osc52 or off [env: NMK_CLIPBOARD]"
+    )]
+    pub clipboard: Option<Backend>,
     #[structopt(long, help = "Render tmux config then exit")]
     pub render: bool,
+    #[structopt(
+        long,
+        requires = "render",
+        help = "Check rendered tmux config with a throwaway tmux server"
+    )]
+    pub check: bool,
     #[structopt(subcommand)]
     pub cmd: Option<SubCommand>,
     #[structopt(value_name = "command", help = "Tmux command")]
@@ -55,14 +75,46 @@ pub struct CmdOpt {
 
 #[derive(Debug, StructOpt)]
 pub enum SubCommand {
+    #[cfg(feature = "archive")]
     #[structopt(about = "Backup files to do clean install")]
     Backup,
     #[structopt(about = "Display entrypoint information")]
     Info,
+    #[structopt(about = "Check installation and environment for common problems")]
+    Doctor(Doctor),
+    #[structopt(about = "Open a workspace session, creating it from its layout file")]
+    Open(Open),
+    #[structopt(about = "Render tmux status line segments")]
+    Status(Status),
     #[structopt(about = "Generate tab-completion scripts for your shell")]
     Completions(Completion),
 }
 
+#[derive(Debug, strum::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Position {
+    Left,
+    Right,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Status {
+    #[structopt(possible_values = &["left", "right"], help = "Which segments of settings to render")]
+    pub position: Position,
+    #[structopt(
+        long,
+        value_name = "id",
+        help = "Pane like %1, its current directory is asked from tmux for git"
+    )]
+    pub pane: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Open {
+    #[structopt(help = "Workspace name, ~/.nmk/workspaces/<name>.toml")]
+    pub name: String,
+}
+
 #[derive(Debug, StructOpt)]
 pub struct Completion {
     #[structopt(short, long, help = "write to file instead of stdout")]
@@ -71,6 +123,12 @@ pub struct Completion {
     pub shell: Shell,
 }
 
+#[derive(Debug, StructOpt)]
+pub struct Doctor {
+    #[structopt(long, help = "Print findings as JSON")]
+    pub json: bool,
+}
+
 pub fn parse() -> CmdOpt {
     CmdOpt::from_args()
 }
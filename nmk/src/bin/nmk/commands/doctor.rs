@@ -0,0 +1,562 @@
+use std::ffi::OsStr;
+use std::fmt::{self, Display};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{env, fs};
+
+use nix::unistd::Uid;
+use same_file::is_same_file;
+use serde::Serialize;
+
+use nmk::bin_name::{TMUX, ZSH};
+use nmk::container;
+use nmk::env_name::{LD_LIBRARY_PATH, NMK_TMUX_VERSION, NMK_ZSH_GLOBAL_RCS, PATH};
+use nmk::error::{Error, ErrorKind};
+use nmk::home::NmkHome;
+use nmk::tmux::cache;
+use nmk::tmux::version::Version;
+
+use crate::cmdline::Doctor;
+use crate::terminal;
+use crate::tmux::{nmk_tmp_dir, tmux_socket_dir};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Pass => "pass",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Finding {
+    check: &'static str,
+    status: Status,
+    message: String,
+    /// How to fix it, only for warn and fail
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hint: Option<String>,
+}
+
+impl Finding {
+    fn pass(check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            status: Status::Pass,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(check: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            check,
+            status: Status::Warn,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(check: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            check,
+            status: Status::Fail,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    findings: Vec<Finding>,
+}
+
+impl Report {
+    fn count(&self, status: Status) -> usize {
+        self.findings.iter().filter(|f| f.status == status).count()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for finding in &self.findings {
+            writeln!(
+                f,
+                "[{}] {}: {}",
+                finding.status, finding.check, finding.message
+            )?;
+            if let Some(ref hint) = finding.hint {
+                writeln!(f, "       hint: {}", hint)?;
+            }
+        }
+        write!(
+            f,
+            "\n{} passed, {} warnings, {} failed",
+            self.count(Status::Pass),
+            self.count(Status::Warn),
+            self.count(Status::Fail)
+        )
+    }
+}
+
+pub fn doctor(opt: Doctor) -> nmk::Result<()> {
+    let nmk_home = NmkHome::locate();
+    let mut findings = vec![check_tmux(), check_zsh()];
+    match nmk_home {
+        Some(ref nmk_home) => {
+            findings.extend(check_layout(nmk_home));
+            findings.push(check_vendor_lib(nmk_home));
+            // PATH is only ours inside nmk, elsewhere every binary would be reported
+            if env::var_os(NMK_ZSH_GLOBAL_RCS).is_some() {
+                findings.extend(check_path_shadowing(nmk_home));
+            }
+        }
+        None => findings.push(Finding::fail(
+            "nmk-home",
+            "NMK_HOME not found",
+            "run nmkup to install dotfiles, or set NMK_HOME to an existing directory",
+        )),
+    }
+    findings.push(check_locale(|name| env::var(name).ok()));
+    findings.push(check_terminal());
+    findings.extend(check_temp_configs(&nmk_tmp_dir(), &tmux_socket_dir()));
+
+    let report = Report { findings };
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{}", report);
+    }
+    match report.count(Status::Fail) {
+        0 => Ok(()),
+        n => Err(Error::msg(
+            ErrorKind::Other,
+            format!("{} check(s) failed", n),
+        )),
+    }
+}
+
+/// Second word of `<bin> --version` like output, e.g. `zsh 5.8 (x86_64-pc-linux-gnu)`
+fn version_word(bin: &Path, arg: &str) -> Option<String> {
+    let output = Command::new(bin).arg(arg).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .split_ascii_whitespace()
+        .nth(1)
+        .map(String::from)
+}
+
+fn check_tmux() -> Finding {
+    const CHECK: &str = "tmux";
+    let bin = match which::which(TMUX) {
+        Ok(bin) => bin,
+        Err(_) => {
+            return Finding::fail(CHECK, "tmux not found in PATH", "install tmux or run nmkup")
+        }
+    };
+    let version = match version_word(&bin, "-V") {
+        Some(version) => version,
+        None => {
+            return Finding::fail(
+                CHECK,
+                format!("cannot get version of {}", bin.display()),
+                format!("check that `{} -V` works", bin.display()),
+            )
+        }
+    };
+    let supported = format!("nmk supports tmux {} and later", Version::OLDEST);
+    let finding = match Version::from_version(&version) {
+        Ok(v) if v.is_unknown_newer() => Finding::pass(
+            CHECK,
+            format!(
+                "tmux {} at {}, newer than {} which nmk knows, assumed compatible",
+                version,
+                bin.display(),
+                Version::NEWEST
+            ),
+        ),
+        Ok(_) => Finding::pass(CHECK, format!("tmux {} at {}", version, bin.display())),
+        Err(_) => Finding::fail(
+            CHECK,
+            format!("tmux {} at {} is not supported", version, bin.display()),
+            format!("{}, upgrade tmux or run nmkup", supported),
+        ),
+    };
+    match env::var(NMK_TMUX_VERSION) {
+        Ok(forced) if finding.status == Status::Pass => Finding::warn(
+            CHECK,
+            format!(
+                "{}, but {}={} overrides it",
+                finding.message, NMK_TMUX_VERSION, forced
+            ),
+            format!("unset {} unless detection is wrong", NMK_TMUX_VERSION),
+        ),
+        _ => finding,
+    }
+}
+
+fn check_zsh() -> Finding {
+    const CHECK: &str = "zsh";
+    match which::which(ZSH) {
+        Ok(bin) => match version_word(&bin, "--version") {
+            Some(version) => Finding::pass(CHECK, format!("zsh {} at {}", version, bin.display())),
+            None => Finding::warn(
+                CHECK,
+                format!("cannot get version of {}", bin.display()),
+                format!("check that `{} --version` works", bin.display()),
+            ),
+        },
+        Err(_) => Finding::fail(CHECK, "zsh not found in PATH", "install zsh or run nmkup"),
+    }
+}
+
+fn check_layout(nmk_home: &NmkHome) -> Vec<Finding> {
+    const CHECK: &str = "nmk-home";
+    const HINT: &str = "run nmkup to reinstall dotfiles";
+    let nmk_path = nmk_home.nmk_path();
+    let mut findings = vec![Finding::pass(CHECK, format!("NMK_HOME is {:?}", nmk_home))];
+    let required = [
+        nmk_path.zsh().join(".zshrc"),
+        nmk_path.vim().join("init.vim"),
+    ];
+    for path in IntoIterator::into_iter(required).filter(|p| !p.exists()) {
+        findings.push(Finding::fail(
+            CHECK,
+            format!("{} is missing", path.display()),
+            HINT,
+        ));
+    }
+    let optional = [nmk_path.bin(), nmk_path.dotfiles_meta()];
+    for path in IntoIterator::into_iter(optional).filter(|p| !p.exists()) {
+        findings.push(Finding::warn(
+            CHECK,
+            format!("{} is missing", path.display()),
+            HINT,
+        ));
+    }
+    findings
+}
+
+/// Libraries reported as `libfoo.so => not found` by ldd
+fn missing_libraries(ldd_output: &str) -> Vec<&str> {
+    ldd_output
+        .lines()
+        .filter(|line| line.contains("not found"))
+        .filter_map(|line| line.split_ascii_whitespace().next())
+        .collect()
+}
+
+fn check_vendor_lib(nmk_home: &NmkHome) -> Finding {
+    const CHECK: &str = "vendor-lib";
+    let nmk_path = nmk_home.nmk_path();
+    let (vendor_bin, vendor_lib) = (nmk_path.vendor_bin(), nmk_path.vendor_lib());
+    let binaries: Vec<PathBuf> = match fs::read_dir(&vendor_bin) {
+        Ok(entries) => entries.flatten().map(|e| e.path()).collect(),
+        Err(_) => return Finding::pass(CHECK, "no vendor binaries, using system ones"),
+    };
+    let mut missing = Vec::new();
+    for bin in &binaries {
+        let output = match Command::new("ldd")
+            .arg(bin)
+            .env(LD_LIBRARY_PATH, &vendor_lib)
+            .output()
+        {
+            Ok(output) => output,
+            Err(_) => {
+                return Finding::warn(
+                    CHECK,
+                    "ldd not found, cannot check vendor libraries",
+                    "install ldd (libc-bin) to run this check",
+                )
+            }
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for lib in missing_libraries(&stdout) {
+            missing.push(format!("{} needs {}", bin.display(), lib));
+        }
+    }
+    if missing.is_empty() {
+        Finding::pass(
+            CHECK,
+            format!("libraries of {} binaries resolve", binaries.len()),
+        )
+    } else {
+        Finding::fail(
+            CHECK,
+            missing.join(", "),
+            format!(
+                "reinstall vendor files with nmkup, or install the libraries to {}",
+                vendor_lib.display()
+            ),
+        )
+    }
+}
+
+/// nmk binaries which resolve to another file when looked up from PATH, run inside nmk only
+fn check_path_shadowing(nmk_home: &NmkHome) -> Vec<Finding> {
+    const CHECK: &str = "path";
+    let search_path = env::var_os(PATH).unwrap_or_default();
+    let mut findings = Vec::new();
+    for nmk_path in nmk_home.layers() {
+        for dir in &[nmk_path.bin(), nmk_path.vendor_bin()] {
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for ours in entries.flatten().map(|e| e.path()) {
+                let name = match ours.file_name() {
+                    Some(name) => name,
+                    None => continue,
+                };
+                if let Some(found) = shadowed_by(name, &ours, &search_path) {
+                    findings.push(Finding::warn(
+                        CHECK,
+                        format!("{} is shadowed by {}", ours.display(), found.display()),
+                        format!(
+                            "move {} before {} in PATH, or remove {}",
+                            dir.display(),
+                            found.parent().unwrap_or(&found).display(),
+                            found.display()
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    if findings.is_empty() {
+        findings.push(Finding::pass(CHECK, "no nmk binary is shadowed"));
+    }
+    findings
+}
+
+/// First `name` found in `search_path` if it is not `ours`
+fn shadowed_by(name: &OsStr, ours: &Path, search_path: &OsStr) -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    let found = which::which_in(name, Some(search_path), cwd).ok()?;
+    if is_same_file(&found, ours).unwrap_or(false) {
+        None
+    } else {
+        Some(found)
+    }
+}
+
+/// Locale of character classification, LC_ALL overrides LC_CTYPE which overrides LANG
+fn check_locale<F: Fn(&str) -> Option<String>>(var: F) -> Finding {
+    const CHECK: &str = "locale";
+    let found = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|name| var(name).filter(|v| !v.is_empty()).map(|v| (*name, v)));
+    match found {
+        Some((name, value)) => {
+            let lower = value.to_lowercase();
+            if lower.contains("utf-8") || lower.contains("utf8") {
+                Finding::pass(CHECK, format!("{}={}", name, value))
+            } else {
+                Finding::warn(
+                    CHECK,
+                    format!("{}={} is not UTF-8", name, value),
+                    "set LANG to a UTF-8 locale like en_US.UTF-8, or run nmk with -u",
+                )
+            }
+        }
+        None => Finding::warn(
+            CHECK,
+            "none of LC_ALL, LC_CTYPE and LANG is set",
+            "set LANG to a UTF-8 locale like en_US.UTF-8, or run nmk with -u",
+        ),
+    }
+}
+
+fn check_terminal() -> Finding {
+    const CHECK: &str = "terminal";
+    let var = |name| env::var(name).unwrap_or_default();
+    let message = format!("TERM={:?} COLORTERM={:?}", var("TERM"), var("COLORTERM"));
+    let support = terminal::color_support();
+    if support.color_256 || container::is_containerized() {
+        let reason = if !support.color_256 {
+            " (assumed inside container)"
+        } else if support.truecolor {
+            " and 24-bit colour"
+        } else {
+            ""
+        };
+        let default_term = terminal::default_term(true);
+        Finding::pass(
+            CHECK,
+            format!(
+                "{} supports 256 colours{}, tmux uses {}",
+                message, reason, default_term
+            ),
+        )
+    } else {
+        Finding::warn(
+            CHECK,
+            format!("{} is not known to support 256 colours", message),
+            "run nmk with -2 if your terminal supports 256 colours",
+        )
+    }
+}
+
+/// Rendered configs are `<socket>.tmux.conf`, kept as cache for the next start of their socket
+///
+/// Configs without cache key come from older nmk, they are stale once their tmux server is gone.
+fn check_temp_configs(nmk_tmp_dir: &Path, socket_dir: &Path) -> Vec<Finding> {
+    const CHECK: &str = "temp-config";
+    let metadata = match nmk_tmp_dir.metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return vec![Finding::pass(CHECK, "no rendered config yet")],
+    };
+    let mut findings = Vec::new();
+    let mode = metadata.permissions().mode() & 0o777;
+    if metadata.uid() != Uid::current().as_raw() || mode != 0o700 {
+        findings.push(Finding::fail(
+            CHECK,
+            format!(
+                "{} has owner {} and mode {:o}, expect {} and 700",
+                nmk_tmp_dir.display(),
+                metadata.uid(),
+                mode,
+                Uid::current()
+            ),
+            format!("remove {}, nmk recreates it", nmk_tmp_dir.display()),
+        ));
+    }
+    let stale: Vec<PathBuf> = fs::read_dir(nmk_tmp_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(OsStr::to_str)
+                .and_then(|name| name.strip_suffix(".tmux.conf"))
+                .is_some_and(|socket| !socket_dir.join(socket).exists())
+                && cache::read_key(path).is_none()
+        })
+        .collect();
+    if stale.is_empty() {
+        if findings.is_empty() {
+            findings.push(Finding::pass(CHECK, "no stale rendered config"));
+        }
+    } else {
+        let names: Vec<_> = stale.iter().map(|p| p.display().to_string()).collect();
+        findings.push(Finding::warn(
+            CHECK,
+            format!("{} stale: {}", stale.len(), names.join(", ")),
+            format!("remove them, e.g. rm {}", shell_words::join(&names)),
+        ));
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_check_locale() {
+        let env = |pairs: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                pairs
+                    .iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        let finding = check_locale(env(&[("LANG", "en_US.UTF-8")]));
+        assert_eq!(finding.status, Status::Pass);
+        let finding = check_locale(env(&[("LANG", "C.utf8")]));
+        assert_eq!(finding.status, Status::Pass);
+        // LC_ALL takes precedence
+        let finding = check_locale(env(&[("LC_ALL", "C"), ("LANG", "en_US.UTF-8")]));
+        assert_eq!(finding.status, Status::Warn);
+        assert_eq!(finding.message, "LC_ALL=C is not UTF-8");
+        // empty value is ignored
+        let finding = check_locale(env(&[("LC_CTYPE", ""), ("LANG", "th_TH.UTF-8")]));
+        assert_eq!(finding.status, Status::Pass);
+        let finding = check_locale(env(&[]));
+        assert_eq!(finding.status, Status::Warn);
+        assert!(finding.hint.is_some());
+    }
+
+    #[test]
+    fn test_missing_libraries() {
+        let output = "\tlinux-vdso.so.1 (0x00007ffd)\n\
+                      \tlibevent-2.1.so.7 => not found\n\
+                      \tlibc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007f)\n\
+                      \tlibtinfo.so.6 => not found\n";
+        assert_eq!(
+            missing_libraries(output),
+            vec!["libevent-2.1.so.7", "libtinfo.so.6"]
+        );
+        assert!(missing_libraries("\tstatically linked\n").is_empty());
+    }
+
+    #[test]
+    fn test_check_temp_configs() {
+        let root = TempDir::new("doctor");
+        let (tmp_dir, socket_dir) = (root.join("nmk"), root.join("tmux"));
+        fs::create_dir_all(&tmp_dir).unwrap();
+        fs::create_dir_all(&socket_dir).unwrap();
+        fs::set_permissions(&tmp_dir, fs::Permissions::from_mode(0o700)).unwrap();
+        fs::write(tmp_dir.join("nmk.tmux.conf"), "").unwrap();
+        fs::write(socket_dir.join("nmk"), "").unwrap();
+
+        let findings = check_temp_configs(&tmp_dir, &socket_dir);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].status, Status::Pass);
+
+        fs::write(tmp_dir.join("old.tmux.conf"), "").unwrap();
+        let findings = check_temp_configs(&tmp_dir, &socket_dir);
+        assert_eq!(findings[0].status, Status::Warn);
+        assert!(findings[0].message.contains("old.tmux.conf"));
+        assert!(!findings[0].message.contains("nmk.tmux.conf"));
+
+        // cache for the next start of its socket
+        fs::remove_file(tmp_dir.join("old.tmux.conf")).unwrap();
+        cache::write(&tmp_dir.join("cached.tmux.conf"), "0123abcd", b"").unwrap();
+        let findings = check_temp_configs(&tmp_dir, &socket_dir);
+        assert_eq!(findings[0].status, Status::Pass);
+
+        fs::set_permissions(&tmp_dir, fs::Permissions::from_mode(0o755)).unwrap();
+        let findings = check_temp_configs(&tmp_dir, &socket_dir);
+        assert_eq!(findings[0].status, Status::Fail);
+
+        let findings = check_temp_configs(&root.join("missing"), &socket_dir);
+        assert_eq!(findings[0].status, Status::Pass);
+    }
+
+    #[test]
+    fn test_report() {
+        let report = Report {
+            findings: vec![
+                Finding::pass("zsh", "zsh 5.8 at /bin/zsh"),
+                Finding::fail("tmux", "tmux not found in PATH", "install tmux"),
+            ],
+        };
+        let text = report.to_string();
+        assert!(text.contains("[pass] zsh: zsh 5.8 at /bin/zsh\n"));
+        assert!(text.contains("[fail] tmux: tmux not found in PATH\n       hint: install tmux"));
+        assert!(text.ends_with("1 passed, 0 warnings, 1 failed"));
+
+        let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["findings"][0]["status"], "pass");
+        assert!(json["findings"][0].get("hint").is_none());
+        assert_eq!(json["findings"][1]["hint"], "install tmux");
+    }
+}
@@ -1,11 +1,13 @@
 use serde::Serialize;
 
 use nmk::arch::detect_current_architecture;
+use nmk::container;
 use nmk::human_time::{seconds_since_build, HumanTime};
 
 #[derive(Serialize)]
 struct Info {
     nmk: Nmk,
+    container: Container,
     rustup: Rustup,
     toolchain: Toolchain,
 }
@@ -16,6 +18,13 @@ struct Toolchain {
     target: &'static str,
 }
 
+#[derive(Serialize)]
+struct Container {
+    kind: &'static str,
+    source: Option<&'static str>,
+    confidence: Option<container::Confidence>,
+}
+
 #[derive(Serialize)]
 struct Rustup {
     get_architecture: String,
@@ -30,10 +39,16 @@ struct Nmk {
 pub fn print_info() -> nmk::Result<()> {
     let version = get_version();
     let build_on = seconds_since_build().map(|secs| format!("{} ago", HumanTime::new(secs)));
+    let detected = container::detect();
     let info = Info {
         nmk: Nmk { version, build_on },
+        container: Container {
+            kind: detected.map_or("none", |c| c.kind.as_str()),
+            source: detected.map(|c| c.source.as_str()),
+            confidence: detected.map(|c| c.source.confidence()),
+        },
         rustup: Rustup {
-            get_architecture: detect_current_architecture()?,
+            get_architecture: detect_current_architecture()?.to_string(),
         },
         toolchain: Toolchain {
             rustc: env!("BUILD_RUSTC_VERSION"),
@@ -1,3 +1,6 @@
+#[cfg(feature = "archive")]
 pub mod backup;
 pub mod completion;
+pub mod doctor;
 pub mod info;
+pub mod status;
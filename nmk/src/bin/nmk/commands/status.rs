@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+use nmk::bin_name::TMUX;
+use nmk::home::NmkHome;
+use nmk::settings::Settings;
+use nmk::status::{self, Request, Segment, StatusCache};
+
+use crate::cmdline::{Position, Status};
+use crate::tmux::create_nmk_tmp_dir;
+
+const CACHE_FILE: &str = "status.json";
+
+/// Called by tmux on every status interval, nothing here is worth an error on the status line
+pub fn status(opt: Status) -> nmk::Result<()> {
+    let settings = match NmkHome::locate() {
+        Some(nmk_home) => Settings::load(&nmk_home),
+        None => Settings::default(),
+    };
+    let segments = match opt.position {
+        Position::Left => &settings.status.left,
+        Position::Right => &settings.status.right,
+    };
+    if segments.is_empty() {
+        return Ok(());
+    }
+    let cache_path = create_nmk_tmp_dir()?.join(CACHE_FILE);
+    let mut cache = StatusCache::load(&cache_path);
+    let pane_path = opt.pane.as_deref().and_then(pane_current_path);
+    let request = Request {
+        segments,
+        pane_path: pane_path.as_deref(),
+        ttl: &|segment| settings.status.ttl(segment),
+        timeout: Duration::from_millis(settings.status.timeout_ms),
+        compute: Segment::compute,
+    };
+    let (rendered, changed) = status::render(&request, &mut cache);
+    println!("{}", rendered);
+    if changed {
+        if let Err(e) = cache.save(&cache_path) {
+            log::warn!("Failed to save {:?}: {}", cache_path, e);
+        }
+    }
+    Ok(())
+}
+
+/// Directory is asked from tmux instead of being put in the status command, where a shell
+/// would expand whatever the directory name has
+fn pane_current_path(pane: &str) -> Option<PathBuf> {
+    let output = Command::new(TMUX)
+        .args(["display-message", "-p", "-t", pane, "#{pane_current_path}"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let path = String::from_utf8(output.stdout).ok()?;
+    let path = path.trim_end_matches('\n');
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
@@ -1,15 +1,24 @@
-use std::array::IntoIter;
 use std::ffi::OsStr;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Write;
+use std::os::unix::process::CommandExt;
 use std::path::Path;
+use std::process::{Command, Stdio};
 use std::{env, io};
 
-use nmk::env_name::{EDITOR, LD_LIBRARY_PATH, NMK_HOME, NMK_TMUX_VERSION, PATH, VIMINIT, ZDOTDIR};
+use nmk::env_name::{
+    EDITOR, LD_LIBRARY_PATH, NMK_HOME, NMK_SYSTEM_HOME, NMK_TMUX_VERSION, PATH, VIMINIT, ZDOTDIR,
+};
 use nmk::home::NmkHome;
 use nmk::human_time::{seconds_since_build, HumanTime};
+use nmk::platform;
+use nmk::settings::Settings;
+use nmk::tmux::check;
+use nmk::tmux::config::Context;
+use nmk::tmux::workspace::Workspace;
+use nmk::update_cache::{epoch_seconds, UpdateCache};
 
-use crate::cmdline::CmdOpt;
+use crate::cmdline::{CmdOpt, Open};
 use crate::path_vec::PathVec;
 use crate::terminal;
 use crate::tmux::{make_config_context, Tmux};
@@ -24,6 +33,14 @@ pub fn set_env<K: AsRef<str>, V: AsRef<OsStr>>(key: K, value: V) {
 fn setup_environment_variable(nmk_home: &NmkHome) {
     set_env(NMK_HOME, nmk_home);
     set_env(ZDOTDIR, nmk_home.nmk_path().zsh());
+    if let Some(system) = nmk_home.system() {
+        set_env(NMK_SYSTEM_HOME, system);
+        // Personal home of system-wide installation keeps zsh histories and extras
+        let zsh_personal = nmk_home.nmk_path().zsh_personal();
+        if let Err(e) = fs::create_dir_all(&zsh_personal) {
+            log::warn!("Failed to create {:?}: {}", zsh_personal, e);
+        }
+    }
 
     // Setup Vim
     let vim_dir = nmk_home.nmk_path().vim();
@@ -54,18 +71,23 @@ fn setup_preferred_editor() {
 }
 
 fn setup_shell_search_path(nmk_home: &NmkHome) {
-    let nmk_path = nmk_home.nmk_path();
     let mut search_path = PathVec::from(env::var_os(PATH).expect("$PATH not found"));
-    let nmk_search_path = [
-        nmk_path.bin(),
-        // vendor directory
-        nmk_path.vendor_bin(),
-    ];
-    search_path = IntoIter::new(nmk_search_path)
+    // Personal home comes before system-wide root
+    let nmk_search_path = nmk_home.layers().flat_map(|nmk_path| {
+        [
+            nmk_path.bin(),
+            // vendor directory
+            nmk_path.vendor_bin(),
+        ]
+    });
+    search_path = nmk_search_path
         .filter(|p| p.exists())
         .chain(search_path)
         .collect();
     search_path = search_path.unique().without_version_managers();
+    if platform::is_wsl() {
+        search_path = search_path.windows_paths_last();
+    }
     set_env(PATH, search_path.join());
 }
 
@@ -90,64 +112,208 @@ fn display_message_of_the_day() -> io::Result<()> {
         .try_for_each(|mut f| io::copy(&mut f, &mut stdout).map(drop))
 }
 
-const DAY_SECS: u64 = 24 * 60 * 60;
-
-fn check_for_update_suggest() {
-    if let Some(secs) = seconds_since_build() {
-        if secs > 45 * DAY_SECS {
+fn check_for_update_suggest(nmk_home: &NmkHome) {
+    let settings = Settings::load(nmk_home);
+    let mut cache = UpdateCache::load(nmk_home).unwrap_or_default();
+    if cache.is_checked() {
+        let outdated = cache.outdated_components(nmk_home);
+        if !outdated.is_empty() {
+            println!(
+                "\n{}\n",
+                update_notice(&outdated, nmk_home.is_system_install())
+            );
+        }
+    } else if let Some(secs) = seconds_since_build() {
+        if secs > settings.update.suggest_after.as_secs() {
             println!(
                 "\nnmk: I's been {} since build.\n",
-                HumanTime::new(secs).to_human(2)
+                HumanTime::new(secs).to_human_in_days(2)
             );
         }
     }
+    let now = epoch_seconds();
+    if cache.should_refresh(now) {
+        // Record the attempt first, so a failing refresh doesn't run on every login
+        cache.attempted_at = now;
+        if let Err(e) = cache.save(nmk_home) {
+            log::debug!("Failed to save update cache: {}", e);
+            return;
+        }
+        spawn_metadata_refresh(nmk_home);
+    }
 }
 
-pub fn main(cmd_opt: CmdOpt) -> io::Result<()> {
-    if cmd_opt.motd {
-        display_message_of_the_day()?;
-        check_for_update_suggest()
+/// A system-wide installation is updated by its administrator, not the user who logs in
+fn update_notice(outdated: &[&str], system_install: bool) -> String {
+    let action = if system_install {
+        "ask your administrator to run nmkup --system"
+    } else {
+        "run nmkup to update"
+    };
+    format!("nmk: Newer {} available, {}.", outdated.join(", "), action)
+}
+
+/// Refresh remote metadata in background, we never wait for it
+///
+/// It runs in its own session, so closing the terminal or Ctrl-C at the prompt doesn't kill it.
+fn spawn_metadata_refresh(nmk_home: &NmkHome) {
+    // nmkup of a system-wide installation is in its root, personal bin may hold other things
+    let nmkup = match nmk_home
+        .layers()
+        .map(|nmk_path| nmk_path.bin().join("nmkup"))
+        .find(|path| path.exists())
+    {
+        Some(nmkup) => nmkup,
+        None => return,
+    };
+    let mut command = Command::new(&nmkup);
+    command
+        .arg("--refresh-metadata")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Only async-signal-safe calls are allowed between fork and exec
+    unsafe {
+        command.pre_exec(|| {
+            if nix::libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let result = command.spawn();
+    match result {
+        Ok(child) => log::debug!("Refreshing metadata in background, pid {}", child.id()),
+        Err(e) => log::debug!("Failed to spawn {:?}: {}", nmkup, e),
+    }
+}
+
+/// Report what tmux thinks of rendered config, fail on any error
+fn check_config(tmux: &Tmux, config: Config) -> io::Result<()> {
+    let errors = match config {
+        Config::Rendered(context) => check::check(&tmux.bin, context, tmux.version),
+        Config::File(path) => check::check_file(&tmux.bin, path),
+    };
+    let errors = errors.map_err(|e| io::Error::other(e.to_string()))?;
+    if errors.is_empty() {
+        println!("tmux {} accepts the configuration", tmux.version);
+        return Ok(());
     }
+    for error in &errors {
+        println!("{}", error);
+    }
+    Err(io::Error::other(format!(
+        "tmux {} reported {} error(s)",
+        tmux.version,
+        errors.len()
+    )))
+}
+
+/// Config which `--check` hands to tmux
+enum Config<'a> {
+    Rendered(&'a Context),
+    /// `--tmux-conf` of user
+    File(&'a Path),
+}
 
+/// Paths to find and run our tmux and zsh
+fn setup_search_paths(nmk_home: &NmkHome) {
+    setup_shell_library_path(nmk_home);
+    setup_shell_search_path(nmk_home);
+}
+
+/// Environment which shells in tmux expect
+fn setup(nmk_home: &NmkHome) {
+    setup_search_paths(nmk_home);
+    setup_environment_variable(nmk_home);
+    crate::zsh::init(nmk_home);
+}
+
+fn find_tmux() -> Tmux {
+    let tmux = Tmux::new();
+    log::debug!("tmux path = {:?}", tmux.bin);
+    log::debug!("tmux version = {}", tmux.version);
+    set_env(NMK_TMUX_VERSION, tmux.version.to_string());
+    tmux
+}
+
+pub fn main(cmd_opt: CmdOpt) -> io::Result<()> {
     let nmk_home = NmkHome::locate().expect("failed to locate dotfiles directory");
     log::debug!("dotfiles directory: {:?}", nmk_home);
 
-    setup_shell_library_path(&nmk_home);
-    setup_shell_search_path(&nmk_home);
-    setup_environment_variable(&nmk_home);
-    crate::zsh::init(&nmk_home);
+    if cmd_opt.motd {
+        display_message_of_the_day()?;
+        check_for_update_suggest(&nmk_home)
+    }
+
+    if cmd_opt.render {
+        // No shell is started, so nothing is set up for it
+        setup_search_paths(&nmk_home);
+        return render(&cmd_opt, &nmk_home);
+    }
+    setup(&nmk_home);
     if cmd_opt.login {
         crate::zsh::exec_login_shell(&cmd_opt);
     } else {
-        let tmux = Tmux::new();
-        log::debug!("tmux path = {:?}", tmux.bin);
-        log::debug!("tmux version = {}", tmux.version);
-        set_env(NMK_TMUX_VERSION, tmux.version.as_str());
-        let support_256_color = cmd_opt.force_256_color || terminal::support_256_color();
+        let tmux = find_tmux();
+        let colors = terminal::color_support();
+        let support_256_color = cmd_opt.force_256_color || colors.support_256_color();
         let tmp_config;
         let config = if let Some(ref conf) = cmd_opt.tmux_conf {
             conf
         } else {
-            let context = make_config_context(&cmd_opt, support_256_color);
-            let mut buf = Vec::with_capacity(8192);
-            nmk::tmux::config::render(&mut buf, &context, tmux.version)?;
-            log::debug!(
-                "tmux configuration length: {}, capacity: {}, remaining bytes before re-alloc: {}",
-                buf.len(),
-                buf.capacity(),
-                buf.capacity() - buf.len(),
-            );
-            if cmd_opt.render {
-                return io::stdout().write_all(&buf);
-            } else {
-                tmp_config = tmux.write_config_in_temp_dir(&cmd_opt, &buf)?;
-                &tmp_config
-            }
+            let context = make_config_context(&cmd_opt, &nmk_home, colors, support_256_color);
+            tmp_config = tmux.cached_config(&cmd_opt, &context)?;
+            &tmp_config
         };
         tmux.exec(&cmd_opt, config, support_256_color);
     }
 }
 
+/// `--render` and `--check`, config of `--tmux-conf` is printed or checked as it is
+fn render(cmd_opt: &CmdOpt, nmk_home: &NmkHome) -> io::Result<()> {
+    let tmux = find_tmux();
+    if let Some(ref conf) = cmd_opt.tmux_conf {
+        if cmd_opt.check {
+            return check_config(&tmux, Config::File(conf));
+        }
+        return io::copy(&mut File::open(conf)?, &mut io::stdout()).map(drop);
+    }
+    let colors = terminal::color_support();
+    let support_256_color = cmd_opt.force_256_color || colors.support_256_color();
+    let context = make_config_context(cmd_opt, nmk_home, colors, support_256_color);
+    if cmd_opt.check {
+        check_config(&tmux, Config::Rendered(&context))
+    } else {
+        let buf = tmux.render_config(&context)?;
+        io::stdout().write_all(&buf)
+    }
+}
+
+/// `nmk open`, the workspace file is read before anything else so a broken one fails early
+pub fn open(cmd_opt: CmdOpt, opt: Open) -> nmk::Result<()> {
+    let nmk_home = NmkHome::locate().expect("failed to locate dotfiles directory");
+    log::debug!("dotfiles directory: {:?}", nmk_home);
+    let dirs: Vec<_> = nmk_home
+        .layers()
+        .map(|nmk_path| nmk_path.workspaces())
+        .collect();
+    let workspace = Workspace::load(&opt.name, &dirs)?;
+
+    setup(&nmk_home);
+    let tmux = find_tmux();
+    let colors = terminal::color_support();
+    let support_256_color = cmd_opt.force_256_color || colors.support_256_color();
+    let config = match cmd_opt.tmux_conf {
+        Some(ref conf) => conf.clone(),
+        None => {
+            let context = make_config_context(&cmd_opt, &nmk_home, colors, support_256_color);
+            tmux.cached_config(&cmd_opt, &context)?
+        }
+    };
+    tmux.open(&cmd_opt, &config, support_256_color, &workspace)
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
@@ -164,4 +330,16 @@ mod tests {
         let actual = build_vim_init(&vim_dir);
         assert_eq!(actual, "source '/home/user with space/.nmk/vim/init.vim'");
     }
+
+    #[test]
+    fn test_update_notice() {
+        assert_eq!(
+            update_notice(&["dotfiles", "entrypoint"], false),
+            "nmk: Newer dotfiles, entrypoint available, run nmkup to update."
+        );
+        assert_eq!(
+            update_notice(&["dotfiles"], true),
+            "nmk: Newer dotfiles available, ask your administrator to run nmkup --system."
+        );
+    }
 }
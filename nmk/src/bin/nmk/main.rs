@@ -4,21 +4,36 @@ mod entrypoint;
 mod logging;
 mod path_vec;
 mod terminal;
+#[cfg(test)]
+#[path = "../../nmk/test_util.rs"]
+mod test_util;
 mod tmux;
 mod utils;
 mod version;
 mod zsh;
 
-fn main() -> nmk::Result<()> {
-    let mut cmd_opt = cmdline::parse();
+fn main() {
+    let cmd_opt = cmdline::parse();
     logging::setup(cmd_opt.verbosity);
     log::debug!("Command line options: {:#?}", cmd_opt);
+    let verbose = cmd_opt.verbosity > 0;
+    if let Err(e) = run(cmd_opt) {
+        log::error!("{}", e.report(verbose));
+        std::process::exit(1);
+    }
+}
+
+fn run(mut cmd_opt: cmdline::CmdOpt) -> nmk::Result<()> {
     if let Some(cmd) = cmd_opt.cmd.take() {
         use cmdline::SubCommand::*;
         match cmd {
+            #[cfg(feature = "archive")]
             Backup => commands::backup::backup()?,
             Completions(c) => commands::completion::generate_completion(c),
+            Doctor(opt) => commands::doctor::doctor(opt)?,
             Info => commands::info::print_info()?,
+            Open(opt) => entrypoint::open(cmd_opt, opt)?,
+            Status(opt) => commands::status::status(opt)?,
         }
     } else {
         entrypoint::main(cmd_opt)?;
@@ -56,6 +56,22 @@ impl PathVec {
         self
     }
 
+    /// Move Windows paths which WSL appends (e.g. `/mnt/c/Windows`) after all linux paths
+    ///
+    /// Searching on Windows filesystem is slow, and nmk paths prepended later must not be
+    /// shadowed by Windows binaries of the same name.
+    pub fn windows_paths_last(self) -> Self {
+        #[inline]
+        fn windows_path(p: &Path) -> bool {
+            let mut components = p.components().skip(1);
+            components.next().is_some_and(|c| c.as_os_str() == "mnt")
+                && components.next().is_some_and(|c| c.as_os_str().len() == 1)
+        }
+        let (windows, linux): (Vec<_>, Vec<_>) =
+            self.vec.into_iter().partition(|p| windows_path(p));
+        linux.into_iter().chain(windows).collect()
+    }
+
     pub fn prepend<T: Into<PathBuf>>(&mut self, path: T) {
         self.vec.push_front(path.into())
     }
@@ -162,6 +178,18 @@ mod tests {
         assert_eq!(actual, OsString::from("/a"))
     }
 
+    #[test]
+    fn test_windows_paths_last() {
+        let input = OsString::from(
+            "/mnt/c/Windows/system32:/usr/bin:/mnt/c/Program Files/Git/cmd:/mnt/data/bin:/bin",
+        );
+        let ps = PathVec::from(input).windows_paths_last();
+        let expect = OsString::from(
+            "/usr/bin:/mnt/data/bin:/bin:/mnt/c/Windows/system32:/mnt/c/Program Files/Git/cmd",
+        );
+        assert_eq!(ps.join(), expect);
+    }
+
     #[test]
     fn test_unique() {
         let input = OsString::from("/a:/b:/a");
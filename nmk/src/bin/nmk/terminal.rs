@@ -1,30 +1,104 @@
 use std::env;
 
 use nmk::container;
+use nmk::terminfo::{self, Terminfo};
 
+use crate::tmux::create_nmk_tmp_dir;
+
+/// Terminals which are known to support 256 colours but whose terminfo may be missing
 fn is_term_256_color(term: impl AsRef<str>) -> bool {
+    let term = term.as_ref();
     [
+        "alacritty",
         "cygwin",
+        "foot",
         "gnome-256color",
         "putty",
         "screen-256color",
+        "tmux-256color",
+        "wezterm",
         "xterm-256color",
+        "xterm-kitty",
     ]
-    .contains(&term.as_ref())
+    .contains(&term)
+        || term.ends_with("-256color")
+        || term.ends_with("-direct")
 }
 
 fn is_colorterm_256_color(term: impl AsRef<str>) -> bool {
     ["gnome-terminal", "rxvt-xpm", "xfce4-terminal"].contains(&term.as_ref())
 }
 
-pub fn support_256_color() -> bool {
-    env::var("TERM").map_or(false, is_term_256_color)
-        || env::var("COLORTERM").map_or(false, is_colorterm_256_color)
-        || container::is_containerized()
+fn is_colorterm_truecolor(term: impl AsRef<str>) -> bool {
+    ["truecolor", "24bit"].contains(&term.as_ref())
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ColorSupport {
+    pub color_256: bool,
+    pub truecolor: bool,
+}
+
+/// Decide colour support from TERM, COLORTERM and the terminfo entry of TERM
+///
+/// Terminfo is trusted when it claims more, lists cover terminals whose entry is not installed.
+fn detect(term: &str, colorterm: &str, info: Option<&Terminfo>) -> ColorSupport {
+    let truecolor = is_colorterm_truecolor(colorterm) || info.is_some_and(Terminfo::has_rgb);
+    let color_256 = truecolor
+        || info.is_some_and(|info| info.colors.is_some_and(|n| n >= 256))
+        || is_term_256_color(term)
+        || is_colorterm_256_color(colorterm);
+    ColorSupport {
+        color_256,
+        truecolor,
+    }
+}
+
+pub fn color_support() -> ColorSupport {
+    let term = env::var("TERM").unwrap_or_default();
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    let info = Some(term.as_str())
+        .filter(|term| !term.is_empty())
+        .and_then(Terminfo::load);
+    detect(&term, &colorterm, info.as_ref())
+}
+
+impl ColorSupport {
+    /// TERM of terminal which supports 24-bit colour
+    pub fn truecolor_term(self) -> Option<String> {
+        if self.truecolor {
+            env::var("TERM").ok().filter(|term| !term.is_empty())
+        } else {
+            None
+        }
+    }
+
+    /// Containers are assumed to support 256 colours, because their TERM is often a plain
+    /// `xterm`
+    pub fn support_256_color(self) -> bool {
+        self.color_256
+            || match create_nmk_tmp_dir() {
+                Ok(dir) => container::is_containerized_cached(&dir),
+                Err(_) => container::is_containerized(),
+            }
+    }
+}
+
+/// Prefer tmux-256color which knows italics and other modern features, when host has it
+pub fn default_term(support_256_color: bool) -> &'static str {
+    if !support_256_color {
+        "screen"
+    } else if terminfo::exists("tmux-256color") {
+        "tmux-256color"
+    } else {
+        "screen-256color"
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::path::Path;
+
     use super::*;
 
     #[test]
@@ -34,6 +108,13 @@ mod tests {
         assert!(is_term_256_color("putty"));
         assert!(is_term_256_color("screen-256color"));
         assert!(is_term_256_color("xterm-256color"));
+        assert!(is_term_256_color("xterm-kitty"));
+        assert!(is_term_256_color("alacritty"));
+        assert!(is_term_256_color("wezterm"));
+        assert!(is_term_256_color("foot"));
+        assert!(is_term_256_color("tmux-256color"));
+        assert!(is_term_256_color("rxvt-unicode-256color"));
+        assert!(is_term_256_color("xterm-direct"));
         assert!(!is_term_256_color("linux"));
         assert!(!is_term_256_color(""));
     }
@@ -46,4 +127,55 @@ mod tests {
         assert!(!is_colorterm_256_color("unknown"));
         assert!(!is_colorterm_256_color(""));
     }
+
+    #[test]
+    fn test_is_colorterm_truecolor() {
+        assert!(is_colorterm_truecolor("truecolor"));
+        assert!(is_colorterm_truecolor("24bit"));
+        assert!(!is_colorterm_truecolor("gnome-terminal"));
+        assert!(!is_colorterm_truecolor(""));
+    }
+
+    #[test]
+    fn test_detect() {
+        let fixture = |term: &str| {
+            let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/nmk/fixtures/terminfo");
+            let path = terminfo::find_in(term, &[dir]).unwrap();
+            Terminfo::parse(&std::fs::read(path).unwrap()).unwrap()
+        };
+        let none = ColorSupport::default();
+        let color_256 = ColorSupport {
+            color_256: true,
+            truecolor: false,
+        };
+        let truecolor = ColorSupport {
+            color_256: true,
+            truecolor: true,
+        };
+        assert_eq!(detect("linux", "", None), none);
+        assert_eq!(detect("dumb", "", Some(&fixture("dumb"))), none);
+        assert_eq!(detect("xterm-kitty", "", None), color_256);
+        assert_eq!(detect("foot", "", None), color_256);
+        assert_eq!(
+            detect("xterm-256color", "", Some(&fixture("xterm-256color"))),
+            color_256
+        );
+        assert_eq!(
+            detect("tmux-256color", "", Some(&fixture("tmux-256color"))),
+            color_256
+        );
+        // terminfo wins over the name
+        assert_eq!(
+            detect("xterm-direct", "", Some(&fixture("xterm-direct"))),
+            truecolor
+        );
+        assert_eq!(
+            detect("nmk-legacy", "", Some(&fixture("nmk-legacy"))),
+            truecolor
+        );
+        // COLORTERM=truecolor implies 256 colours even with unknown TERM
+        assert_eq!(detect("xterm", "truecolor", None), truecolor);
+        assert_eq!(detect("alacritty", "truecolor", None), truecolor);
+        assert_eq!(detect("xterm", "xfce4-terminal", None), color_256);
+    }
 }
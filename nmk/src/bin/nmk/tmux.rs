@@ -1,17 +1,28 @@
+use std::ffi::OsStr;
 use std::os::unix::fs::PermissionsExt;
 use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Output};
+use std::process::{Command, Output, Stdio};
 use std::{env, fs, io};
 
 use nix::unistd::Uid;
 
 use nmk::bin_name::{TMUX, ZSH};
 use nmk::env_name::NMK_TMUX_VERSION;
+use nmk::error::{Error, ErrorKind};
+use nmk::home::{NmkHome, NmkPath};
+use nmk::platform::PlatformType;
+use nmk::settings::Settings;
+use nmk::tmux::cache;
+use nmk::tmux::capability::Feature;
+use nmk::tmux::clipboard::{self, Backend};
 use nmk::tmux::config::Context;
+use nmk::tmux::theme::{Theme, DEFAULT_THEME};
 use nmk::tmux::version::{TmuxVersionError, Version};
+use nmk::tmux::workspace::Workspace;
 
 use crate::cmdline::CmdOpt;
+use crate::terminal::{self, ColorSupport};
 use crate::utils::print_usage_time;
 
 pub struct Tmux {
@@ -19,40 +30,68 @@ pub struct Tmux {
     pub version: Version,
 }
 
-fn find_version() -> Result<Version, TmuxVersionError> {
+const VERSION_CACHE: &str = "tmux.version";
+
+/// Version of tmux binary, saved with its key in temp directory to save `tmux -V` on next start
+fn cached_version(bin: &Path) -> Result<Version, TmuxVersionError> {
+    let cache_path = nmk_tmp_dir().join(VERSION_CACHE);
+    let key = cache::file_key(bin);
+    let cached = fs::read_to_string(&cache_path).ok().and_then(|s| {
+        let (cached_key, version) = s.trim().split_once(' ')?;
+        Some(version.to_string()).filter(|_| cached_key == key)
+    });
+    if let Some(version) = cached.and_then(|s| Version::from_version(&s).ok()) {
+        log::debug!("Using cached tmux version");
+        return Ok(version);
+    }
+    let version = query_version(bin)?;
+    let saved =
+        create_nmk_tmp_dir().and_then(|_| fs::write(&cache_path, format!("{} {}\n", key, version)));
+    if let Err(e) = saved {
+        log::debug!("Failed to save {:?}: {}", cache_path, e);
+    }
+    Ok(version)
+}
+
+fn find_version(bin: &Path) -> Result<Version, TmuxVersionError> {
     if let Ok(s) = std::env::var(NMK_TMUX_VERSION) {
         log::debug!("Using tmux version from environment variable");
         Version::from_version(&s)
     } else {
-        let Output {
-            status,
-            stderr,
-            stdout,
-        } = Command::new(TMUX)
-            .arg("-V")
-            .output()
-            .expect("failed to get tmux version output");
-        if !status.success() {
-            panic!(
-                "tmux exit with status code: {:?}, error: {}",
-                status.code(),
-                String::from_utf8_lossy(&stderr)
-            );
-        }
-        Version::from_version_output(&stdout)
+        cached_version(bin)
     }
 }
 
+fn query_version(bin: &Path) -> Result<Version, TmuxVersionError> {
+    let Output {
+        status,
+        stderr,
+        stdout,
+    } = Command::new(bin)
+        .arg("-V")
+        .output()
+        .expect("failed to get tmux version output");
+    if !status.success() {
+        panic!(
+            "tmux exit with status code: {:?}, error: {}",
+            status.code(),
+            String::from_utf8_lossy(&stderr)
+        );
+    }
+    Version::from_version_output(&stdout)
+}
+
 impl Tmux {
     pub fn new() -> Tmux {
         let bin = which::which(TMUX).expect("cannot find tmux binary");
-        let version = find_version().expect("find tmux version error");
+        let version = find_version(&bin).expect("find tmux version error");
         Tmux { bin, version }
     }
 
-    pub fn exec(&self, cmd_opt: &CmdOpt, config: &Path, is_color_term: bool) -> ! {
+    /// tmux on nmk socket, which starts the server with `config` if needed
+    fn command(&self, cmd_opt: &CmdOpt, config: &Path, is_color_term: bool) -> Command {
         let mut cmd = Command::new(TMUX);
-        cmd.args(&["-L", &cmd_opt.socket]);
+        cmd.args(["-L", &cmd_opt.socket]);
         if is_color_term {
             cmd.arg("-2");
         }
@@ -61,37 +100,155 @@ impl Tmux {
         }
         cmd.arg("-f");
         cmd.arg(config);
+        cmd
+    }
+
+    pub fn exec(&self, cmd_opt: &CmdOpt, config: &Path, is_color_term: bool) -> ! {
+        let mut cmd = self.command(cmd_opt, config, is_color_term);
         if cmd_opt.args.is_empty() {
             // Attach to tmux or create new session
-            cmd.args(&["new-session", "-A"]);
-            if self.version < Version::V31 {
-                cmd.args(&["-s", "0"]);
+            cmd.args(["new-session", "-A"]);
+            if !self.version.supports(Feature::NewSessionDefaultName) {
+                cmd.args(["-s", "0"]);
             }
         } else {
             log::debug!("Positional arguments: {:?}", cmd_opt.args);
             cmd.args(cmd_opt.args.iter());
         }
         log::debug!("exec command: {:?}", cmd);
-        print_usage_time(&cmd_opt);
+        print_usage_time(cmd_opt);
         let err = cmd.exec();
         panic!("exec {:?} fail with {:?}", cmd, err);
     }
 
-    pub fn write_config_in_temp_dir(
+    /// Build the workspace session unless it exists, then attach to it
+    pub fn open(
         &self,
         cmd_opt: &CmdOpt,
-        contents: &[u8],
-    ) -> io::Result<PathBuf> {
+        config: &Path,
+        is_color_term: bool,
+        workspace: &Workspace,
+    ) -> nmk::Result<()> {
+        let session = workspace.session();
+        let target = format!("={}", session);
+        let exists = self
+            .command(cmd_opt, config, is_color_term)
+            .args(["has-session", "-t", &target])
+            .stderr(Stdio::null())
+            .status()?
+            .success();
+        if exists {
+            log::debug!("Session {} exists", session);
+        } else {
+            build_session(workspace, self.version, |args| {
+                let output = self
+                    .command(cmd_opt, config, is_color_term)
+                    .args(args)
+                    .output()?;
+                if !output.status.success() {
+                    return Err(Error::msg(
+                        ErrorKind::Other,
+                        format!(
+                            "tmux {} failed: {}",
+                            args.join(" "),
+                            String::from_utf8_lossy(&output.stderr).trim()
+                        ),
+                    ));
+                }
+                Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+            })?;
+        }
+        // A client of the same server switches instead of nesting another client
+        let socket = tmux_socket_dir().join(&cmd_opt.socket);
+        let attach = if is_client_of(&socket, env::var_os("TMUX").as_deref()) {
+            "switch-client"
+        } else {
+            "attach-session"
+        };
+        let mut cmd = self.command(cmd_opt, config, is_color_term);
+        cmd.args([attach, "-t", &target]);
+        log::debug!("exec command: {:?}", cmd);
+        print_usage_time(cmd_opt);
+        let err = cmd.exec();
+        Err(Error::msg(
+            ErrorKind::Io,
+            format!("exec {:?} fail with {:?}", cmd, err),
+        ))
+    }
+
+    pub fn render_config(&self, context: &Context) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(8192);
+        nmk::tmux::config::render(&mut buf, context, self.version)?;
+        log::debug!(
+            "tmux configuration length: {}, capacity: {}, remaining bytes before re-alloc: {}",
+            buf.len(),
+            buf.capacity(),
+            buf.capacity() - buf.len(),
+        );
+        Ok(buf)
+    }
+
+    /// Rendered config of the socket in temp directory, rendered again only when inputs change
+    ///
+    /// The key is computed from `context`, so the cost of building it is not saved: settings and
+    /// themes are read, and over SSH clipboard detection connects to the forwarded display. That
+    /// connect goes to localhost for SSH forwarding and never waits on DNS.
+    pub fn cached_config(&self, cmd_opt: &CmdOpt, context: &Context) -> io::Result<PathBuf> {
         let nmk_tmp_dir = create_nmk_tmp_dir()?;
         let config = nmk_tmp_dir.join(format!("{}.tmux.conf", cmd_opt.socket));
-        fs::write(&config, contents)?;
+        let key = cache::key(
+            context,
+            self.version,
+            PlatformType::detect(),
+            &cache::build_id(),
+        );
+        if cache::is_fresh(&config, &key) {
+            log::debug!("Reuse cached tmux configuration {:?}", config);
+        } else {
+            cache::write(&config, &key, &self.render_config(context)?)?;
+        }
         Ok(config)
     }
 }
 
-fn create_nmk_tmp_dir() -> io::Result<PathBuf> {
-    let tmp_dir = env::temp_dir();
-    let nmk_tmp_dir = tmp_dir.join(format!("nmk-{}", Uid::current()));
+/// Build the workspace session, a half built one is killed so the next open builds it again
+fn build_session<F>(workspace: &Workspace, v: Version, mut run: F) -> nmk::Result<()>
+where
+    F: FnMut(&[String]) -> nmk::Result<String>,
+{
+    let result = workspace.build(v, &mut run);
+    if result.is_err() {
+        let target = format!("={}", workspace.session());
+        if let Err(e) = run(&["kill-session".into(), "-t".into(), target]) {
+            log::debug!("Failed to kill session {}: {}", workspace.session(), e);
+        }
+    }
+    result
+}
+
+/// Directory of rendered configs, one per tmux socket
+pub fn nmk_tmp_dir() -> PathBuf {
+    env::temp_dir().join(format!("nmk-{}", Uid::current()))
+}
+
+/// Directory of tmux server sockets, see `TMUX_TMPDIR` in tmux(1)
+pub fn tmux_socket_dir() -> PathBuf {
+    env::var_os("TMUX_TMPDIR")
+        .filter(|v| !v.is_empty())
+        .map_or_else(|| PathBuf::from("/tmp"), PathBuf::from)
+        .join(format!("tmux-{}", Uid::current()))
+}
+
+/// `TMUX` of a tmux client is socket path, server pid and session id
+fn is_client_of(socket: &Path, tmux_env: Option<&OsStr>) -> bool {
+    tmux_env
+        .and_then(OsStr::to_str)
+        .and_then(|v| v.rsplitn(3, ',').nth(2))
+        .is_some_and(|path| Path::new(path) == socket)
+}
+
+pub fn create_nmk_tmp_dir() -> io::Result<PathBuf> {
+    let nmk_tmp_dir = nmk_tmp_dir();
     if !nmk_tmp_dir.exists() {
         fs::create_dir(&nmk_tmp_dir)?;
         let mut permissions = nmk_tmp_dir.metadata()?.permissions();
@@ -101,16 +258,93 @@ fn create_nmk_tmp_dir() -> io::Result<PathBuf> {
     Ok(nmk_tmp_dir)
 }
 
-pub fn make_config_context(cmd_opt: &CmdOpt, support_256_color: bool) -> Context {
-    let default_term = if support_256_color {
-        "screen-256color"
-    } else {
-        "screen"
-    };
+pub fn make_config_context(
+    cmd_opt: &CmdOpt,
+    nmk_home: &NmkHome,
+    colors: ColorSupport,
+    support_256_color: bool,
+) -> Context {
+    let settings = Settings::load(nmk_home);
     Context {
         support_256_color,
+        truecolor_term: colors.truecolor_term(),
+        theme: load_theme(cmd_opt, &settings, nmk_home),
+        clipboard: clipboard_backend(cmd_opt, &settings),
         detach_on_destroy: cmd_opt.detach_on_destroy,
-        default_term: default_term.to_owned(),
+        default_term: terminal::default_term(support_256_color).to_owned(),
         default_shell: which::which(ZSH).expect("zsh not found"),
+        key_binding_files: layered(nmk_home, |nmk_path| nmk_path.tmux_key_bindings()),
+        overlay_dirs: layered(nmk_home, |nmk_path| nmk_path.tmux_overlays()),
+    }
+}
+
+/// System-wide paths come first, so personal ones can override them
+fn layered<F: Fn(NmkPath) -> PathBuf>(nmk_home: &NmkHome, f: F) -> Vec<PathBuf> {
+    let mut paths: Vec<_> = nmk_home.layers().map(f).collect();
+    paths.reverse();
+    paths
+}
+
+/// Command line option, then NMK_THEME, then settings file
+fn load_theme(cmd_opt: &CmdOpt, settings: &Settings, nmk_home: &NmkHome) -> Theme {
+    let name = cmd_opt
+        .theme
+        .clone()
+        .or_else(|| settings.tmux.theme.clone())
+        .unwrap_or_else(|| DEFAULT_THEME.to_string());
+    log::debug!("tmux theme = {}", name);
+    let dirs: Vec<_> = nmk_home
+        .layers()
+        .map(|nmk_path| nmk_path.themes())
+        .collect();
+    Theme::load(&name, &dirs)
+}
+
+/// Forced by flag, then by settings, otherwise detected
+fn clipboard_backend(cmd_opt: &CmdOpt, settings: &Settings) -> Backend {
+    let backend = cmd_opt
+        .clipboard
+        .or(settings.tmux.clipboard)
+        .unwrap_or_else(clipboard::detect);
+    log::debug!("clipboard = {}", backend);
+    backend
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_client_of() {
+        let socket = Path::new("/tmp/tmux-1000/nmk");
+        let env = |v: &'static str| Some(OsStr::new(v));
+        assert!(is_client_of(socket, env("/tmp/tmux-1000/nmk,4242,0")));
+        assert!(!is_client_of(socket, env("/tmp/tmux-1000/default,4242,0")));
+        assert!(!is_client_of(socket, env("/tmp/tmux-1000/nmk")));
+        assert!(!is_client_of(socket, None));
+    }
+
+    #[test]
+    fn test_build_session_failure() {
+        let workspace = Workspace::parse("api", "[[window]]\n[[window]]\n").unwrap();
+        let mut commands = Vec::new();
+        let result = build_session(&workspace, Version::V32, |args| {
+            commands.push(args.join(" "));
+            if args[0] == "new-window" {
+                return Err(Error::msg(ErrorKind::Other, "tmux new-window failed"));
+            }
+            Ok("%0\n".to_string())
+        });
+        assert_eq!(result.unwrap_err().to_string(), "tmux new-window failed");
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[2], "kill-session -t =api");
+
+        let mut commands = Vec::new();
+        build_session(&workspace, Version::V32, |args| {
+            commands.push(args.join(" "));
+            Ok("%0\n".to_string())
+        })
+        .unwrap();
+        assert!(!commands.iter().any(|c| c.starts_with("kill-session")));
     }
 }
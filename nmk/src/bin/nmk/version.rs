@@ -6,11 +6,11 @@ pub fn get_verbose_version() -> Option<String> {
         (Some(secs), Some(sha)) => Some(format!(
             "#{} ({} since last build)",
             sha,
-            HumanTime::new(secs).to_human(2)
+            HumanTime::new(secs).to_human_in_days(2)
         )),
         (Some(secs), None) => Some(format!(
             "({} since last build)",
-            HumanTime::new(secs).to_human(2)
+            HumanTime::new(secs).to_human_in_days(2)
         )),
         _ => None,
     }
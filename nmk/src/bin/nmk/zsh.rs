@@ -5,6 +5,7 @@ use nmk::bin_name::ZSH;
 use nmk::config::one_hot;
 use nmk::env_name::NMK_ZSH_GLOBAL_RCS;
 use nmk::home::NmkHome;
+use nmk::platform::{self, PlatformType};
 
 use crate::cmdline::CmdOpt;
 use crate::entrypoint::set_env;
@@ -22,10 +23,13 @@ fn has_vendor_zsh(nmk_home: &NmkHome) -> bool {
 ///   - on MacOs, zprofile call /usr/libexec/path_helper which will change order in PATH
 ///   - on Alpine, global zprofile source /etc/profile which overwrite PATH environment
 pub fn use_global_rcs(nmk_home: &NmkHome) -> bool {
-    use nmk::platform::PlatformType::{self, *};
-    let platform = PlatformType::detect();
-    let not_friendly_global_rcs = matches!(platform, MacOs | Alpine | Arch);
-    has_vendor_zsh(nmk_home) || !not_friendly_global_rcs
+    has_vendor_zsh(nmk_home) || friendly_global_rcs(platform::distribution())
+}
+
+/// Distribution decides, WSL runs the distribution's own rc files
+fn friendly_global_rcs(platform: PlatformType) -> bool {
+    use PlatformType::*;
+    !matches!(platform, MacOs | Alpine | Arch)
 }
 
 pub fn init(nmk_home: &NmkHome) {
@@ -42,7 +46,20 @@ pub fn exec_login_shell(cmd_opt: &CmdOpt) -> ! {
     cmd.env("SHELL", zsh);
     // Signal zsh that it is a login shell by prepend - to arg0
     cmd.arg0("-zsh");
-    print_usage_time(&cmd_opt);
+    print_usage_time(cmd_opt);
     let err = cmd.exec();
     panic!("exec {:?} fail with {:?}", cmd, err);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_friendly_global_rcs() {
+        // WSL doesn't hide the distribution, Alpine under WSL is still Alpine
+        assert!(!friendly_global_rcs(PlatformType::Alpine));
+        assert!(!friendly_global_rcs(PlatformType::MacOs));
+        assert!(friendly_global_rcs(PlatformType::Linux));
+    }
+}
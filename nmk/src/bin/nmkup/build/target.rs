@@ -1,33 +1,130 @@
 use std::str::FromStr;
 
-use once_cell::sync::Lazy;
-use regex::Regex;
-
 use nmk::arch::detect_current_architecture;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Target {
     Amd64Linux,
     Arm64Linux,
     ArmLinux,
     ArmV7Linux,
     ArmV7LinuxHardFloat,
+    I686Linux,
+    Riscv64Linux,
+    PowerPc64LeLinux,
+    S390xLinux,
+}
+
+struct TargetSpec {
+    target: Target,
+    /// Target triple of distributed binaries
+    build_triple: &'static str,
+    /// `get_architecture` outputs of machines which use this target
+    architectures: &'static [&'static str],
 }
 
+/// Adding a target is a matter of adding a row here, build.py, build-all of Makefile and main()
+/// of nmkup-init.sh
+///
+/// Riscv64, powerpc64le and s390x are built against glibc because their musl targets are not
+/// well supported by rust yet.
+const TARGETS: &[TargetSpec] = &[
+    TargetSpec {
+        target: Target::Amd64Linux,
+        build_triple: "x86_64-unknown-linux-musl",
+        architectures: &["x86_64-unknown-linux-gnu", "x86_64-unknown-linux-musl"],
+    },
+    TargetSpec {
+        target: Target::Arm64Linux,
+        build_triple: "aarch64-unknown-linux-musl",
+        architectures: &["aarch64-unknown-linux-gnu", "aarch64-unknown-linux-musl"],
+    },
+    // arm build use non hard-float to maximize compatibility in one binary
+    TargetSpec {
+        target: Target::ArmLinux,
+        build_triple: "arm-unknown-linux-musleabi",
+        architectures: &[
+            "arm-unknown-linux-gnueabi",
+            "arm-unknown-linux-gnueabihf",
+            "arm-unknown-linux-musleabi",
+            "arm-unknown-linux-musleabihf",
+        ],
+    },
+    TargetSpec {
+        target: Target::ArmV7Linux,
+        build_triple: "arm-unknown-linux-musleabi",
+        architectures: &[
+            "armv7-unknown-linux-gnueabi",
+            "armv7-unknown-linux-musleabi",
+        ],
+    },
+    // use armv7 for raspberry pi 4
+    TargetSpec {
+        target: Target::ArmV7LinuxHardFloat,
+        build_triple: "armv7-unknown-linux-musleabihf",
+        architectures: &[
+            "armv7-unknown-linux-gnueabihf",
+            "armv7-unknown-linux-musleabihf",
+        ],
+    },
+    TargetSpec {
+        target: Target::I686Linux,
+        build_triple: "i686-unknown-linux-musl",
+        architectures: &["i686-unknown-linux-gnu", "i686-unknown-linux-musl"],
+    },
+    TargetSpec {
+        target: Target::Riscv64Linux,
+        build_triple: "riscv64gc-unknown-linux-gnu",
+        architectures: &["riscv64gc-unknown-linux-gnu"],
+    },
+    TargetSpec {
+        target: Target::PowerPc64LeLinux,
+        build_triple: "powerpc64le-unknown-linux-gnu",
+        architectures: &["powerpc64le-unknown-linux-gnu"],
+    },
+    TargetSpec {
+        target: Target::S390xLinux,
+        build_triple: "s390x-unknown-linux-gnu",
+        architectures: &["s390x-unknown-linux-gnu"],
+    },
+];
+
 impl Target {
     pub fn detect() -> Result<Self, String> {
-        let arch = detect_current_architecture().expect("failed to detect architecture");
-        FromStr::from_str(&arch)
+        let arch = detect_current_architecture().map_err(|e| e.to_string())?;
+        FromStr::from_str(&arch.to_string())
+    }
+
+    fn spec(&self) -> &'static TargetSpec {
+        TARGETS
+            .iter()
+            .find(|spec| spec.target == *self)
+            .expect("target is missing from TARGETS")
+    }
+
+    /// Target triple of distributed binaries
+    pub fn build_triple(&self) -> &'static str {
+        self.spec().build_triple
     }
 
     pub fn remote_binary_name(&self, bin: &str) -> String {
-        let bin_suffix = match *self {
-            Target::Amd64Linux => "x86_64-unknown-linux-musl.xz",
-            Target::Arm64Linux => "aarch64-unknown-linux-musl.xz",
-            Target::ArmLinux | Target::ArmV7Linux => "arm-unknown-linux-musleabi.xz",
-            Target::ArmV7LinuxHardFloat => "armv7-unknown-linux-musleabihf.xz",
-        };
-        format!("{}-{}", bin, bin_suffix)
+        format!("{}-{}.xz", bin, self.build_triple())
+    }
+
+    /// Uncompressed binary, this is what nmkup-init.sh downloads
+    pub fn remote_init_name(&self, bin: &str) -> String {
+        format!("{}-{}", bin, self.build_triple())
+    }
+
+    /// Shell `case` pattern of `get_architecture` output which can run this target binaries
+    pub fn architecture_pattern(&self) -> String {
+        let build_triple = self.build_triple();
+        TARGETS
+            .iter()
+            .filter(|spec| spec.build_triple == build_triple)
+            .flat_map(|spec| spec.architectures.iter().copied())
+            .collect::<Vec<_>>()
+            .join(" | ")
     }
 }
 
@@ -35,27 +132,118 @@ impl FromStr for Target {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            x if x.starts_with("x86_64-unknown-linux-") => Ok(Target::Amd64Linux),
-            x if x.starts_with("aarch64-unknown-linux-") => Ok(Target::Arm64Linux),
-            x if ARMV7_HARD_FLOAT.is_match(x) => Ok(Target::ArmV7LinuxHardFloat),
-            x if x.starts_with("armv7-unknown-linux") => Ok(Target::ArmV7Linux),
-            "arm-unknown-linux-musleabi" => Ok(Target::ArmLinux),
-            _ => Err(s.to_string()),
-        }
+        TARGETS
+            .iter()
+            .find(|spec| spec.architectures.contains(&s))
+            .map(|spec| spec.target)
+            .ok_or_else(|| s.to_string())
     }
 }
 
-static ARMV7_HARD_FLOAT: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"armv7-unknown-linux.*hf").unwrap());
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Every architecture and the build triple it installs
+    const MAPPINGS: &[(&str, &str)] = &[
+        ("x86_64-unknown-linux-gnu", "x86_64-unknown-linux-musl"),
+        ("x86_64-unknown-linux-musl", "x86_64-unknown-linux-musl"),
+        ("aarch64-unknown-linux-gnu", "aarch64-unknown-linux-musl"),
+        ("aarch64-unknown-linux-musl", "aarch64-unknown-linux-musl"),
+        ("arm-unknown-linux-gnueabi", "arm-unknown-linux-musleabi"),
+        ("arm-unknown-linux-gnueabihf", "arm-unknown-linux-musleabi"),
+        ("arm-unknown-linux-musleabi", "arm-unknown-linux-musleabi"),
+        ("arm-unknown-linux-musleabihf", "arm-unknown-linux-musleabi"),
+        ("armv7-unknown-linux-gnueabi", "arm-unknown-linux-musleabi"),
+        ("armv7-unknown-linux-musleabi", "arm-unknown-linux-musleabi"),
+        (
+            "armv7-unknown-linux-gnueabihf",
+            "armv7-unknown-linux-musleabihf",
+        ),
+        (
+            "armv7-unknown-linux-musleabihf",
+            "armv7-unknown-linux-musleabihf",
+        ),
+        ("i686-unknown-linux-gnu", "i686-unknown-linux-musl"),
+        ("i686-unknown-linux-musl", "i686-unknown-linux-musl"),
+        ("riscv64gc-unknown-linux-gnu", "riscv64gc-unknown-linux-gnu"),
+        (
+            "powerpc64le-unknown-linux-gnu",
+            "powerpc64le-unknown-linux-gnu",
+        ),
+        ("s390x-unknown-linux-gnu", "s390x-unknown-linux-gnu"),
+    ];
+
+    #[test]
+    fn test_target_mappings() {
+        for (arch, build_triple) in MAPPINGS {
+            let target = Target::from_str(arch).unwrap();
+            assert_eq!(target.build_triple(), *build_triple, "{}", arch);
+        }
+        let mapped: usize = TARGETS.iter().map(|spec| spec.architectures.len()).sum();
+        assert_eq!(MAPPINGS.len(), mapped);
+    }
+
     #[test]
-    fn test_armv7_hard_float_matcher() {
-        assert!(ARMV7_HARD_FLOAT.is_match("armv7-unknown-linux-gnueabihf"));
-        assert!(ARMV7_HARD_FLOAT.is_match("armv7-unknown-linux-musleabihf"));
+    fn test_unsupported_architecture() {
+        for arch in &[
+            "riscv64gc-unknown-linux-musl",
+            "powerpc-unknown-linux-gnu",
+            "x86_64-apple-darwin",
+            "",
+        ] {
+            assert_eq!(Target::from_str(arch), Err(arch.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_build_triple_round_trip() {
+        for spec in TARGETS {
+            let target = Target::from_str(spec.build_triple).unwrap();
+            assert_eq!(target.build_triple(), spec.build_triple);
+            assert!(target.architecture_pattern().contains(spec.build_triple));
+        }
+    }
+
+    #[test]
+    fn test_remote_binary_name() {
+        let target = Target::from_str("x86_64-unknown-linux-gnu").unwrap();
+        assert_eq!(
+            target.remote_binary_name("nmk"),
+            "nmk-x86_64-unknown-linux-musl.xz"
+        );
+        assert_eq!(
+            target.remote_init_name("nmkup"),
+            "nmkup-x86_64-unknown-linux-musl"
+        );
+        let target = Target::from_str("riscv64gc-unknown-linux-gnu").unwrap();
+        assert_eq!(
+            target.remote_binary_name("nmk"),
+            "nmk-riscv64gc-unknown-linux-gnu.xz"
+        );
+    }
+
+    #[test]
+    fn test_architecture_pattern() {
+        assert_eq!(
+            Target::ArmV7Linux.architecture_pattern(),
+            "arm-unknown-linux-gnueabi | arm-unknown-linux-gnueabihf | \
+             arm-unknown-linux-musleabi | arm-unknown-linux-musleabihf | \
+             armv7-unknown-linux-gnueabi | armv7-unknown-linux-musleabi"
+        );
+    }
+
+    /// nmkup-init.sh must download the same binary as `Target` for every architecture
+    #[test]
+    fn test_nmkup_init_script_in_sync() {
+        let script = include_str!("../../../../nmkup.nuimk.com/nmkup-init.sh");
+        for spec in TARGETS {
+            let case = format!(
+                "        {})\n            _url=\"${{NMKUP_UPDATE_ROOT}}/nmkup-{}\"\n",
+                spec.architectures.join(" | "),
+                spec.build_triple
+            );
+            assert!(script.contains(&case), "{}", case);
+        }
     }
 }
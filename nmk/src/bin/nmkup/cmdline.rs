@@ -1,5 +1,16 @@
+use std::path::PathBuf;
+
 use structopt::StructOpt;
 
+use crate::build::Target;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, strum::EnumString, strum::EnumVariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "nmkup",
@@ -14,8 +25,62 @@ pub struct CmdOpt {
     pub no_filter: bool,
     #[structopt(long, help = "Install vendored files")]
     pub vendor: bool,
+    #[structopt(
+        long,
+        help = "Install to system-wide root ($NMK_SYSTEM_HOME or /opt/nmk), requires root"
+    )]
+    pub system: bool,
+    #[structopt(
+        long,
+        help = "Only refresh cached remote metadata used by update notice"
+    )]
+    pub refresh_metadata: bool,
     #[structopt(short, parse(from_occurrences), help = "Request verbose logging")]
     pub verbosity: u8,
+    #[structopt(
+        long,
+        default_value = "text",
+        possible_values = <OutputFormat as strum::VariantNames>::VARIANTS,
+        help = "Report format, json report is written to stdout"
+    )]
+    pub output: OutputFormat,
+    #[structopt(
+        long,
+        value_name = "dir",
+        help = "Install from files extracted by installer script instead of remote storage"
+    )]
+    pub bundle: Option<PathBuf>,
+    #[structopt(subcommand)]
+    pub cmd: Option<SubCommand>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum SubCommand {
+    #[structopt(about = "Export a self-extracting installer script for offline installation")]
+    ExportInstaller(ExportInstaller),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ExportInstaller {
+    #[structopt(
+        long,
+        value_name = "triple",
+        help = "Target triple of installed binaries"
+    )]
+    pub target: Target,
+    #[structopt(
+        short,
+        long,
+        default_value = "nmkup-installer.sh",
+        help = "Write installer script to file"
+    )]
+    pub output: PathBuf,
+    #[structopt(
+        long,
+        value_name = "name",
+        help = "Embed vendor files, named as nmkup --vendor lists them"
+    )]
+    pub vendor: Option<String>,
 }
 
 pub fn from_args() -> CmdOpt {
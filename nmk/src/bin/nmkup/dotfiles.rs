@@ -6,13 +6,15 @@ use bytes::Bytes;
 use tar::Archive;
 use xz2::read::XzDecoder;
 
-use nmk::gcs::{download_file, get_object_meta, get_object_meta_url, ObjectMeta};
+use nmk::gcs::ObjectMeta;
 use nmk::home::NmkHome;
 
 use crate::cmdline::CmdOpt;
+use crate::remote::Remote;
+use crate::report::{Action, ComponentReport};
 
-const DOTFILES_META: &str = ".dotfiles.meta";
-const TAG: &str = "dotfiles";
+pub const REMOTE_OBJECT: &str = "dotfiles.tar.xz";
+pub const TAG: &str = "dotfiles";
 
 async fn extract_dotfiles<P: AsRef<Path>>(data: Bytes, destination: P) -> nmk::Result<()> {
     let destination = destination.as_ref();
@@ -29,28 +31,37 @@ async fn extract_dotfiles<P: AsRef<Path>>(data: Bytes, destination: P) -> nmk::R
     Ok(())
 }
 
-fn is_dotfiles_up2date(meta_path: &Path, gcs_meta: &ObjectMeta) -> bool {
-    if !meta_path.exists() {
-        log::debug!("{}: Not found cached metadata.", TAG);
-        return false;
-    }
-    let cached_meta = ObjectMeta::read_from_file(meta_path);
+fn is_dotfiles_up2date(cached_meta: Option<&ObjectMeta>, gcs_meta: &ObjectMeta) -> bool {
+    let cached_meta = match cached_meta {
+        Some(meta) => meta,
+        None => {
+            log::debug!("{}: Not found cached metadata.", TAG);
+            return false;
+        }
+    };
     log::debug!("{}: gcs generation {}.", TAG, gcs_meta.generation);
     log::debug!("{}: cached generation {}.", TAG, cached_meta.generation);
     cached_meta.generation == gcs_meta.generation
 }
 
-pub async fn install_or_update(cmd_opt: &CmdOpt, nmk_home: &NmkHome) -> nmk::Result<()> {
+pub async fn install_or_update(
+    cmd_opt: &CmdOpt,
+    nmk_home: &NmkHome,
+    remote: &Remote,
+    report: &mut ComponentReport,
+) -> nmk::Result<()> {
     if !nmk_home.as_path().exists() {
         fs::create_dir_all(nmk_home)?;
         log::info!("Created {:?} directory", nmk_home);
     }
 
-    let meta_path = nmk_home.as_path().join(DOTFILES_META);
+    let meta_path = nmk_home.nmk_path().dotfiles_meta();
 
     // check if it is safe to install
     let nmk_home_empty = nmk_home.as_path().read_dir()?.next().is_none();
     let meta_do_exist = meta_path.exists();
+    let cached_meta = meta_do_exist.then(|| ObjectMeta::read_from_file(&meta_path));
+    report.previous_generation = cached_meta.as_ref().map(|m| m.generation.clone());
     if !cmd_opt.force && !nmk_home_empty {
         assert!(
             meta_do_exist,
@@ -58,14 +69,13 @@ pub async fn install_or_update(cmd_opt: &CmdOpt, nmk_home: &NmkHome) -> nmk::Res
         );
     }
 
-    let meta_url = get_object_meta_url("dotfiles.tar.xz");
-
-    let client = reqwest::Client::new();
     log::debug!("{}: Getting metadata.", TAG);
-    let meta = get_object_meta(&client, &meta_url).await?;
+    let meta = remote.get_object_meta(REMOTE_OBJECT).await?;
     log::debug!("{}: Received metadata.", TAG);
-    if !cmd_opt.force && is_dotfiles_up2date(&meta_path, &meta) {
+    report.new_generation = Some(meta.generation.clone());
+    if !cmd_opt.force && is_dotfiles_up2date(cached_meta.as_ref(), &meta) {
         log::info!("{}: Already up to date.", TAG);
+        report.action = Action::UpToDate;
     } else {
         if meta_do_exist {
             // uninstall old version
@@ -77,10 +87,12 @@ pub async fn install_or_update(cmd_opt: &CmdOpt, nmk_home: &NmkHome) -> nmk::Res
         }
 
         log::debug!("{}: Getting data.", TAG);
-        let tar_xz_data = download_file(&client, &meta.media_link).await?;
+        let tar_xz_data = remote.download(&meta).await?;
         log::debug!("{}: Received data.", TAG);
+        report.add_downloaded(tar_xz_data.len());
         extract_dotfiles(tar_xz_data, nmk_home).await?;
         meta.write_to_file(&meta_path);
+        report.action = Action::Installed;
         log::info!("{}: Done.", TAG)
     }
     Ok(())
@@ -4,22 +4,22 @@ use std::path::Path;
 use bytes::{Buf, Bytes};
 
 use nmk::bin_name::NMK;
-use nmk::gcs::{download_file, get_object_meta, get_object_meta_url, ObjectMeta};
+use nmk::gcs::ObjectMeta;
 use nmk::home::NmkHome;
 use nmk::setup::install;
 
 use crate::build::Target;
 use crate::cmdline::CmdOpt;
+use crate::remote::Remote;
+use crate::report::{Action, ComponentReport};
 
-const TAG: &str = "entrypoint";
+pub const TAG: &str = "entrypoint";
 
 fn install_entrypoint(data: Bytes, dst: impl AsRef<Path>) -> io::Result<()> {
     let mut reader = xz2::read::XzDecoder::new(data.reader());
     install(&mut reader, dst)
 }
 
-const NMK_META: &str = ".nmk.meta";
-
 #[derive(Copy, Clone)]
 pub enum EntrypointInstallation {
     Installed,
@@ -29,43 +29,55 @@ pub enum EntrypointInstallation {
 pub async fn install_or_update(
     cmd_opt: &CmdOpt,
     nmk_home: &NmkHome,
+    remote: &Remote,
+    report: &mut ComponentReport,
 ) -> nmk::Result<EntrypointInstallation> {
     let target = Target::detect().expect("unsupported arch");
     let tar_file = target.remote_binary_name("nmk");
-    let meta_path = nmk_home.as_path().join(NMK_META);
-    let meta_url = get_object_meta_url(&tar_file);
+    let meta_path = nmk_home.nmk_path().entrypoint_meta();
 
-    let client = reqwest::Client::new();
+    let cached_meta = meta_path
+        .exists()
+        .then(|| ObjectMeta::read_from_file(&meta_path));
+    report.previous_generation = cached_meta.as_ref().map(|m| m.generation.clone());
 
     log::debug!("{}: Getting metadata.", TAG);
-    let meta = get_object_meta(&client, &meta_url).await?;
+    let meta = remote.get_object_meta(&tar_file).await?;
     log::debug!("{}: Received metadata.", TAG);
+    report.new_generation = Some(meta.generation.clone());
     let entrypoint_path = nmk_home.nmk_path().bin().join(NMK);
-    if !cmd_opt.force && is_entrypoint_up2date(&meta_path, &meta, &entrypoint_path) {
+    if !cmd_opt.force && is_entrypoint_up2date(cached_meta.as_ref(), &meta, &entrypoint_path) {
         log::info!("{}: Already up to date.", TAG);
+        report.action = Action::UpToDate;
         Ok(EntrypointInstallation::Up2Date)
     } else {
-        let data_url = &meta.media_link;
-        log::debug!("{}: Getting data from {}.", TAG, data_url);
-        let data = download_file(&client, data_url).await?;
+        log::debug!("{}: Getting data from {}.", TAG, meta.media_link);
+        let data = remote.download(&meta).await?;
         log::debug!("{}: Received data.", TAG);
+        report.add_downloaded(data.len());
         install_entrypoint(data, entrypoint_path)?;
         meta.write_to_file(&meta_path);
+        report.action = Action::Installed;
         log::info!("{}: Done.", TAG);
         Ok(EntrypointInstallation::Installed)
     }
 }
 
-fn is_entrypoint_up2date(meta_path: &Path, gcs_meta: &ObjectMeta, entrypoint_path: &Path) -> bool {
+fn is_entrypoint_up2date(
+    cached_meta: Option<&ObjectMeta>,
+    gcs_meta: &ObjectMeta,
+    entrypoint_path: &Path,
+) -> bool {
     if !entrypoint_path.exists() {
         return false;
     }
-    if !meta_path.exists() {
-        log::debug!("{}: Not found cached metadata.", TAG);
-        return false;
-    }
-
-    let cached_meta = ObjectMeta::read_from_file(meta_path);
+    let cached_meta = match cached_meta {
+        Some(meta) => meta,
+        None => {
+            log::debug!("{}: Not found cached metadata.", TAG);
+            return false;
+        }
+    };
     log::debug!("{}: gcs generation {}.", TAG, gcs_meta.generation);
     log::debug!("{}: cached generation {}.", TAG, cached_meta.generation);
     cached_meta.generation == gcs_meta.generation
@@ -0,0 +1,202 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::fs::PermissionsExt;
+
+use tar::{Builder, Header};
+
+use crate::cmdline::ExportInstaller;
+use crate::dotfiles;
+use crate::remote::{bundle_meta_name, Remote};
+use crate::vendor;
+
+const TAG: &str = "export";
+
+/// A modified version of https://sh.rustup.rs, we reuse its architecture detection
+const NMKUP_INIT_SCRIPT: &str = include_str!("../../../nmkup.nuimk.com/nmkup-init.sh");
+const INSTALLER_SCRIPT: &str = include_str!("installer.sh");
+
+/// File name of nmkup inside bundle, `nmkup-init` prefix tells nmkup to install itself
+const NMKUP_INIT: &str = "nmkup-init";
+
+/// Output of `cksum`, checked by installer script after extraction
+struct Checksum {
+    name: String,
+    crc: u32,
+    size: usize,
+}
+
+struct Payload {
+    tar: Builder<Vec<u8>>,
+    checksums: Vec<Checksum>,
+}
+
+impl Payload {
+    fn new() -> Self {
+        Self {
+            tar: Builder::new(Vec::new()),
+            checksums: Vec::new(),
+        }
+    }
+
+    fn append(&mut self, name: &str, data: &[u8], mode: u32) -> io::Result<()> {
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(mode);
+        header.set_cksum();
+        self.tar.append_data(&mut header, name, data)?;
+        self.checksums.push(Checksum {
+            name: name.to_string(),
+            crc: posix_cksum(data),
+            size: data.len(),
+        });
+        Ok(())
+    }
+
+    fn finish(self) -> io::Result<(Vec<u8>, Vec<Checksum>)> {
+        Ok((self.tar.into_inner()?, self.checksums))
+    }
+}
+
+pub async fn export_installer(opt: &ExportInstaller) -> nmk::Result<()> {
+    let target = &opt.target;
+    let client = reqwest::Client::new();
+    let remote = Remote::Gcs(client.clone());
+    let mut payload = Payload::new();
+
+    let objects = [
+        dotfiles::REMOTE_OBJECT.to_string(),
+        target.remote_binary_name("nmk"),
+    ];
+    for object in objects.iter() {
+        log::info!("{}: Getting {}.", TAG, object);
+        let meta = remote.get_object_meta(object).await?;
+        let data = remote.download(&meta).await?;
+        let meta_json = serde_json::to_vec_pretty(&meta)?;
+        payload.append(&bundle_meta_name(object), &meta_json, 0o644)?;
+        payload.append(&meta.name, &data, 0o644)?;
+    }
+    if let Some(ref name) = opt.vendor {
+        log::info!("{}: Getting vendor files {}.", TAG, name);
+        let meta = vendor::find_vendor_files(&client, name).await?;
+        let data = remote.download(&meta).await?;
+        let meta_json = serde_json::to_vec_pretty(&meta)?;
+        payload.append(&bundle_meta_name(vendor::BUNDLE_OBJECT), &meta_json, 0o644)?;
+        payload.append(&meta.name, &data, 0o644)?;
+    }
+    let nmkup = target.remote_init_name("nmkup");
+    log::info!("{}: Getting {}.", TAG, nmkup);
+    let meta = remote.get_object_meta(&nmkup).await?;
+    let data = remote.download(&meta).await?;
+    payload.append(NMKUP_INIT, &data, 0o755)?;
+
+    let (tar, checksums) = payload.finish()?;
+    let script = render_installer_script(
+        target.build_triple(),
+        &target.architecture_pattern(),
+        &checksums,
+    );
+    let mut output = BufWriter::new(File::create(&opt.output)?);
+    output.write_all(script.as_bytes())?;
+    output.write_all(&tar)?;
+    output.flush()?;
+    drop(output);
+
+    let mut permissions = fs::metadata(&opt.output)?.permissions();
+    permissions.set_mode(0o755);
+    fs::set_permissions(&opt.output, permissions)?;
+    log::info!("{}: Installer is written to {:?}.", TAG, opt.output);
+    Ok(())
+}
+
+fn render_installer_script(
+    target: &str,
+    architecture_pattern: &str,
+    checksums: &[Checksum],
+) -> String {
+    let init_functions = NMKUP_INIT_SCRIPT
+        .lines()
+        .take_while(|line| !line.starts_with(r##"main "$@""##))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let verify_checksums = checksums
+        .iter()
+        .map(|c| {
+            format!(
+                "    verify_checksum \"$_dir/{}\" \"{} {}\"\n",
+                c.name, c.crc, c.size
+            )
+        })
+        .collect::<String>();
+    let installer = INSTALLER_SCRIPT
+        .replace("@TARGET@", target)
+        .replace("@ARCHITECTURE_PATTERN@", architecture_pattern)
+        .replace("@VERIFY_CHECKSUMS@\n", &verify_checksums);
+    let script = format!("{}\n{}", init_functions, installer);
+    // Payload starts right after the last line of script
+    let payload_line = script.lines().count() + 1;
+    script.replace("@PAYLOAD_LINE@", &payload_line.to_string())
+}
+
+/// Checksum compatible with POSIX `cksum` utility
+fn posix_cksum(data: &[u8]) -> u32 {
+    fn update(crc: u32, byte: u8) -> u32 {
+        let mut crc = crc ^ (u32::from(byte) << 24);
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    }
+    let mut crc = data.iter().fold(0, |crc, &b| update(crc, b));
+    let mut len = data.len();
+    while len > 0 {
+        crc = update(crc, len as u8);
+        len >>= 8;
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_posix_cksum() {
+        // printf 123456789 | cksum
+        assert_eq!(posix_cksum(b"123456789"), 930766865);
+        // cksum < /dev/null
+        assert_eq!(posix_cksum(b""), 4294967295);
+    }
+
+    #[test]
+    fn test_render_installer_script() {
+        let checksums = [Checksum {
+            name: "nmkup-init".to_string(),
+            crc: 1,
+            size: 2,
+        }];
+        let script = render_installer_script(
+            "x86_64-unknown-linux-musl",
+            "x86_64-unknown-linux-*",
+            &checksums,
+        );
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains("get_architecture() {"));
+        assert!(script.contains("        x86_64-unknown-linux-*)\n"));
+        assert!(script.contains("    verify_checksum \"$_dir/nmkup-init\" \"1 2\"\n"));
+        assert!(script.ends_with("exit 0\n"));
+        let payload_line = script.lines().count() + 1;
+        assert!(script.contains(&format!("tail -n +{} \"$0\"", payload_line)));
+        for placeholder in &[
+            "@TARGET@",
+            "@ARCHITECTURE_PATTERN@",
+            "@PAYLOAD_LINE@",
+            "@VERIFY_CHECKSUMS@",
+        ] {
+            assert!(!script.contains(placeholder));
+        }
+    }
+}
@@ -0,0 +1,87 @@
+# This part is generated by `nmkup export-installer`.
+# Functions above are copied from nmkup-init.sh, usage() and main() are redefined here.
+
+usage() {
+    cat 1>&2 <<EOF
+nmkup-installer (@TARGET@)
+The offline installer for nmk
+
+USAGE:
+    sh nmkup-installer.sh [FLAGS]
+
+All flags are passed to nmkup, run nmkup --help to see them.
+EOF
+}
+
+verify_checksum() {
+    local _file="$1"
+    local _expected="$2"
+    local _actual
+    _actual="$(cksum < "$_file")"
+    # Let word splitting normalize whitespace in cksum output
+    # shellcheck disable=SC2086
+    set -- $_actual
+    if [ "$1 $2" != "$_expected" ]; then
+        err "checksum mismatch: $_file"
+    fi
+}
+
+main() {
+    need_cmd uname
+    need_cmd mktemp
+    need_cmd chmod
+    need_cmd rm
+    need_cmd tail
+    need_cmd tar
+    need_cmd cksum
+
+    for arg in "$@"; do
+        case "$arg" in
+            -h|--help)
+                usage
+                exit 0
+                ;;
+            *)
+                ;;
+        esac
+    done
+
+    get_architecture || return 1
+    local _arch="$RETVAL"
+    assert_nz "$_arch" "arch"
+
+    case "$_arch" in
+        @ARCHITECTURE_PATTERN@)
+            ;;
+        *)
+            err "This installer is built for @TARGET@, but this machine is $_arch"
+            ;;
+    esac
+
+    local _dir
+    _dir="$(mktemp -d 2>/dev/null || ensure mktemp -d -t nmkup)"
+
+    say "extracting installer"
+    ensure tail -n +@PAYLOAD_LINE@ "$0" > "$_dir/payload.tar"
+    ensure tar -xf "$_dir/payload.tar" -C "$_dir"
+    ignore rm "$_dir/payload.tar"
+
+@VERIFY_CHECKSUMS@
+    local _file="$_dir/nmkup-init"
+    ensure chmod u+x "$_file"
+    if [ ! -x "$_file" ]; then
+        printf '%s\n' "Cannot execute $_file (likely because of mounting /tmp as noexec)." 1>&2
+        printf '%s\n' "Please set TMPDIR to a location where you can execute binaries." 1>&2
+        exit 1
+    fi
+
+    ignore "$_file" --bundle "$_dir" "$@"
+    local _retval=$?
+
+    ignore rm -rf "$_dir"
+
+    return "$_retval"
+}
+
+main "$@" || exit 1
+exit 0
@@ -1,8 +1,12 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
 
 use dirs::home_dir;
+use nix::unistd::Uid;
 
 use nmk::backup::backup_files;
+use nmk::error::{Error, ErrorKind};
 use nmk::home::NmkHome;
 use nmk::platform;
 
@@ -11,41 +15,129 @@ mod cmdline;
 mod config;
 mod dotfiles;
 mod entrypoint;
+mod export;
 mod logging;
+mod metadata;
 mod os_release;
+mod remote;
+mod report;
+#[cfg(test)]
+#[path = "../../nmk/test_util.rs"]
+mod test_util;
 mod updater;
 mod vendor;
 
-async fn main_task(cmd_opt: cmdline::CmdOpt, _settings: config::Config) -> nmk::Result<()> {
+/// Components in order of installation
+const COMPONENTS: &[&str] = &[dotfiles::TAG, entrypoint::TAG, updater::TAG, vendor::TAG];
+
+async fn main_task(
+    cmd_opt: &cmdline::CmdOpt,
+    _settings: config::Config,
+    report: &mut report::Report,
+) -> nmk::Result<()> {
     // Installation should be done in order
-    let nmk_home = NmkHome::find_for_install().expect("failed to locate NMK_HOME");
+    let nmk_home = if cmd_opt.system {
+        assert!(
+            Uid::current().is_root(),
+            "System-wide installation requires root. Abort."
+        );
+        NmkHome::find_system_for_install()
+    } else {
+        NmkHome::find_for_install().expect("failed to locate NMK_HOME")
+    };
+    let remote = remote::Remote::new(cmd_opt);
+    if cmd_opt.refresh_metadata {
+        return metadata::refresh(&nmk_home, &remote).await;
+    }
     assert!(!nmk_home.is_git(), "nmk is managed by git. Abort.");
     if cmd_opt.backup {
         let home = home_dir().expect("failed to find home directory");
         let output_tar = home.join("nmk-backup.tar");
         backup_files(&nmk_home, &output_tar)?;
     }
-    dotfiles::install_or_update(&cmd_opt, &nmk_home).await?;
+    let status = report.start(dotfiles::TAG);
+    let result = dotfiles::install_or_update(cmd_opt, &nmk_home, &remote, status).await;
+    report.finish(result)?;
     if platform::is_mac() {
         log::error!("Not supporting os");
-        return Ok(());
+        report.skip(entrypoint::TAG);
+        report.skip(updater::TAG);
+        report.skip(vendor::TAG);
+        return metadata::record(&nmk_home, report);
     }
-    let entrypoint_installation = entrypoint::install_or_update(&cmd_opt, &nmk_home).await?;
-    updater::self_setup(&nmk_home, is_init(), entrypoint_installation).await?;
+    let status = report.start(entrypoint::TAG);
+    let result = entrypoint::install_or_update(cmd_opt, &nmk_home, &remote, status).await;
+    let entrypoint_installation = report.finish(result)?;
+    let status = report.start(updater::TAG);
+    let result = updater::self_setup(
+        &nmk_home,
+        is_init(),
+        entrypoint_installation,
+        &remote,
+        status,
+    )
+    .await;
+    report.finish(result)?;
     if cmd_opt.vendor {
-        vendor::install(&cmd_opt, &nmk_home).await?;
+        let status = report.start(vendor::TAG);
+        let result = vendor::install(cmd_opt, &nmk_home, &remote, status).await;
+        report.finish(result)?;
+    } else {
+        report.skip(vendor::TAG);
     }
-    Ok(())
+    metadata::record(&nmk_home, report)
 }
 
-fn main() -> nmk::Result<()> {
+fn main() {
     let cmd_opt = cmdline::from_args();
     let config = config::Config::new();
     logging::setup(cmd_opt.verbosity);
+    if let Err(e) = run(&cmd_opt, config) {
+        log::error!("{}", e.report(cmd_opt.verbosity > 0));
+        std::process::exit(1);
+    }
+}
+
+fn run(cmd_opt: &cmdline::CmdOpt, config: config::Config) -> nmk::Result<()> {
     let rt = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()?;
-    rt.block_on(main_task(cmd_opt, config))
+    if let Some(cmdline::SubCommand::ExportInstaller(ref opt)) = cmd_opt.cmd {
+        return rt.block_on(export::export_installer(opt));
+    }
+    let mut report = report::Report::default();
+    // A panic still gets its report, the default hook has printed the message already
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        rt.block_on(main_task(cmd_opt, config, &mut report))
+    }));
+    let result = match result {
+        Ok(result) => {
+            report.complete(COMPONENTS, None);
+            result
+        }
+        Err(payload) => {
+            let message = panic_message(payload.as_ref());
+            report.complete(COMPONENTS, Some(&message));
+            Err(Error::msg(
+                ErrorKind::Other,
+                format!("panicked: {}", message),
+            ))
+        }
+    };
+    if cmd_opt.output == cmdline::OutputFormat::Json {
+        println!("{}", report.to_json()?);
+    }
+    result
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
 }
 
 /// Check if this script is run from init script
@@ -0,0 +1,47 @@
+use nmk::home::NmkHome;
+use nmk::update_cache::{epoch_seconds, UpdateCache, DOTFILES, ENTRYPOINT};
+
+use crate::build::Target;
+use crate::remote::Remote;
+use crate::report::Report;
+use crate::{dotfiles, entrypoint};
+
+const TAG: &str = "metadata";
+
+/// Fetch latest remote metadata without installing anything
+pub async fn refresh(nmk_home: &NmkHome, remote: &Remote) -> nmk::Result<()> {
+    let target = Target::detect().expect("unsupported arch");
+    let objects = [
+        (DOTFILES, dotfiles::REMOTE_OBJECT.to_string()),
+        (ENTRYPOINT, target.remote_binary_name("nmk")),
+    ];
+    let mut cache = UpdateCache::load(nmk_home).unwrap_or_default();
+    for (component, object) in IntoIterator::into_iter(objects) {
+        log::debug!("{}: Getting {} metadata.", TAG, component);
+        let meta = remote.get_object_meta(&object).await?;
+        cache
+            .generations
+            .insert(component.to_string(), meta.generation);
+    }
+    cache.checked_at = epoch_seconds();
+    cache.save(nmk_home)?;
+    log::info!("{}: Done.", TAG);
+    Ok(())
+}
+
+/// Update cached remote metadata with generations we've seen during installation
+pub fn record(nmk_home: &NmkHome, report: &Report) -> nmk::Result<()> {
+    let mut cache = UpdateCache::load(nmk_home).unwrap_or_default();
+    for (component, tag) in [(DOTFILES, dotfiles::TAG), (ENTRYPOINT, entrypoint::TAG)].iter() {
+        let generation = report
+            .components
+            .iter()
+            .find(|c| c.component == *tag)
+            .and_then(|c| c.new_generation.clone());
+        if let Some(generation) = generation {
+            cache.generations.insert(component.to_string(), generation);
+        }
+    }
+    cache.checked_at = epoch_seconds();
+    cache.save(nmk_home)
+}
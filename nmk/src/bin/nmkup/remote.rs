@@ -0,0 +1,82 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use reqwest::Client;
+
+use nmk::error::{Context, Error, ErrorKind};
+use nmk::gcs::{download_file, get_object_meta, get_object_meta_url, ObjectMeta};
+
+use crate::cmdline::CmdOpt;
+
+/// Where installation files come from
+pub enum Remote {
+    Gcs(Client),
+    /// Directory extracted from installer script, see `export-installer`
+    Bundle(PathBuf),
+}
+
+impl Remote {
+    pub fn new(cmd_opt: &CmdOpt) -> Self {
+        match cmd_opt.bundle {
+            Some(ref dir) => Remote::Bundle(dir.clone()),
+            None => Remote::Gcs(Client::new()),
+        }
+    }
+
+    pub async fn get_object_meta(&self, object: &str) -> nmk::Result<ObjectMeta> {
+        match self {
+            Remote::Gcs(client) => get_object_meta(client, &get_object_meta_url(object))
+                .await
+                .with_context(|| format!("failed to get metadata of {}", object)),
+            Remote::Bundle(dir) => read_bundle_meta(&dir.join(bundle_meta_name(object))),
+        }
+    }
+
+    pub async fn download(&self, meta: &ObjectMeta) -> nmk::Result<Bytes> {
+        let data = match self {
+            Remote::Gcs(client) => download_file(client, &meta.media_link)
+                .await
+                .with_context(|| format!("failed to download {}", meta.name))?,
+            Remote::Bundle(dir) => fs::read(dir.join(&meta.name))
+                .with_context(|| format!("failed to read {} from bundle", meta.name))?
+                .into(),
+        };
+        meta.verify_size(&data)?;
+        Ok(data)
+    }
+}
+
+/// Bundle without metadata of an object is incomplete, not something to panic on
+fn read_bundle_meta(path: &Path) -> nmk::Result<ObjectMeta> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(Error::msg(
+                ErrorKind::Integrity,
+                format!("bundle is missing {}", path.display()),
+            ))
+        }
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    serde_json::from_slice(&data).with_context(|| format!("invalid metadata {}", path.display()))
+}
+
+/// File name of object metadata inside bundle
+pub fn bundle_meta_name(object: &str) -> String {
+    format!("{}.meta", object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_missing_meta() {
+        let path = Path::new("/nonexistent/bundle").join(bundle_meta_name("nmk.tar.xz"));
+        let err = read_bundle_meta(&path).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::Integrity);
+        assert!(err.to_string().contains("nmk.tar.xz.meta"));
+    }
+}
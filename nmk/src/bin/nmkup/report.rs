@@ -0,0 +1,182 @@
+use std::time::Instant;
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Installed,
+    UpToDate,
+    Skipped,
+    Failed,
+}
+
+/// Outcome of installing or updating a single component
+#[derive(Debug, Serialize)]
+pub struct ComponentReport {
+    pub component: &'static str,
+    pub action: Action,
+    pub previous_generation: Option<String>,
+    pub new_generation: Option<String>,
+    pub bytes_downloaded: u64,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+    #[serde(skip)]
+    start_time: Instant,
+    #[serde(skip)]
+    finished: bool,
+}
+
+impl ComponentReport {
+    fn new(component: &'static str) -> Self {
+        Self {
+            component,
+            action: Action::Skipped,
+            previous_generation: None,
+            new_generation: None,
+            bytes_downloaded: 0,
+            duration_ms: 0,
+            error: None,
+            start_time: Instant::now(),
+            finished: false,
+        }
+    }
+
+    pub fn add_downloaded(&mut self, len: usize) {
+        self.bytes_downloaded += len as u64;
+    }
+
+    /// Record duration, and error details if `result` is an error
+    fn finish<T>(&mut self, result: nmk::Result<T>) -> nmk::Result<T> {
+        if let Err(ref e) = result {
+            self.fail(format!("{:#}", e));
+        }
+        self.duration_ms = self.start_time.elapsed().as_millis() as u64;
+        self.finished = true;
+        result
+    }
+
+    fn fail(&mut self, error: String) {
+        self.action = Action::Failed;
+        self.error = Some(error);
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub components: Vec<ComponentReport>,
+}
+
+impl Report {
+    /// Start tracking a component, its result is recorded by `finish`
+    pub fn start(&mut self, component: &'static str) -> &mut ComponentReport {
+        self.components.push(ComponentReport::new(component));
+        self.components
+            .last_mut()
+            .expect("component was just added")
+    }
+
+    /// Record result of the last started component
+    pub fn finish<T>(&mut self, result: nmk::Result<T>) -> nmk::Result<T> {
+        match self.components.last_mut() {
+            Some(component) => component.finish(result),
+            None => result,
+        }
+    }
+
+    pub fn skip(&mut self, component: &'static str) {
+        let mut skipped = ComponentReport::new(component);
+        skipped.finished = true;
+        self.components.push(skipped);
+    }
+
+    /// Fail the component which was interrupted by `panic`, and skip the ones which never
+    /// started, so every component has a result
+    pub fn complete(&mut self, components: &[&'static str], panic: Option<&str>) {
+        for component in self.components.iter_mut().filter(|c| !c.finished) {
+            let error = match panic {
+                Some(message) => format!("panicked: {}", message),
+                None => "interrupted".to_string(),
+            };
+            component.fail(error);
+            component.duration_ms = component.start_time.elapsed().as_millis() as u64;
+            component.finished = true;
+        }
+        for name in components {
+            if !self.components.iter().any(|c| c.component == *name) {
+                self.skip(name);
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> nmk::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPONENTS: &[&str] = &["dotfiles", "entrypoint", "vendor"];
+
+    #[test]
+    fn test_report_json() {
+        let mut report = Report::default();
+        let dotfiles = report.start("dotfiles");
+        dotfiles.action = Action::Installed;
+        dotfiles.previous_generation = Some("1".to_string());
+        dotfiles.new_generation = Some("2".to_string());
+        dotfiles.add_downloaded(1024);
+        report.finish(Ok(())).unwrap();
+        report.skip("vendor");
+        report.complete(&["dotfiles", "vendor"], None);
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        let components = json["components"].as_array().unwrap();
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0]["component"], "dotfiles");
+        assert_eq!(components[0]["action"], "installed");
+        assert_eq!(components[0]["previous_generation"], "1");
+        assert_eq!(components[0]["new_generation"], "2");
+        assert_eq!(components[0]["bytes_downloaded"], 1024);
+        assert!(components[0]["error"].is_null());
+        assert_eq!(components[1]["action"], "skipped");
+    }
+
+    #[test]
+    fn test_report_failed() {
+        let mut report = Report::default();
+        report.start("dotfiles");
+        let err = std::io::Error::other("boom");
+        let result: nmk::Result<()> = Err(err.into());
+        assert!(report.finish(result).is_err());
+        // later components never ran
+        report.complete(COMPONENTS, None);
+        let actions: Vec<_> = report.components.iter().map(|c| c.action).collect();
+        assert_eq!(actions, [Action::Failed, Action::Skipped, Action::Skipped]);
+        assert!(report.components[0]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("boom"));
+    }
+
+    #[test]
+    fn test_report_panicked() {
+        let mut report = Report::default();
+        report.start("dotfiles");
+        report.finish(Ok(())).unwrap();
+        report.start("entrypoint");
+        report.complete(COMPONENTS, Some("unsupported arch"));
+        let entrypoint = &report.components[1];
+        assert_eq!(entrypoint.action, Action::Failed);
+        assert_eq!(
+            entrypoint.error.as_deref(),
+            Some("panicked: unsupported arch")
+        );
+        assert_eq!(report.components[0].action, Action::Skipped);
+        assert_eq!(report.components[2].component, "vendor");
+        assert_eq!(report.components[2].action, Action::Skipped);
+    }
+}
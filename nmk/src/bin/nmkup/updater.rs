@@ -4,19 +4,22 @@ use std::{env, fs, io};
 use bytes::{Buf, Bytes};
 use same_file::is_same_file;
 
-use nmk::gcs::{download_file, get_object_meta, get_object_meta_url};
 use nmk::home::NmkHome;
 use nmk::setup::install;
 
 use crate::build::Target;
 use crate::entrypoint::EntrypointInstallation;
+use crate::remote::Remote;
+use crate::report::{Action, ComponentReport};
 
-const TAG: &str = "updater";
+pub const TAG: &str = "updater";
 
 pub async fn self_setup(
     nmk_home: &NmkHome,
     is_init: bool,
     entrypoint_installation: EntrypointInstallation,
+    remote: &Remote,
+    report: &mut ComponentReport,
 ) -> nmk::Result<()> {
     let current_exec = env::current_exe()?;
     let target_bin = nmk_home.nmk_path().bin().join("nmkup");
@@ -26,36 +29,37 @@ pub async fn self_setup(
         // Entrypoint and updater are built at the same time.
         // So we update updater if entrypoint is updated.
         if matches!(entrypoint_installation, EntrypointInstallation::Installed) {
-            perform_self_update_from_remote(&target_bin).await?;
+            perform_self_update_from_remote(&target_bin, remote, report).await?;
+            report.action = Action::Installed;
             log::info!("{}: Done.", TAG);
+        } else {
+            report.action = Action::UpToDate;
         }
     } else {
-        fs::copy(current_exec, target_bin)?;
+        install(&mut fs::File::open(current_exec)?, target_bin)?;
+        report.action = Action::Installed;
         log::info!("{}: Done.", TAG);
     }
     Ok(())
 }
 
-pub async fn perform_self_update_from_remote(target_bin: &Path) -> nmk::Result<()> {
+pub async fn perform_self_update_from_remote(
+    target_bin: &Path,
+    remote: &Remote,
+    report: &mut ComponentReport,
+) -> nmk::Result<()> {
     let target = Target::detect().expect("unsupported arch");
     let tar_file = target.remote_binary_name("nmkup");
-    let client = reqwest::Client::new();
-    let meta_url = get_object_meta_url(&tar_file);
     log::debug!("{}: Getting metadata.", TAG);
-    let meta = get_object_meta(&client, &meta_url).await?;
+    let meta = remote.get_object_meta(&tar_file).await?;
     log::debug!("{}: Received metadata.", TAG);
-    let data_url = &meta.media_link;
-    log::debug!("{}: Getting data from {}.", TAG, data_url);
-    let data = download_file(&client, data_url).await?;
+    report.new_generation = Some(meta.generation.clone());
+    log::debug!("{}: Getting data from {}.", TAG, meta.media_link);
+    let data = remote.download(&meta).await?;
+    report.add_downloaded(data.len());
 
-    let target_bin = fs::canonicalize(target_bin)?;
-    let parent_dir = target_bin
-        .parent()
-        .unwrap_or_else(|| panic!("{}: Failed to find parent directory.", TAG));
-    let temp_target = parent_dir.join("nmkup.next");
-    install_updater(data, &temp_target)
-        .unwrap_or_else(|_| panic!("{}: Failed to extract data", TAG));
-    fs::rename(temp_target, target_bin)?;
+    // install replaces the running binary with a rename, it never writes in place
+    install_updater(data, target_bin).unwrap_or_else(|_| panic!("{}: Failed to extract data", TAG));
     Ok(())
 }
 
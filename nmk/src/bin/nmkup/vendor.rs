@@ -4,35 +4,52 @@ use std::path::Path;
 use std::{fs, io};
 
 use bytes::Bytes;
+use reqwest::Client;
 use tar::Archive;
 use xz2::read::XzDecoder;
 
-use nmk::gcs::{download_file, ObjectMeta};
+use nmk::error::{Context, Error, ErrorKind};
+use nmk::gcs::ObjectMeta;
 use nmk::home::NmkHome;
 
 use crate::build::Target;
 use crate::cmdline::CmdOpt;
 use crate::os_release::OsReleaseId;
+use crate::remote::Remote;
+use crate::report::{Action, ComponentReport};
 
+/// Name of vendor files inside installer bundle
+pub const BUNDLE_OBJECT: &str = "vendor.tar.xz";
 const LIST_OBJECTS_URL: &str =
     "https://storage.googleapis.com/storage/v1/b/nmk.nuimk.com/o?delimiter=/&prefix=nmk-vendor/";
-const TAG: &str = "vendor";
+pub const TAG: &str = "vendor";
 
-pub async fn install(cmd_opt: &CmdOpt, nmk_home: &NmkHome) -> nmk::Result<()> {
-    let client = reqwest::Client::new();
-    let mut objects: Vec<_> = nmk::gcs::list_objects(&client, LIST_OBJECTS_URL).await?;
-    objects.retain(|obj| obj.name.ends_with(".tar.xz"));
-    if !cmd_opt.no_filter {
-        objects.retain(filter_by_os_release());
-        objects.retain(filter_by_arch());
-    }
-    let obj_meta = select_vendor_files(&objects)?;
-    let download_url = obj_meta.media_link.as_str();
-    log::info!("{}: Download url {}", TAG, download_url);
-    let client = reqwest::Client::new();
-    log::debug!("{}: Getting data.", TAG);
-    let tar_xz_data = download_file(&client, download_url).await?;
+pub async fn install(
+    cmd_opt: &CmdOpt,
+    nmk_home: &NmkHome,
+    remote: &Remote,
+    report: &mut ComponentReport,
+) -> nmk::Result<()> {
+    let obj_meta = match remote {
+        Remote::Gcs(client) => {
+            let mut objects = list_vendor_files(client).await?;
+            if !cmd_opt.no_filter {
+                objects.retain(filter_by_os_release());
+                objects.retain(filter_by_arch());
+            }
+            select_vendor_files(&objects)?.clone()
+        }
+        // Installer is used where remote storage can't be reached, never fall back to it
+        Remote::Bundle(_) => remote
+            .get_object_meta(BUNDLE_OBJECT)
+            .await
+            .context("installer has no vendor files, export it with --vendor")?,
+    };
+    report.new_generation = Some(obj_meta.generation.clone());
+    log::info!("{}: Getting {}", TAG, obj_meta.name);
+    let tar_xz_data = remote.download(&obj_meta).await?;
     log::debug!("{}: Received data.", TAG);
+    report.add_downloaded(tar_xz_data.len());
     let vendor_dir = nmk_home.nmk_path().vendor();
     if vendor_dir.exists() {
         log::debug!("{}: Removing {:?} content.", TAG, vendor_dir);
@@ -42,10 +59,34 @@ pub async fn install(cmd_opt: &CmdOpt, nmk_home: &NmkHome) -> nmk::Result<()> {
     }
     log::debug!("{}: Extracting data.", TAG);
     extract_vendor_files(tar_xz_data, &vendor_dir).await?;
+    report.action = Action::Installed;
     log::info!("{}: Done.", TAG);
     Ok(())
 }
 
+async fn list_vendor_files(client: &Client) -> nmk::Result<Vec<ObjectMeta>> {
+    let mut objects = nmk::gcs::list_objects(client, LIST_OBJECTS_URL).await?;
+    objects.retain(|obj| obj.name.ends_with(".tar.xz"));
+    Ok(objects)
+}
+
+/// Vendor files named as `nmkup --vendor` lists them, for `export-installer`
+pub async fn find_vendor_files(client: &Client, name: &str) -> nmk::Result<ObjectMeta> {
+    let objects = list_vendor_files(client).await?;
+    let names = get_display_name(&objects);
+    match names.iter().position(|n| *n == name) {
+        Some(index) => Ok(objects[index].clone()),
+        None => Err(Error::msg(
+            ErrorKind::Other,
+            format!(
+                "vendor files {} not found, available: {}",
+                name,
+                names.join(", ")
+            ),
+        )),
+    }
+}
+
 fn filter_by_os_release() -> impl FnMut(&ObjectMeta) -> bool {
     use crate::os_release::OsReleaseId::*;
     let pattern = OsReleaseId::parse_os_release().map(|id| match id {
@@ -56,7 +97,7 @@ fn filter_by_os_release() -> impl FnMut(&ObjectMeta) -> bool {
     });
     move |item: &ObjectMeta| {
         // Try to filter by os-release data, if we can't determine os-release, don't filter at all.
-        pattern.map_or(true, |pat| item.name.contains(pat))
+        pattern.is_none_or(|pat| item.name.contains(pat))
     }
 }
 
@@ -76,7 +117,7 @@ fn filter_by_arch() -> impl FnMut(&ObjectMeta) -> bool {
 fn get_display_name(objects: &[ObjectMeta]) -> Vec<&str> {
     objects
         .iter()
-        .flat_map(|obj| obj.name.split('/').last())
+        .flat_map(|obj| obj.name.split('/').next_back())
         .collect()
 }
 
@@ -141,3 +182,65 @@ async fn extract_vendor_files(data: Bytes, destination: impl AsRef<Path>) -> io:
     log::info!("{}: Installing to {:?}.", TAG, destination.as_ref());
     archive.unpack(destination)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use structopt::StructOpt;
+
+    use super::*;
+    use crate::remote::bundle_meta_name;
+    use crate::report::Report;
+    use crate::test_util::TempDir;
+
+    fn tar_xz(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        tar.append_data(&mut header, name, data).unwrap();
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(&tar.into_inner().unwrap()).unwrap();
+        xz.finish().unwrap()
+    }
+
+    #[test]
+    fn test_install_from_bundle() {
+        let bundle = TempDir::new("vendor-bundle");
+        let home = TempDir::new("vendor-home");
+        let nmk_home = NmkHome::from(home.to_path_buf());
+        let cmd_opt = CmdOpt::from_iter(&["nmkup", "--vendor"]);
+        let remote = Remote::Bundle(bundle.to_path_buf());
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut report = Report::default();
+        let mut install = || rt.block_on(install(&cmd_opt, &nmk_home, &remote, report.start(TAG)));
+
+        let err = install().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "installer has no vendor files, export it with --vendor"
+        );
+
+        let data = tar_xz("bin/tmux", b"tmux");
+        fs::create_dir(bundle.join("nmk-vendor")).unwrap();
+        fs::write(bundle.join("nmk-vendor/ubuntu.tar.xz"), &data).unwrap();
+        let meta = ObjectMeta {
+            id: String::new(),
+            self_link: String::new(),
+            media_link: String::new(),
+            name: "nmk-vendor/ubuntu.tar.xz".to_string(),
+            generation: "1".to_string(),
+            size: data.len().to_string(),
+            md5_hash: String::new(),
+            etag: String::new(),
+        };
+        let meta_path = bundle.join(bundle_meta_name(BUNDLE_OBJECT));
+        fs::write(meta_path, serde_json::to_vec(&meta).unwrap()).unwrap();
+        install().unwrap();
+        assert_eq!(fs::read(home.join("vendor/bin/tmux")).unwrap(), b"tmux");
+    }
+}
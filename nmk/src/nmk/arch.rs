@@ -1,34 +1,707 @@
-use std::io::Write;
-use std::iter;
-use std::process::{Command, Stdio};
-
-use indoc::indoc;
-
-/// A modified version of https://sh.rustup.rs
-///
-/// N.B. we could compress this but include-flate (0.1.3) doesn't support stable yet.
-const NMKUP_INIT_SCRIPT: &str = include_str!("../../nmkup.nuimk.com/nmkup-init.sh");
-
-const GET_ARCHITECTURE: &str = indoc! {r##"
-    get_architecture || return 1
-    echo $RETVAL
-"##};
-
-pub fn detect_current_architecture() -> crate::Result<String> {
-    let get_architecture_script = NMKUP_INIT_SCRIPT
-        .lines()
-        .take_while(|line| !line.starts_with(r##"main "$@""##))
-        .chain(iter::once(GET_ARCHITECTURE))
-        .collect::<Vec<_>>()
-        .join("\n");
-    let mut shell = Command::new("sh")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()?;
-    let stdin = shell.stdin.as_mut().expect("failed to open stdin");
-    stdin.write_all(get_architecture_script.as_bytes())?;
-    let output = shell.wait_with_output()?;
-    let arch = std::str::from_utf8(&output.stdout)?.trim().to_string();
-    Ok(arch)
+//! Native port of `get_architecture` from nmkup-init.sh (a modified version of https://sh.rustup.rs)
+//!
+//! It gives the same answer as the script except for arm, where we know more than the script:
+//! - ABI comes from float flags of userland ELF, so soft-float userland gets `eabi` where the
+//!   script assumes `eabihf` for armv6l and armv7l, and nothing for arm.
+//! - armv7 without NEON falls back to arm with musl too, the script only checks glibc.
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::Read;
+
+use nix::sys::utsname::uname;
+
+/// Files used to inspect userland, `/bin/sh` is preferred because our own binary is static
+const ELF_CANDIDATES: &[&str] = &["/bin/sh", "/proc/self/exe"];
+
+/// Program headers (and interpreter path) are near the start of file, no need to read all of it
+const ELF_READ_LIMIT: u64 = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Cpu {
+    X86_64,
+    I686,
+    Aarch64,
+    Arm,
+    ArmV7,
+    Mips,
+    Mipsel,
+    Mips64,
+    Mips64el,
+    PowerPc,
+    PowerPc64,
+    PowerPc64le,
+    S390x,
+    Riscv64gc,
+}
+
+impl Cpu {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Cpu::X86_64 => "x86_64",
+            Cpu::I686 => "i686",
+            Cpu::Aarch64 => "aarch64",
+            Cpu::Arm => "arm",
+            Cpu::ArmV7 => "armv7",
+            Cpu::Mips => "mips",
+            Cpu::Mipsel => "mipsel",
+            Cpu::Mips64 => "mips64",
+            Cpu::Mips64el => "mips64el",
+            Cpu::PowerPc => "powerpc",
+            Cpu::PowerPc64 => "powerpc64",
+            Cpu::PowerPc64le => "powerpc64le",
+            Cpu::S390x => "s390x",
+            Cpu::Riscv64gc => "riscv64gc",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Os {
+    Linux,
+    Android,
+    FreeBsd,
+    NetBsd,
+    DragonFly,
+    Darwin,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Libc {
+    Gnu,
+    Musl,
+}
+
+/// Suffix after libc in linux triples
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Abi {
+    Default,
+    Eabi,
+    EabiHf,
+    Abi64,
+}
+
+/// Target triple of the running machine, formats the same as `get_architecture`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Triple {
+    pub cpu: Cpu,
+    pub os: Os,
+    /// Only meaningful for linux
+    pub libc: Libc,
+    pub abi: Abi,
+}
+
+impl Display for Triple {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cpu = self.cpu.as_str();
+        match self.os {
+            Os::Linux => {
+                let libc = match self.libc {
+                    Libc::Gnu => "gnu",
+                    Libc::Musl => "musl",
+                };
+                let abi = match self.abi {
+                    Abi::Default => "",
+                    Abi::Eabi => "eabi",
+                    Abi::EabiHf => "eabihf",
+                    Abi::Abi64 => "abi64",
+                };
+                write!(f, "{}-unknown-linux-{}{}", cpu, libc, abi)
+            }
+            Os::Android => match self.cpu {
+                Cpu::Arm | Cpu::ArmV7 => write!(f, "{}-linux-androideabi", cpu),
+                _ => write!(f, "{}-linux-android", cpu),
+            },
+            Os::FreeBsd => write!(f, "{}-unknown-freebsd", cpu),
+            Os::NetBsd => write!(f, "{}-unknown-netbsd", cpu),
+            Os::DragonFly => write!(f, "{}-unknown-dragonfly", cpu),
+            Os::Darwin => write!(f, "{}-apple-darwin", cpu),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ArchError {
+    UnknownOs(String),
+    UnknownCpu(String),
+    Unsupported(&'static str),
+}
+
+impl Display for ArchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchError::UnknownOs(os) => write!(f, "unrecognized OS type: {}", os),
+            ArchError::UnknownCpu(cpu) => write!(f, "unknown CPU type: {}", cpu),
+            ArchError::Unsupported(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl std::error::Error for ArchError {}
+
+/// What we learn from ELF header of a userland binary
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct ElfInfo {
+    is_64bit: bool,
+    big_endian: bool,
+    /// Only known for arm binaries
+    hard_float: Option<bool>,
+    interpreter: Option<String>,
+}
+
+/// Everything the detection depends on, so it can be tested with fixtures
+#[derive(Clone, Debug)]
+struct Probe {
+    sysname: String,
+    machine: String,
+    is_android: bool,
+    elf: Option<ElfInfo>,
+    libc: Libc,
+    cpuinfo: Option<String>,
+    /// `uname -m` of Darwin says i386 on some 64-bit machines, sysctl knows better
+    darwin_x86_64: bool,
+}
+
+impl Probe {
+    fn current() -> Self {
+        let uts = uname();
+        let sysname = uts.sysname().to_string();
+        let is_linux = sysname == "Linux";
+        let elf = ELF_CANDIDATES.iter().find_map(|path| read_elf_info(path));
+        let libc = if is_linux {
+            detect_libc(elf.as_ref())
+        } else {
+            Libc::Gnu
+        };
+        let machine = uts.machine().to_string();
+        let darwin_x86_64 = sysname == "Darwin" && machine == "i386" && sysctl_has_x86_64();
+        Self {
+            machine,
+            darwin_x86_64,
+            is_android: is_linux && std::env::var_os("ANDROID_ROOT").is_some(),
+            cpuinfo: is_linux
+                .then(|| fs::read_to_string("/proc/cpuinfo").ok())
+                .flatten(),
+            sysname,
+            elf,
+            libc,
+        }
+    }
+}
+
+pub fn detect_current_architecture() -> crate::Result<Triple> {
+    Ok(detect(&Probe::current())?)
+}
+
+fn detect(probe: &Probe) -> Result<Triple, ArchError> {
+    let os = match probe.sysname.as_str() {
+        "Linux" if probe.is_android => Os::Android,
+        "Linux" => Os::Linux,
+        "FreeBSD" => Os::FreeBsd,
+        "NetBSD" => Os::NetBsd,
+        "DragonFly" => Os::DragonFly,
+        "Darwin" => Os::Darwin,
+        other => return Err(ArchError::UnknownOs(other.to_string())),
+    };
+    let elf = probe.elf.as_ref();
+    // Like the script, only glibc userland is checked for 32-bit, static musl binaries of the
+    // 64-bit cpu run anyway
+    let is_32bit = os == Os::Linux && probe.libc == Libc::Gnu && elf.is_some_and(|e| !e.is_64bit);
+    // Soft-float userland on hard-float capable cpu should use eabi
+    let arm_abi = |default| match elf.and_then(|e| e.hard_float) {
+        Some(true) => Abi::EabiHf,
+        Some(false) => Abi::Eabi,
+        None => default,
+    };
+    let mips = |big, little| match elf {
+        Some(e) if e.big_endian => Ok(big),
+        Some(_) => Ok(little),
+        None => Err(ArchError::Unsupported("unknown platform endianness")),
+    };
+    let machine = if probe.darwin_x86_64 {
+        "x86_64"
+    } else {
+        probe.machine.as_str()
+    };
+
+    let (mut cpu, mut abi) = match machine {
+        "i386" | "i486" | "i686" | "i786" | "x86" => (Cpu::I686, Abi::Default),
+        "xscale" | "arm" => (Cpu::Arm, arm_abi(Abi::Default)),
+        "armv6l" => (Cpu::Arm, arm_abi(Abi::EabiHf)),
+        "armv7l" | "armv8l" => (Cpu::ArmV7, arm_abi(Abi::EabiHf)),
+        "aarch64" | "arm64" => (Cpu::Aarch64, Abi::Default),
+        "x86_64" | "x86-64" | "x64" | "amd64" => (Cpu::X86_64, Abi::Default),
+        "mips" => (mips(Cpu::Mips, Cpu::Mipsel)?, Abi::Default),
+        // only n64 ABI is supported for now
+        "mips64" if elf.is_some_and(|e| e.is_64bit) => {
+            (mips(Cpu::Mips64, Cpu::Mips64el)?, Abi::Abi64)
+        }
+        "mips64" => (Cpu::Mips64, Abi::Default),
+        "ppc" => (Cpu::PowerPc, Abi::Default),
+        "ppc64" => (Cpu::PowerPc64, Abi::Default),
+        "ppc64le" => (Cpu::PowerPc64le, Abi::Default),
+        "s390x" => (Cpu::S390x, Abi::Default),
+        "riscv64" => (Cpu::Riscv64gc, Abi::Default),
+        other => return Err(ArchError::UnknownCpu(other.to_string())),
+    };
+
+    // 64-bit linux with 32-bit userland
+    if is_32bit {
+        match cpu {
+            Cpu::X86_64 => cpu = Cpu::I686,
+            Cpu::Mips64 => cpu = mips(Cpu::Mips, Cpu::Mipsel)?,
+            Cpu::PowerPc64 => cpu = Cpu::PowerPc,
+            Cpu::Aarch64 => {
+                cpu = Cpu::ArmV7;
+                abi = arm_abi(Abi::EabiHf);
+            }
+            Cpu::Riscv64gc => {
+                return Err(ArchError::Unsupported(
+                    "riscv64 with 32-bit userland unsupported",
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    // Android triples don't carry abi
+    if os == Os::Android {
+        abi = Abi::Default;
+    }
+
+    // armv7 without the CPU features Rust needs in that build, fall back to arm. Our musleabihf
+    // build needs NEON as well. See https://github.com/rust-lang/rustup.rs/issues/587.
+    if os == Os::Linux && cpu == Cpu::ArmV7 && abi == Abi::EabiHf {
+        if let Some(cpuinfo) = &probe.cpuinfo {
+            let without_neon = cpuinfo
+                .lines()
+                .filter(|line| line.starts_with("Features"))
+                .any(|line| !line.split_whitespace().any(|f| f == "neon"));
+            if without_neon {
+                cpu = Cpu::Arm;
+            }
+        }
+    }
+
+    Ok(Triple {
+        cpu,
+        os,
+        libc: probe.libc,
+        abi,
+    })
+}
+
+fn sysctl_has_x86_64() -> bool {
+    std::process::Command::new("sysctl")
+        .arg("hw.optional.x86_64")
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains(": 1"))
+}
+
+fn read_elf_info(path: &str) -> Option<ElfInfo> {
+    let mut data = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(ELF_READ_LIMIT)
+        .read_to_end(&mut data)
+        .ok()?;
+    parse_elf(&data)
+}
+
+fn parse_elf(data: &[u8]) -> Option<ElfInfo> {
+    const EM_ARM: u16 = 40;
+    const PT_INTERP: u32 = 3;
+    const EF_ARM_EABIMASK: u32 = 0xFF00_0000;
+    const EF_ARM_EABI_VER5: u32 = 0x0500_0000;
+    const EF_ARM_ABI_FLOAT_SOFT: u32 = 0x200;
+    const EF_ARM_ABI_FLOAT_HARD: u32 = 0x400;
+
+    if data.get(..4)? != b"\x7fELF" {
+        return None;
+    }
+    let is_64bit = match data.get(4)? {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let big_endian = match data.get(5)? {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let read = |offset: usize, size: usize| -> Option<u64> {
+        let bytes = data.get(offset..offset.checked_add(size)?)?;
+        let fold = |acc: u64, b: &u8| (acc << 8) | u64::from(*b);
+        Some(if big_endian {
+            bytes.iter().fold(0, fold)
+        } else {
+            bytes.iter().rev().fold(0, fold)
+        })
+    };
+    let word = if is_64bit { 8 } else { 4 };
+    let machine = read(18, 2)? as u16;
+    let (phoff, flags, phentsize, phnum) = if is_64bit {
+        (read(32, 8)?, read(48, 4)?, read(54, 2)?, read(56, 2)?)
+    } else {
+        (read(28, 4)?, read(36, 4)?, read(42, 2)?, read(44, 2)?)
+    };
+    let flags = flags as u32;
+
+    let interpreter = (0..phnum).find_map(|i| {
+        let header = usize::try_from(i.checked_mul(phentsize)?.checked_add(phoff)?).ok()?;
+        if read(header, 4)? as u32 != PT_INTERP {
+            return None;
+        }
+        // p_offset and p_filesz are after p_type (and p_flags on 64-bit)
+        let (offset, size) = if is_64bit {
+            (read(header + 8, word)?, read(header + 32, word)?)
+        } else {
+            (read(header + 4, word)?, read(header + 16, word)?)
+        };
+        let end = usize::try_from(offset.checked_add(size)?).ok()?;
+        let bytes = data.get(usize::try_from(offset).ok()?..end)?;
+        let path = bytes.split(|b| *b == 0).next()?;
+        Some(String::from_utf8_lossy(path).into_owned())
+    });
+
+    let hard_float = if machine != EM_ARM {
+        None
+    } else if flags & EF_ARM_EABIMASK == EF_ARM_EABI_VER5 && flags & EF_ARM_ABI_FLOAT_HARD != 0 {
+        Some(true)
+    } else if flags & EF_ARM_EABIMASK == EF_ARM_EABI_VER5 && flags & EF_ARM_ABI_FLOAT_SOFT != 0 {
+        Some(false)
+    } else {
+        // ld-linux-armhf.so.3 or ld-musl-armhf.so.1
+        interpreter.as_ref().map(|interp| interp.contains("armhf"))
+    };
+
+    Some(ElfInfo {
+        is_64bit,
+        big_endian,
+        hard_float,
+        interpreter,
+    })
+}
+
+fn detect_libc(elf: Option<&ElfInfo>) -> Libc {
+    match elf.and_then(|e| e.interpreter.as_deref()) {
+        Some(interp) => libc_of_interpreter(interp),
+        // static userland, e.g. busybox, look for musl loader instead
+        None => {
+            let has_musl_loader = fs::read_dir("/lib").is_ok_and(|entries| {
+                entries
+                    .flatten()
+                    .any(|entry| entry.file_name().to_string_lossy().starts_with("ld-musl-"))
+            });
+            if has_musl_loader {
+                Libc::Musl
+            } else {
+                Libc::Gnu
+            }
+        }
+    }
+}
+
+fn libc_of_interpreter(interpreter: &str) -> Libc {
+    if interpreter.contains("ld-musl") {
+        Libc::Musl
+    } else {
+        Libc::Gnu
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal ELF file with a program header table holding only PT_INTERP
+    fn elf(is_64bit: bool, big_endian: bool, machine: u16, flags: u32, interp: &str) -> Vec<u8> {
+        let mut data = vec![0u8; 0x100];
+        data[..4].copy_from_slice(b"\x7fELF");
+        data[4] = if is_64bit { 2 } else { 1 };
+        data[5] = if big_endian { 2 } else { 1 };
+        let mut write = |offset: usize, size: usize, value: u64| {
+            let bytes = &mut data[offset..offset + size];
+            for (i, b) in bytes.iter_mut().enumerate() {
+                let shift = if big_endian { size - 1 - i } else { i };
+                *b = (value >> (shift * 8)) as u8;
+            }
+        };
+        let phoff = 0x40;
+        let interp_offset = 0x80;
+        write(18, 2, u64::from(machine));
+        if is_64bit {
+            write(32, 8, phoff);
+            write(48, 4, u64::from(flags));
+            write(54, 2, 56);
+            write(56, 2, 1);
+            write(0x40, 4, 3);
+            write(0x40 + 8, 8, interp_offset);
+            write(0x40 + 32, 8, interp.len() as u64 + 1);
+        } else {
+            write(28, 4, phoff);
+            write(36, 4, u64::from(flags));
+            write(42, 2, 32);
+            write(44, 2, 1);
+            write(0x40, 4, 3);
+            write(0x40 + 4, 4, interp_offset);
+            write(0x40 + 16, 4, interp.len() as u64 + 1);
+        }
+        let start = interp_offset as usize;
+        data[start..start + interp.len()].copy_from_slice(interp.as_bytes());
+        data
+    }
+
+    fn elf_info(is_64bit: bool, big_endian: bool, hard_float: Option<bool>) -> Option<ElfInfo> {
+        Some(ElfInfo {
+            is_64bit,
+            big_endian,
+            hard_float,
+            interpreter: None,
+        })
+    }
+
+    fn linux(machine: &str, elf: Option<ElfInfo>, libc: Libc) -> Probe {
+        Probe {
+            sysname: "Linux".to_string(),
+            machine: machine.to_string(),
+            is_android: false,
+            elf,
+            libc,
+            cpuinfo: None,
+            darwin_x86_64: false,
+        }
+    }
+
+    #[test]
+    fn test_detect_fixtures() {
+        let le64 = || elf_info(true, false, None);
+        let le32 = || elf_info(false, false, None);
+        let hf = || elf_info(false, false, Some(true));
+        let sf = || elf_info(false, false, Some(false));
+        let darwin = |machine: &str| Probe {
+            sysname: "Darwin".to_string(),
+            ..linux(machine, None, Libc::Gnu)
+        };
+        let android = |machine: &str| Probe {
+            is_android: true,
+            ..linux(machine, le64(), Libc::Gnu)
+        };
+        let raspbian_without_neon = Probe {
+            cpuinfo: Some("processor\t: 0\nFeatures\t: half thumb fastmult vfp edsp\n".to_string()),
+            ..linux("armv7l", hf(), Libc::Gnu)
+        };
+        let raspbian_with_neon = Probe {
+            cpuinfo: Some("Features\t: half thumb fastmult vfp edsp neon vfpv3\n".to_string()),
+            ..linux("armv7l", hf(), Libc::Gnu)
+        };
+        let fixtures = vec![
+            (
+                linux("x86_64", le64(), Libc::Gnu),
+                "x86_64-unknown-linux-gnu",
+            ),
+            (
+                linux("x86_64", le64(), Libc::Musl),
+                "x86_64-unknown-linux-musl",
+            ),
+            (linux("x86_64", le32(), Libc::Gnu), "i686-unknown-linux-gnu"),
+            (linux("x86_64", None, Libc::Gnu), "x86_64-unknown-linux-gnu"),
+            (linux("i686", le32(), Libc::Musl), "i686-unknown-linux-musl"),
+            // the script only looks for 32-bit userland with glibc
+            (
+                linux("x86_64", le32(), Libc::Musl),
+                "x86_64-unknown-linux-musl",
+            ),
+            (
+                linux("aarch64", le64(), Libc::Gnu),
+                "aarch64-unknown-linux-gnu",
+            ),
+            (
+                linux("aarch64", hf(), Libc::Gnu),
+                "armv7-unknown-linux-gnueabihf",
+            ),
+            (
+                linux("armv7l", hf(), Libc::Gnu),
+                "armv7-unknown-linux-gnueabihf",
+            ),
+            // the script says gnueabihf whatever the userland is
+            (
+                linux("armv7l", sf(), Libc::Gnu),
+                "armv7-unknown-linux-gnueabi",
+            ),
+            (
+                linux("armv7l", None, Libc::Musl),
+                "armv7-unknown-linux-musleabihf",
+            ),
+            (raspbian_without_neon.clone(), "arm-unknown-linux-gnueabihf"),
+            // the script checks NEON only with glibc
+            (
+                Probe {
+                    libc: Libc::Musl,
+                    ..raspbian_without_neon
+                },
+                "arm-unknown-linux-musleabihf",
+            ),
+            (raspbian_with_neon, "armv7-unknown-linux-gnueabihf"),
+            (
+                linux("armv6l", hf(), Libc::Gnu),
+                "arm-unknown-linux-gnueabihf",
+            ),
+            (linux("arm", sf(), Libc::Musl), "arm-unknown-linux-musleabi"),
+            (linux("arm", None, Libc::Gnu), "arm-unknown-linux-gnu"),
+            (
+                linux("mips", elf_info(false, true, None), Libc::Gnu),
+                "mips-unknown-linux-gnu",
+            ),
+            (linux("mips", le32(), Libc::Gnu), "mipsel-unknown-linux-gnu"),
+            (
+                linux("mips64", elf_info(true, true, None), Libc::Gnu),
+                "mips64-unknown-linux-gnuabi64",
+            ),
+            (
+                linux("mips64", le64(), Libc::Gnu),
+                "mips64el-unknown-linux-gnuabi64",
+            ),
+            (
+                linux("mips64", le32(), Libc::Gnu),
+                "mipsel-unknown-linux-gnu",
+            ),
+            (
+                linux("ppc64", elf_info(false, true, None), Libc::Gnu),
+                "powerpc-unknown-linux-gnu",
+            ),
+            (
+                linux("ppc64le", le64(), Libc::Gnu),
+                "powerpc64le-unknown-linux-gnu",
+            ),
+            (
+                linux("s390x", elf_info(true, true, None), Libc::Gnu),
+                "s390x-unknown-linux-gnu",
+            ),
+            (
+                linux("riscv64", le64(), Libc::Gnu),
+                "riscv64gc-unknown-linux-gnu",
+            ),
+            (darwin("x86_64"), "x86_64-apple-darwin"),
+            (darwin("i386"), "i686-apple-darwin"),
+            (
+                Probe {
+                    darwin_x86_64: true,
+                    ..darwin("i386")
+                },
+                "x86_64-apple-darwin",
+            ),
+            (darwin("arm64"), "aarch64-apple-darwin"),
+            (android("aarch64"), "aarch64-linux-android"),
+            (android("armv7l"), "armv7-linux-androideabi"),
+        ];
+        for (probe, expected) in fixtures {
+            let triple = detect(&probe).unwrap_or_else(|e| panic!("{:?}: {}", probe, e));
+            assert_eq!(triple.to_string(), expected, "{:?}", probe);
+        }
+    }
+
+    #[test]
+    fn test_detect_errors() {
+        let riscv32 = linux("riscv64", elf_info(false, false, None), Libc::Gnu);
+        assert!(matches!(detect(&riscv32), Err(ArchError::Unsupported(_))));
+        let mips = linux("mips", None, Libc::Gnu);
+        assert!(matches!(detect(&mips), Err(ArchError::Unsupported(_))));
+        let sparc = linux("sparc64", None, Libc::Gnu);
+        assert!(matches!(detect(&sparc), Err(ArchError::UnknownCpu(_))));
+        let haiku = Probe {
+            sysname: "Haiku".to_string(),
+            ..sparc
+        };
+        assert!(matches!(detect(&haiku), Err(ArchError::UnknownOs(_))));
+    }
+
+    #[test]
+    fn test_parse_elf() {
+        let data = elf(true, false, 62, 0, "/lib64/ld-linux-x86-64.so.2");
+        let info = parse_elf(&data).unwrap();
+        assert!(info.is_64bit);
+        assert!(!info.big_endian);
+        assert_eq!(info.hard_float, None);
+        assert_eq!(
+            info.interpreter.as_deref(),
+            Some("/lib64/ld-linux-x86-64.so.2")
+        );
+
+        let data = elf(true, true, 22, 0, "/lib/ld64.so.1");
+        let info = parse_elf(&data).unwrap();
+        assert!(info.big_endian);
+        assert_eq!(info.interpreter.as_deref(), Some("/lib/ld64.so.1"));
+
+        let data = elf(false, false, 40, 0x0500_0400, "/lib/ld-linux-armhf.so.3");
+        let info = parse_elf(&data).unwrap();
+        assert!(!info.is_64bit);
+        assert_eq!(info.hard_float, Some(true));
+
+        let data = elf(false, false, 40, 0x0500_0200, "/lib/ld-linux.so.3");
+        assert_eq!(parse_elf(&data).unwrap().hard_float, Some(false));
+
+        // no float flags, fall back to interpreter name
+        let data = elf(false, false, 40, 0, "/lib/ld-musl-armhf.so.1");
+        assert_eq!(parse_elf(&data).unwrap().hard_float, Some(true));
+
+        // offsets which overflow are not followed
+        let mut data = elf(true, false, 62, 0, "/lib/ld.so");
+        data[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(parse_elf(&data).unwrap().interpreter, None);
+        let mut data = elf(true, false, 62, 0, "/lib/ld.so");
+        data[0x40 + 8..0x40 + 16].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(parse_elf(&data).unwrap().interpreter, None);
+
+        assert_eq!(parse_elf(b"#!/bin/sh\n"), None);
+        assert_eq!(parse_elf(b"\x7fELF"), None);
+    }
+
+    #[test]
+    fn test_libc_of_interpreter() {
+        assert_eq!(libc_of_interpreter("/lib/ld-musl-x86_64.so.1"), Libc::Musl);
+        assert_eq!(libc_of_interpreter("/lib/ld-musl-armhf.so.1"), Libc::Musl);
+        assert_eq!(
+            libc_of_interpreter("/lib64/ld-linux-x86-64.so.2"),
+            Libc::Gnu
+        );
+    }
+
+    /// Compare with the shell implementation, this only runs where `sh` is available
+    #[test]
+    fn test_same_as_nmkup_init_script() {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let script = include_str!("../../nmkup.nuimk.com/nmkup-init.sh")
+            .lines()
+            .take_while(|line| !line.starts_with(r##"main "$@""##))
+            .chain(std::iter::once(
+                "get_architecture || return 1\necho $RETVAL",
+            ))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let shell = Command::new("sh")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut shell = match shell {
+            Ok(shell) => shell,
+            Err(_) => return,
+        };
+        shell
+            .stdin
+            .as_mut()
+            .unwrap()
+            .write_all(script.as_bytes())
+            .unwrap();
+        let output = shell.wait_with_output().unwrap();
+        let expected = String::from_utf8(output.stdout).unwrap();
+        if expected.trim().is_empty() {
+            return;
+        }
+        let triple = detect_current_architecture().unwrap();
+        assert_eq!(triple.to_string(), expected.trim());
+    }
 }
@@ -29,7 +29,7 @@ fn should_backup_dir(dir: &Path) -> bool {
 }
 
 pub fn backup_files(nmk_home: &NmkHome, ar_path: &Path) -> io::Result<()> {
-    let mut ar = tar::Builder::new(BufWriter::new(File::create(&ar_path)?));
+    let mut ar = tar::Builder::new(BufWriter::new(File::create(ar_path)?));
     let base_dir = nmk_home.as_path();
     ar.follow_symlinks(false);
     for name in BACKUP_DIRS {
@@ -1,7 +1,109 @@
+use std::env;
+use std::fmt::{self, Display};
 use std::fs;
+use std::path::Path;
 
+use serde::Serialize;
+
+use crate::config::one_hot;
 use crate::platform;
 
+const BOOT_ID: &str = "/proc/sys/kernel/random/boot_id";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContainerKind {
+    Docker,
+    Podman,
+    Kubernetes,
+    Lxc,
+    SystemdNspawn,
+    Containerd,
+    CriO,
+    /// Something says we are in a container, but not which one
+    Unknown,
+}
+
+impl ContainerKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContainerKind::Docker => "docker",
+            ContainerKind::Podman => "podman",
+            ContainerKind::Kubernetes => "kubernetes",
+            ContainerKind::Lxc => "lxc",
+            ContainerKind::SystemdNspawn => "systemd-nspawn",
+            ContainerKind::Containerd => "containerd",
+            ContainerKind::CriO => "cri-o",
+            ContainerKind::Unknown => "unknown",
+        }
+    }
+
+    /// Parse value of `container` environment variable or `/run/systemd/container`
+    fn from_marker(s: &str) -> Option<Self> {
+        match s.trim() {
+            "" => None,
+            "docker" => Some(ContainerKind::Docker),
+            "podman" => Some(ContainerKind::Podman),
+            "lxc" | "lxc-libvirt" => Some(ContainerKind::Lxc),
+            "systemd-nspawn" => Some(ContainerKind::SystemdNspawn),
+            "crio" | "cri-o" => Some(ContainerKind::CriO),
+            _ => Some(ContainerKind::Unknown),
+        }
+    }
+}
+
+impl Display for ContainerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Which signal the detection comes from, in the order they are checked
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Source {
+    /// `container` variable of pid 1 or ourself, `KUBERNETES_SERVICE_HOST`
+    Environment,
+    /// `/run/.containerenv`, `/.dockerenv` or `/run/systemd/container`
+    MarkerFile,
+    Cgroup,
+    MountInfo,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Confidence {
+    High,
+    Medium,
+    Low,
+}
+
+impl Source {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Source::Environment => "environment",
+            Source::MarkerFile => "marker-file",
+            Source::Cgroup => "cgroup",
+            Source::MountInfo => "mountinfo",
+        }
+    }
+
+    /// Runtimes set markers on purpose, paths in cgroup and mountinfo are only a convention
+    pub fn confidence(self) -> Confidence {
+        match self {
+            Source::Environment | Source::MarkerFile => Confidence::High,
+            Source::Cgroup => Confidence::Medium,
+            Source::MountInfo => Confidence::Low,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Container {
+    pub kind: ContainerKind,
+    pub source: Source,
+}
+
 struct CGroup<'a> {
     control_group: &'a str,
     #[allow(dead_code)]
@@ -14,7 +116,7 @@ impl<'a> CGroup<'a> {
     pub const SEPARATOR: char = ':';
 
     pub fn parse(line: &'a str) -> Option<Self> {
-        let mut iter = line.split(Self::SEPARATOR);
+        let mut iter = line.splitn(3, Self::SEPARATOR);
         let (hierarchy_id, subsystems, control_group) = (iter.next()?, iter.next()?, iter.next()?);
         Some(Self {
             control_group,
@@ -23,30 +125,197 @@ impl<'a> CGroup<'a> {
         })
     }
 
-    pub fn is_container(&self) -> bool {
+    /// Works with both cgroup v1 and v2 (`0::/...`) lines
+    pub fn container_kind(&self) -> Option<ContainerKind> {
         let cgroup = self.control_group;
-        cgroup.starts_with("/docker") || cgroup.starts_with("/kube")
+        // Order matters, kubernetes paths contain runtime names too
+        if cgroup.contains("/kubepods") {
+            Some(ContainerKind::Kubernetes)
+        } else if cgroup.contains("libpod") {
+            Some(ContainerKind::Podman)
+        } else if cgroup.starts_with("/docker") || cgroup.contains("/docker-") {
+            Some(ContainerKind::Docker)
+        } else if cgroup.contains("crio-") {
+            Some(ContainerKind::CriO)
+        } else if cgroup.contains("containerd") {
+            Some(ContainerKind::Containerd)
+        } else if cgroup.starts_with("/lxc/") || cgroup.starts_with("/lxc.payload") {
+            Some(ContainerKind::Lxc)
+        } else if cgroup.starts_with("/machine.slice/machine-") {
+            Some(ContainerKind::SystemdNspawn)
+        } else {
+            None
+        }
     }
 }
 
-fn is_container(s: &str) -> bool {
+fn container_of_cgroup(s: &str) -> Option<ContainerKind> {
     s.lines()
         .flat_map(CGroup::parse)
-        .any(|cg| cg.is_container())
+        .find_map(|cg| cg.container_kind())
 }
 
-pub fn is_containerized() -> bool {
+/// Line of `/proc/self/mountinfo`, the number of optional fields before ` - ` varies
+struct Mount<'a> {
+    root: &'a str,
+    mount_point: &'a str,
+    source: &'a str,
+}
+
+impl<'a> Mount<'a> {
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let (fields, fs_fields) = line.split_once(" - ")?;
+        let mut fields = fields.split(' ');
+        let (root, mount_point) = (fields.nth(3)?, fields.next()?);
+        let source = fs_fields.split(' ').nth(1)?;
+        Some(Self {
+            root,
+            mount_point,
+            source,
+        })
+    }
+}
+
+/// Look for container storage in roots and sources of mounts which a runtime sets up for the
+/// container, e.g. `/etc/hostname` bind mount
+///
+/// Other mounts are skipped, a host which runs containers lists their storage too.
+fn container_of_mountinfo(s: &str) -> Option<ContainerKind> {
+    const MOUNT_POINTS: &[&str] = &["/", "/etc/hostname", "/etc/hosts", "/etc/resolv.conf"];
+    const PATTERNS: &[(&str, ContainerKind)] = &[
+        ("/var/lib/kubelet/", ContainerKind::Kubernetes),
+        ("/var/lib/docker/", ContainerKind::Docker),
+        ("/var/lib/containers/storage/", ContainerKind::Podman),
+        (
+            "/containers/storage/overlay-containers/",
+            ContainerKind::Podman,
+        ),
+        ("/var/lib/containerd/", ContainerKind::Containerd),
+        ("/run/containerd/", ContainerKind::Containerd),
+        ("/var/lib/lxc/", ContainerKind::Lxc),
+        ("/var/lib/lxd/", ContainerKind::Lxc),
+        ("/var/lib/machines/", ContainerKind::SystemdNspawn),
+    ];
+    let mounts: Vec<_> = s
+        .lines()
+        .flat_map(Mount::parse)
+        .filter(|m| MOUNT_POINTS.contains(&m.mount_point))
+        .collect();
+    PATTERNS.iter().find_map(|(pattern, kind)| {
+        mounts
+            .iter()
+            .any(|m| m.root.contains(pattern) || m.source.contains(pattern))
+            .then_some(*kind)
+    })
+}
+
+/// Raw inputs of the detection, collected without failing so detection can never panic
+#[derive(Default)]
+struct Signals {
+    env_container: Option<String>,
+    pid1_container: Option<String>,
+    kubernetes: bool,
+    containerenv: bool,
+    dockerenv: bool,
+    systemd_container: Option<String>,
+    cgroup: Option<String>,
+    mountinfo: Option<String>,
+}
+
+impl Signals {
+    fn collect() -> Self {
+        let exists = |s: &str| std::path::Path::new(s).exists();
+        Self {
+            env_container: env::var("container").ok(),
+            pid1_container: fs::read("/proc/1/environ")
+                .ok()
+                .and_then(|environ| pid1_container(&environ)),
+            kubernetes: env::var_os("KUBERNETES_SERVICE_HOST").is_some(),
+            containerenv: exists("/run/.containerenv"),
+            dockerenv: exists("/.dockerenv"),
+            systemd_container: fs::read_to_string("/run/systemd/container").ok(),
+            cgroup: fs::read_to_string("/proc/self/cgroup").ok(),
+            mountinfo: fs::read_to_string("/proc/self/mountinfo").ok(),
+        }
+    }
+
+    fn detect(&self) -> Option<Container> {
+        let found = |kind, source| Some(Container { kind, source });
+        let marker = self.pid1_container.as_ref().or(self.env_container.as_ref());
+        if self.kubernetes {
+            return found(ContainerKind::Kubernetes, Source::Environment);
+        }
+        if let Some(kind) = marker.and_then(|s| ContainerKind::from_marker(s)) {
+            return found(kind, Source::Environment);
+        }
+        if self.containerenv {
+            return found(ContainerKind::Podman, Source::MarkerFile);
+        }
+        if self.dockerenv {
+            return found(ContainerKind::Docker, Source::MarkerFile);
+        }
+        let systemd_container = self.systemd_container.as_ref();
+        if let Some(kind) = systemd_container.and_then(|s| ContainerKind::from_marker(s)) {
+            return found(kind, Source::MarkerFile);
+        }
+        if let Some(kind) = self.cgroup.as_deref().and_then(container_of_cgroup) {
+            return found(kind, Source::Cgroup);
+        }
+        if let Some(kind) = self.mountinfo.as_deref().and_then(container_of_mountinfo) {
+            return found(kind, Source::MountInfo);
+        }
+        None
+    }
+}
+
+fn pid1_container(environ: &[u8]) -> Option<String> {
+    environ
+        .split(|b| *b == 0)
+        .find_map(|var| var.strip_prefix(b"container="))
+        .map(|value| String::from_utf8_lossy(value).into_owned())
+}
+
+/// Detect container runtime we are running in, `None` if we are not in a container
+pub fn detect() -> Option<Container> {
     if platform::is_mac() {
-        return false;
+        return None;
+    }
+    Signals::collect().detect()
+}
+
+pub fn is_containerized() -> bool {
+    detect().is_some()
+}
+
+/// `is_containerized` remembered in `dir` until reboot, so login doesn't read /proc every time
+pub fn is_containerized_cached(dir: &Path) -> bool {
+    match fs::read_to_string(BOOT_ID) {
+        Ok(boot_id) => cached(&dir.join("container"), boot_id.trim(), is_containerized),
+        Err(_) => is_containerized(),
+    }
+}
+
+/// Cache file holds boot id and the answer, like `<boot id> 1`
+fn cached<F: FnOnce() -> bool>(path: &Path, boot_id: &str, detect: F) -> bool {
+    let cached = fs::read_to_string(path).ok().and_then(|data| {
+        let (id, value) = data.trim().split_once(' ')?;
+        (id == boot_id).then(|| value == one_hot(true))
+    });
+    if let Some(containerized) = cached {
+        return containerized;
+    }
+    let containerized = detect();
+    let data = format!("{} {}\n", boot_id, one_hot(containerized));
+    if let Err(e) = fs::write(path, data) {
+        log::debug!("Failed to write {:?}: {}", path, e);
     }
-    let self_cgroup = format!("/proc/{}/cgroup", std::process::id());
-    let contents = fs::read_to_string(self_cgroup).expect("cannot read self cgroup");
-    is_container(&contents)
+    containerized
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::TempDir;
 
     #[test]
     fn test_cgroup_parse() {
@@ -62,20 +331,193 @@ mod tests {
     }
 
     #[test]
-    fn test_is_container() {
+    fn test_container_of_cgroup() {
         let docker_cgroup = r#"
 12:cpu,cpuacct:/docker/c6fa62a9938149f6098fd0cdaffc9cdf0f526f25d97b5f6e2a4cc1fccc7f7ce1
 11:perf_event:/docker/c6fa62a9938149f6098fd0cdaffc9cdf0f526f25d97b5f6e2a4cc1fccc7f7ce1
 10:rdma:/"#;
-        assert!(is_container(docker_cgroup));
+        assert_eq!(
+            container_of_cgroup(docker_cgroup),
+            Some(ContainerKind::Docker)
+        );
         let init_cgroup = r#"
 12:cpu,cpuacct:/
 11:perf_event:/
 0::/init.scope"#;
-        assert!(!is_container(init_cgroup));
+        assert_eq!(container_of_cgroup(init_cgroup), None);
         let k8s_cgroup = r#"
 12:hugetlb:/kubepods/besteffort/poda00e29fd-7bbd-11e9-8679-fa163ea7e3b8/c4b1403f3d9c7ce261be851df71d9a9773c53419075ccda39ae8fe6a39fd2eb1
 11:cpuset:/kubepods/besteffort/poda00e29fd-7bbd-11e9-8679-fa163ea7e3b8/c4b1403f3d9c7ce261be851df71d9a9773c53419075ccda39ae8fe6a39fd2eb1"#;
-        assert!(is_container(k8s_cgroup));
+        assert_eq!(
+            container_of_cgroup(k8s_cgroup),
+            Some(ContainerKind::Kubernetes)
+        );
+        let cases = [
+            (
+                "0::/system.slice/docker-4f1d6e3b2a.scope",
+                Some(ContainerKind::Docker),
+            ),
+            (
+                "0::/user.slice/user-1000.slice/user@1000.service/user.slice/libpod-2b8f.scope/container",
+                Some(ContainerKind::Podman),
+            ),
+            ("0::/lxc.payload.ubuntu/init.scope", Some(ContainerKind::Lxc)),
+            ("4:memory:/lxc/ubuntu", Some(ContainerKind::Lxc)),
+            (
+                "0::/machine.slice/machine-debian.scope/payload",
+                Some(ContainerKind::SystemdNspawn),
+            ),
+            (
+                "0::/system.slice/cri-containerd-7a3c.scope",
+                Some(ContainerKind::Containerd),
+            ),
+            ("0::/system.slice/crio-9e1d.scope", Some(ContainerKind::CriO)),
+            // cgroup namespace hides the path, this alone tells nothing
+            ("0::/", None),
+            ("", None),
+            ("garbage", None),
+        ];
+        for (cgroup, expected) in IntoIterator::into_iter(cases) {
+            assert_eq!(container_of_cgroup(cgroup), expected, "{}", cgroup);
+        }
+    }
+
+    #[test]
+    fn test_container_of_mountinfo() {
+        let docker = "\
+1276 1107 0:118 / / rw,relatime master:532 - overlay overlay rw,lowerdir=/var/lib/docker/overlay2/l/ABC
+1285 1276 259:2 /var/lib/docker/containers/7e0f/hostname /etc/hostname rw,relatime - ext4 /dev/nvme0n1p2 rw";
+        assert_eq!(container_of_mountinfo(docker), Some(ContainerKind::Docker));
+        let podman = "\
+612 540 0:50 / / rw,relatime - overlay overlay rw,lowerdir=/home/u/.local/share/containers/storage/overlay/l/X
+630 612 0:25 /containers/storage/overlay-containers/9a/userdata/hostname /etc/hostname rw - tmpfs tmpfs rw";
+        assert_eq!(container_of_mountinfo(podman), Some(ContainerKind::Podman));
+        let kubernetes = "\
+2001 1900 259:1 /var/lib/kubelet/pods/0a1b/etc-hosts /etc/hosts rw,relatime - ext4 /dev/sda1 rw
+2002 1900 259:1 /var/lib/containerd/io.containerd.grpc.v1.cri/sandboxes/5c/hostname /etc/hostname rw - ext4 /dev/sda1 rw";
+        assert_eq!(
+            container_of_mountinfo(kubernetes),
+            Some(ContainerKind::Kubernetes)
+        );
+        let host = "\
+22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw
+23 22 0:21 / /proc rw,nosuid,nodev,noexec,relatime shared:12 - proc proc rw";
+        assert_eq!(container_of_mountinfo(host), None);
+        // storage of containers running on the host is not ours
+        let host = include_str!("fixtures/mountinfo/host-with-containers");
+        assert_eq!(container_of_mountinfo(host), None);
+    }
+
+    #[test]
+    fn test_detect_signals() {
+        let detect = |signals: Signals| signals.detect().map(|c| (c.kind, c.source));
+        assert_eq!(detect(Signals::default()), None);
+        assert_eq!(
+            detect(Signals {
+                env_container: Some("podman".to_string()),
+                containerenv: true,
+                ..Default::default()
+            }),
+            Some((ContainerKind::Podman, Source::Environment))
+        );
+        assert_eq!(
+            detect(Signals {
+                pid1_container: Some("systemd-nspawn".to_string()),
+                ..Default::default()
+            }),
+            Some((ContainerKind::SystemdNspawn, Source::Environment))
+        );
+        assert_eq!(
+            detect(Signals {
+                pid1_container: Some("lxc".to_string()),
+                cgroup: Some("0::/".to_string()),
+                ..Default::default()
+            }),
+            Some((ContainerKind::Lxc, Source::Environment))
+        );
+        assert_eq!(
+            detect(Signals {
+                kubernetes: true,
+                dockerenv: true,
+                ..Default::default()
+            }),
+            Some((ContainerKind::Kubernetes, Source::Environment))
+        );
+        assert_eq!(
+            detect(Signals {
+                containerenv: true,
+                ..Default::default()
+            }),
+            Some((ContainerKind::Podman, Source::MarkerFile))
+        );
+        assert_eq!(
+            detect(Signals {
+                dockerenv: true,
+                cgroup: Some("0::/".to_string()),
+                ..Default::default()
+            }),
+            Some((ContainerKind::Docker, Source::MarkerFile))
+        );
+        assert_eq!(
+            detect(Signals {
+                systemd_container: Some("systemd-nspawn\n".to_string()),
+                ..Default::default()
+            }),
+            Some((ContainerKind::SystemdNspawn, Source::MarkerFile))
+        );
+        assert_eq!(
+            detect(Signals {
+                cgroup: Some("0::/system.slice/crio-9e1d.scope".to_string()),
+                ..Default::default()
+            }),
+            Some((ContainerKind::CriO, Source::Cgroup))
+        );
+        assert_eq!(
+            detect(Signals {
+                cgroup: Some("0::/".to_string()),
+                mountinfo: Some(
+                    "2 1 8:1 /var/lib/containerd/x/hostname /etc/hostname rw - ext4 /dev/sda1 rw"
+                        .to_string()
+                ),
+                ..Default::default()
+            }),
+            Some((ContainerKind::Containerd, Source::MountInfo))
+        );
+        assert_eq!(
+            detect(Signals {
+                env_container: Some("oci".to_string()),
+                ..Default::default()
+            }),
+            Some((ContainerKind::Unknown, Source::Environment))
+        );
+    }
+
+    #[test]
+    fn test_pid1_container() {
+        let environ = b"PATH=/usr/bin\0container=podman\0HOME=/\0";
+        assert_eq!(pid1_container(environ), Some("podman".to_string()));
+        assert_eq!(pid1_container(b"PATH=/usr/bin\0"), None);
+        assert_eq!(pid1_container(b""), None);
+    }
+
+    #[test]
+    fn test_source_confidence() {
+        assert_eq!(Source::Environment.confidence(), Confidence::High);
+        assert_eq!(Source::MarkerFile.confidence(), Confidence::High);
+        assert_eq!(Source::Cgroup.confidence(), Confidence::Medium);
+        assert_eq!(Source::MountInfo.confidence(), Confidence::Low);
+    }
+
+    #[test]
+    fn test_cached() {
+        let dir = TempDir::new("container");
+        let path = dir.join("container");
+        assert!(cached(&path, "boot-1", || true));
+        assert!(cached(&path, "boot-1", || panic!("detected again")));
+        // reboot
+        assert!(!cached(&path, "boot-2", || false));
+        assert!(!cached(&path, "boot-2", || panic!("detected again")));
+        fs::write(&path, "garbage").unwrap();
+        assert!(cached(&path, "boot-2", || true));
     }
 }
@@ -8,8 +8,12 @@ macro_rules! declare {
 declare!(EDITOR);
 declare!(LD_LIBRARY_PATH);
 declare!(NMK_BIN);
+declare!(NMK_CLIPBOARD);
 declare!(NMK_HOME);
+declare!(NMK_SYSTEM_HOME);
+declare!(NMK_THEME);
 declare!(NMK_TMUX_VERSION);
+declare!(NMK_UPDATE_SUGGEST_AFTER);
 declare!(NMK_ZSH_GLOBAL_RCS);
 declare!(PATH);
 declare!(VIMINIT);
@@ -1,62 +1,259 @@
 use std::fmt::{self, Debug, Display};
+use std::panic::Location;
 
+/// Broad category of an error, this is what callers should match on
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorKind {
+    Io,
+    Network,
+    Parse,
+    Unsupported,
+    /// Downloaded or installed data is not what we expect
+    Integrity,
+    Other,
+}
+
+/// User rendering (`{}`) shows only the outermost message, `{:#}` shows the whole cause chain.
+/// Debug rendering (`{:?}`) adds error kind and source locations, it's meant for `-v`.
 pub struct Error {
     /// This `Box` allows us to keep the size of `Error` as small as possible
     err: Box<ErrorImpl>,
 }
 
 struct ErrorImpl {
+    kind: ErrorKind,
+    /// Message attached by `Context`, `error` is an `Error` in this case
+    context: Option<String>,
     error: Box<dyn std::error::Error>,
     tag: &'static str,
-    caller: std::panic::Location<'static>,
+    caller: Location<'static>,
+}
+
+/// Plain message as an error
+#[derive(Debug)]
+struct Message(String);
+
+impl Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
 }
 
+impl std::error::Error for Message {}
+
 impl Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let err = self.err.as_ref();
-        write!(
-            f,
-            "{} at {}:{}:{} {:?}",
-            err.tag,
-            err.caller.file(),
-            err.caller.line(),
-            err.caller.column(),
-            err.error
-        )
+        write!(f, "{:?}: {}", self.kind(), self)?;
+        let mut current = self;
+        loop {
+            let err = current.err.as_ref();
+            write!(
+                f,
+                "\n    at {}:{}:{} ({})",
+                err.caller.file(),
+                err.caller.line(),
+                err.caller.column(),
+                err.tag
+            )?;
+            match current.inner() {
+                Some(inner) => {
+                    write!(f, "\ncaused by: {}", inner)?;
+                    current = inner;
+                }
+                None => break,
+            }
+        }
+        let mut source = std::error::Error::source(current);
+        while let Some(err) = source {
+            write!(f, "\ncaused by: {}", err)?;
+            source = err.source();
+        }
+        Ok(())
     }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let err = self.err.as_ref();
-        write!(
-            f,
-            "{} at {}:{}:{} {}",
-            err.tag,
-            err.caller.file(),
-            err.caller.line(),
-            err.caller.column(),
-            err.error
-        )
+        match err.context {
+            Some(ref context) => f.write_str(context)?,
+            None => Display::fmt(&err.error, f)?,
+        }
+        if f.alternate() {
+            let mut source = std::error::Error::source(self);
+            while let Some(err) = source {
+                write!(f, ": {}", err)?;
+                source = err.source();
+            }
+        }
+        Ok(())
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        let err = self.err.as_ref();
+        if err.context.is_some() {
+            Some(err.error.as_ref())
+        } else {
+            // Our Display already shows the wrapped error
+            err.error.source()
+        }
+    }
+}
 
 impl Error {
     pub(crate) fn new(
+        kind: ErrorKind,
         error: Box<dyn std::error::Error>,
         tag: &'static str,
-        caller: std::panic::Location<'static>,
+        caller: Location<'static>,
     ) -> Self {
         Self {
-            err: Box::new(ErrorImpl { error, tag, caller }),
+            err: Box::new(ErrorImpl {
+                kind,
+                context: None,
+                error,
+                tag,
+                caller,
+            }),
+        }
+    }
+
+    /// Create an error from a message
+    #[track_caller]
+    pub fn msg(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self::new(
+            kind,
+            Box::new(Message(message.into())),
+            "message",
+            *Location::caller(),
+        )
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.err.kind
+    }
+
+    #[track_caller]
+    fn wrap(self, context: String) -> Self {
+        Self {
+            err: Box::new(ErrorImpl {
+                kind: self.kind(),
+                context: Some(context),
+                error: Box::new(self),
+                tag: "context",
+                caller: *Location::caller(),
+            }),
+        }
+    }
+
+    /// Error wrapped by `Context`
+    fn inner(&self) -> Option<&Error> {
+        let err = self.err.as_ref();
+        err.context.as_ref()?;
+        err.error.downcast_ref::<Error>()
+    }
+
+    /// Render for end users, location and kind are only shown when `verbose`
+    pub fn report(&self, verbose: bool) -> String {
+        if verbose {
+            format!("{:?}", self)
+        } else {
+            format!("{:#}", self)
+        }
+    }
+}
+
+/// Attach a message describing what we were doing when the error happened
+pub trait Context<T> {
+    fn context<C: Display>(self, context: C) -> crate::Result<T>;
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> crate::Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for std::result::Result<T, E> {
+    #[track_caller]
+    fn context<C: Display>(self, context: C) -> crate::Result<T> {
+        match self {
+            Ok(v) => Ok(v),
+            Err(e) => Err(e.into().wrap(context.to_string())),
+        }
+    }
+
+    #[track_caller]
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> crate::Result<T> {
+        match self {
+            Ok(v) => Ok(v),
+            Err(e) => Err(e.into().wrap(f().to_string())),
         }
     }
 }
 
-impl_from_error!(reqwest::Error);
-impl_from_error!(serde_json::Error);
-impl_from_error!(std::io::Error);
-impl_from_error!(std::str::Utf8Error);
-impl_from_error!(toml::ser::Error);
+impl_from_error!(crate::arch::ArchError, Unsupported);
+#[cfg(feature = "network")]
+impl_from_error!(reqwest::Error, Network);
+impl_from_error!(serde_json::Error, Parse);
+impl_from_error!(crate::human_time::ParseHumanTimeError, Parse);
+impl_from_error!(std::io::Error, Io);
+impl_from_error!(crate::terminfo::TerminfoError, Parse);
+impl_from_error!(std::str::Utf8Error, Parse);
+impl_from_error!(toml::de::Error, Parse);
+impl_from_error!(toml::ser::Error, Other);
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    fn read_config() -> crate::Result<()> {
+        let err = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        Err(err).context("failed to read config")
+    }
+
+    #[test]
+    fn test_kind() {
+        let err: Error = io::Error::other("boom").into();
+        assert_eq!(err.kind(), ErrorKind::Io);
+        let err = serde_json::from_str::<u8>("x").unwrap_err();
+        assert_eq!(Error::from(err).kind(), ErrorKind::Parse);
+        let err = Error::msg(ErrorKind::Integrity, "size mismatch");
+        assert_eq!(err.kind(), ErrorKind::Integrity);
+        assert_eq!(err.to_string(), "size mismatch");
+    }
+
+    #[test]
+    fn test_context() {
+        let err = read_config()
+            .with_context(|| format!("failed to start {}", "nmk"))
+            .unwrap_err();
+        // context keeps kind of the cause
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(err.to_string(), "failed to start nmk");
+        assert_eq!(
+            format!("{:#}", err),
+            "failed to start nmk: failed to read config: no such file"
+        );
+
+        let source = std::error::Error::source(&err).unwrap();
+        assert_eq!(source.to_string(), "failed to read config");
+        let source = source.source().unwrap();
+        assert_eq!(source.to_string(), "no such file");
+        assert!(source.source().is_none());
+    }
+
+    #[test]
+    fn test_report() {
+        let err = read_config().unwrap_err();
+        let user = err.report(false);
+        assert_eq!(user, "failed to read config: no such file");
+        assert!(!user.contains("error.rs"));
+
+        let debug = err.report(true);
+        assert!(debug.starts_with("Io: failed to read config"));
+        assert!(debug.contains("caused by: no such file"));
+        assert!(debug.contains(file!()));
+        assert!(debug.contains("std::io::Error"));
+    }
+}
@@ -1,4 +1,4 @@
-use std::fmt::{self, Debug, Display};
+use std::fmt::{self, Display};
 use std::fs;
 use std::path::Path;
 
@@ -6,6 +6,8 @@ use bytes::Bytes;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
+use crate::error::{Error, ErrorKind};
+
 const GET_OBJECT_BASE_URL: &str = "https://www.googleapis.com/storage/v1/b/nmk.nuimk.com/o";
 
 #[derive(Deserialize)]
@@ -14,7 +16,7 @@ pub struct ListObjectResponse {
     pub items: Vec<ObjectMeta>,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ObjectMeta {
     pub id: String,
@@ -34,6 +36,23 @@ impl ObjectMeta {
         fs::write(path, json_data).expect("failed to write ObjectMeta to file");
     }
 
+    /// Check downloaded data against object size, this catches truncated downloads
+    pub fn verify_size(&self, data: &[u8]) -> crate::Result<()> {
+        if self.size.parse::<usize>().ok() == Some(data.len()) {
+            Ok(())
+        } else {
+            Err(Error::msg(
+                ErrorKind::Integrity,
+                format!(
+                    "{} has {} bytes, expected {} bytes",
+                    self.name,
+                    data.len(),
+                    self.size
+                ),
+            ))
+        }
+    }
+
     pub fn read_from_file(path: &Path) -> Self {
         let json_data = fs::read(path).expect("failed to read ObjectMeta from file");
         serde_json::from_slice(&json_data).expect("failed to deserialize ObjectMeta")
@@ -47,14 +66,15 @@ enum GcsError {
 
 impl Display for GcsError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // This Debug is intentional
-        Debug::fmt(self, f)
+        match self {
+            GcsError::HttpError { status, url } => write!(f, "HTTP {} from {}", status, url),
+        }
     }
 }
 
 impl std::error::Error for GcsError {}
 
-impl_from_error!(GcsError);
+impl_from_error!(GcsError, Network);
 
 pub async fn download_file(client: &Client, media_link: &str) -> crate::Result<Bytes> {
     let response = client.get(media_link).send().await?;
@@ -102,3 +122,34 @@ pub async fn list_objects(client: &Client, url: &str) -> crate::Result<Vec<Objec
     let list_result = serde_json::from_slice::<ListObjectResponse>(&data)?;
     Ok(list_result.items)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(size: &str) -> ObjectMeta {
+        ObjectMeta {
+            id: String::new(),
+            self_link: String::new(),
+            media_link: String::new(),
+            name: "nmk.tar.xz".to_string(),
+            generation: "1".to_string(),
+            size: size.to_string(),
+            md5_hash: String::new(),
+            etag: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_verify_size() {
+        assert!(meta("4").verify_size(b"data").is_ok());
+        assert!(meta("0").verify_size(b"").is_ok());
+
+        let err = meta("5").verify_size(b"data").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Integrity);
+        assert_eq!(err.to_string(), "nmk.tar.xz has 4 bytes, expected 5 bytes");
+        // metadata we can't make sense of doesn't let anything through
+        assert!(meta("").verify_size(b"").is_err());
+        assert!(meta("-4").verify_size(b"data").is_err());
+    }
+}
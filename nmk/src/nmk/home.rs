@@ -1,18 +1,31 @@
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
-use std::{env, fmt};
+use std::{env, fmt, iter};
 
 use dirs::home_dir;
 
-use crate::env_name::NMK_HOME;
+use crate::env_name::{NMK_HOME, NMK_SYSTEM_HOME};
+
+const DEFAULT_SYSTEM_HOME: &str = "/opt/nmk";
 
 /// Home directory of dotfiles
+///
+/// On shared servers, dotfiles and vendor files can be installed once in a system-wide root.
+/// In that case the personal home only keeps histories and extras, and lookups fall back to
+/// the system root.
 #[derive(Clone)]
-pub struct NmkHome(PathBuf);
+pub struct NmkHome {
+    path: PathBuf,
+    system: Option<PathBuf>,
+}
 
 impl fmt::Debug for NmkHome {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(&self.0, f)
+        fmt::Debug::fmt(&self.path, f)?;
+        if let Some(ref system) = self.system {
+            write!(f, " over {:?}", system)?;
+        }
+        Ok(())
     }
 }
 
@@ -26,9 +39,15 @@ fn find_path_from_env() -> Option<PathBuf> {
         .map(PathBuf::from)
 }
 
+fn find_system_path() -> PathBuf {
+    env::var_os(NMK_SYSTEM_HOME)
+        .filter(|v| !v.is_empty())
+        .map_or_else(|| PathBuf::from(DEFAULT_SYSTEM_HOME), PathBuf::from)
+}
+
 impl NmkHome {
     pub fn is_git(&self) -> bool {
-        self.0.join(".git").exists()
+        self.path.join(".git").exists()
     }
 
     /// Attempt to locate correct NMK_HOME candidate
@@ -38,12 +57,21 @@ impl NmkHome {
     /// Canonicalization is necessary because we use this value in vendored zsh which required
     /// absolute path.
     /// If path doesn't exist, return None
+    ///
+    /// If system-wide root ($NMK_SYSTEM_HOME or /opt/nmk) exists, it is layered under personal
+    /// home, and personal home is allowed to not exist yet.
     pub fn locate() -> Option<Self> {
-        find_path_from_env()
+        let path = find_path_from_env()
             .and_then(|p| p.canonicalize().ok())
-            .or_else(find_path_from_homedir)
-            .filter(|p| p.exists())
-            .map(Self::from)
+            .or_else(find_path_from_homedir)?;
+        let system = find_system_path()
+            .canonicalize()
+            .ok()
+            .filter(|system| *system != path);
+        if !path.exists() && system.is_none() {
+            return None;
+        }
+        Some(Self { path, system })
     }
 
     pub fn find_for_install() -> Option<Self> {
@@ -52,42 +80,93 @@ impl NmkHome {
             .map(Self::from)
     }
 
-    pub fn nmk_path(&self) -> NmkPath {
-        NmkPath(self.0.as_path())
+    /// System-wide root, which is managed by root
+    pub fn find_system_for_install() -> Self {
+        Self::from(find_system_path())
+    }
+
+    pub fn nmk_path(&self) -> NmkPath<'_> {
+        NmkPath {
+            path: self.path.as_path(),
+            system: self.system.as_deref(),
+        }
+    }
+
+    /// Personal home then system-wide root
+    pub fn layers(&self) -> impl Iterator<Item = NmkPath<'_>> {
+        iter::once(self.path.as_path())
+            .chain(self.system.as_deref())
+            .map(|path| NmkPath { path, system: None })
+    }
+
+    pub fn system(&self) -> Option<&Path> {
+        self.system.as_deref()
+    }
+
+    /// Binaries come from system-wide root, which only its administrator can update
+    pub fn is_system_install(&self) -> bool {
+        self.system()
+            .is_some_and(|system| self.nmk_path().bin().starts_with(system))
     }
 
     pub fn as_path(&self) -> &Path {
-        self.0.as_path()
+        self.path.as_path()
     }
 }
 
 impl From<PathBuf> for NmkHome {
-    fn from(inner: PathBuf) -> Self {
-        Self(inner)
+    fn from(path: PathBuf) -> Self {
+        Self { path, system: None }
     }
 }
 
 impl AsRef<Path> for NmkHome {
     fn as_ref(&self) -> &Path {
-        self.0.as_ref()
+        self.path.as_ref()
     }
 }
 
 impl AsRef<OsStr> for NmkHome {
     fn as_ref(&self) -> &OsStr {
-        self.0.as_os_str()
+        self.path.as_os_str()
     }
 }
 
-pub struct NmkPath<'a>(&'a Path);
+/// Paths inside NmkHome, resolved from personal home first then system-wide root
+pub struct NmkPath<'a> {
+    path: &'a Path,
+    system: Option<&'a Path>,
+}
 
 impl<'a> NmkPath<'a> {
+    /// Resolve `name` to personal home if it exists (or `marker` exists inside it), otherwise to
+    /// system-wide root if it has `name`.
+    fn resolve(&self, name: &str, marker: &str) -> PathBuf {
+        let path = self.path.join(name);
+        let overridden = if marker.is_empty() {
+            path.exists()
+        } else {
+            path.join(marker).exists()
+        };
+        match self.system {
+            Some(system) if !overridden => {
+                let system_path = system.join(name);
+                if system_path.exists() {
+                    system_path
+                } else {
+                    path
+                }
+            }
+            _ => path,
+        }
+    }
+
     pub fn bin(&self) -> PathBuf {
-        self.0.join("bin")
+        self.resolve("bin", "")
     }
 
     pub fn vendor(&self) -> PathBuf {
-        self.0.join("vendor")
+        self.resolve("vendor", "")
     }
 
     pub fn vendor_bin(&self) -> PathBuf {
@@ -98,11 +177,114 @@ impl<'a> NmkPath<'a> {
         self.vendor().join("lib")
     }
 
+    /// Personal zsh directory only overrides system one if it has its own .zshrc,
+    /// otherwise it just keeps histories and extras.
     pub fn zsh(&self) -> PathBuf {
-        self.0.join("zsh")
+        self.resolve("zsh", ".zshrc")
     }
 
     pub fn vim(&self) -> PathBuf {
-        self.0.join("vim")
+        self.resolve("vim", "init.vim")
+    }
+
+    /// Personal zsh directory, which keeps histories and extras
+    pub fn zsh_personal(&self) -> PathBuf {
+        self.path.join("zsh")
+    }
+
+    /// Metadata of installed dotfiles
+    pub fn dotfiles_meta(&self) -> PathBuf {
+        self.resolve(".dotfiles.meta", "")
+    }
+
+    /// Metadata of installed entrypoint
+    pub fn entrypoint_meta(&self) -> PathBuf {
+        self.resolve(".nmk.meta", "")
+    }
+
+    /// Remote metadata cached for update notice
+    pub fn update_cache(&self) -> PathBuf {
+        self.path.join(".update.cache")
+    }
+
+    /// User tmux configuration overlays, see `tmux::overlay`
+    pub fn tmux_overlays(&self) -> PathBuf {
+        self.path.join("tmux.d")
+    }
+
+    /// User key bindings, see `tmux::keybinding`
+    pub fn tmux_key_bindings(&self) -> PathBuf {
+        self.path.join("tmux-keys.toml")
+    }
+
+    /// User colour themes, see `tmux::theme`
+    pub fn themes(&self) -> PathBuf {
+        self.path.join("themes")
+    }
+
+    /// Session layouts for `nmk open`, see `tmux::workspace`
+    pub fn workspaces(&self) -> PathBuf {
+        self.path.join("workspaces")
+    }
+
+    /// User settings, see `Settings`
+    pub fn settings(&self) -> PathBuf {
+        self.path.join("nmk.toml")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_resolve_user_first_then_system() {
+        let user = TempDir::new("home-user");
+        let system = TempDir::new("home-system");
+        for dir in &["bin", "vendor/bin", "zsh", "vim"] {
+            fs::create_dir_all(system.join(dir)).unwrap();
+        }
+        fs::write(system.join("zsh/.zshrc"), "").unwrap();
+        // personal zsh directory with only histories
+        fs::create_dir_all(user.join("zsh")).unwrap();
+        // personal vim overrides system one
+        fs::create_dir_all(user.join("vim")).unwrap();
+        fs::write(user.join("vim/init.vim"), "").unwrap();
+
+        let home = NmkHome {
+            path: user.to_path_buf(),
+            system: Some(system.to_path_buf()),
+        };
+        let nmk_path = home.nmk_path();
+        assert_eq!(nmk_path.bin(), system.join("bin"));
+        assert_eq!(nmk_path.vendor_bin(), system.join("vendor/bin"));
+        assert_eq!(nmk_path.zsh(), system.join("zsh"));
+        assert_eq!(nmk_path.zsh_personal(), user.join("zsh"));
+        assert_eq!(nmk_path.vim(), user.join("vim"));
+        // written by the user, never taken from system home
+        fs::write(system.join("nmk.toml"), "").unwrap();
+        fs::write(system.join(".update.cache"), "").unwrap();
+        assert_eq!(nmk_path.settings(), user.join("nmk.toml"));
+        assert_eq!(nmk_path.update_cache(), user.join(".update.cache"));
+        assert!(home.is_system_install());
+
+        fs::create_dir_all(user.join("bin")).unwrap();
+        assert_eq!(nmk_path.bin(), user.join("bin"));
+        assert!(!home.is_system_install());
+
+        let layers: Vec<_> = home.layers().map(|p| p.bin()).collect();
+        assert_eq!(layers, vec![user.join("bin"), system.join("bin")]);
+    }
+
+    #[test]
+    fn test_resolve_without_system() {
+        let home = NmkHome::from(PathBuf::from("/nonexistent/.nmk"));
+        let nmk_path = home.nmk_path();
+        assert_eq!(nmk_path.bin(), PathBuf::from("/nonexistent/.nmk/bin"));
+        assert_eq!(nmk_path.zsh(), PathBuf::from("/nonexistent/.nmk/zsh"));
+        assert_eq!(home.layers().count(), 1);
     }
 }
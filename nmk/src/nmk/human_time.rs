@@ -1,51 +1,71 @@
 use std::fmt::Write;
 use std::fmt::{self, Display};
+use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
 const MINUTE_SECONDS: u64 = 60;
 const HOUR_SECONDS: u64 = 60 * MINUTE_SECONDS;
-const DAY_SECONDS: u64 = 24 * HOUR_SECONDS;
+pub const DAY_SECONDS: u64 = 24 * HOUR_SECONDS;
+const WEEK_SECONDS: u64 = 7 * DAY_SECONDS;
+/// Calendar year without leap day, this is only for display
+const YEAR_SECONDS: u64 = 365 * DAY_SECONDS;
 
 const ALL_COMPONENTS: u8 = u8::MAX;
 
-#[derive(Copy, Clone)]
+/// Duration in seconds, displayed and parsed as `1y 2w 3d 4h 5m 6s`
+///
+/// Parsing accepts any subset of units in that order, with or without spaces, e.g. `45d`,
+/// `2h30m` or `1w`. Plain number is seconds. `m` is always minutes.
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct HumanTime(u64);
 
+/// How `to_human_with` treats the part smaller than the last displayed component
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Rounding {
+    Floor,
+    Nearest,
+    Ceil,
+}
+
 impl HumanTime {
     pub fn new(secs: u64) -> Self {
         HumanTime(secs)
     }
 
-    pub fn days(self) -> Option<u64> {
-        if self.0 >= DAY_SECONDS {
-            Some(self.0 / DAY_SECONDS)
-        } else {
-            None
-        }
+    pub fn as_secs(self) -> u64 {
+        self.0
     }
 
-    pub fn hours(self) -> Option<u64> {
-        if self.0 >= HOUR_SECONDS {
-            Some(self.0 / HOUR_SECONDS % 24)
-        } else {
-            None
+    /// Value of `unit` after bigger units are taken out, `None` if duration is less than `unit`
+    fn value_of(self, unit: Unit) -> Option<u64> {
+        if self.0 < unit.seconds() && unit != Unit::Second {
+            return None;
         }
+        Some(self.remainder_above(unit) / unit.seconds())
     }
 
-    pub fn minutes(self) -> Option<u64> {
-        if self.0 >= MINUTE_SECONDS {
-            Some(self.0 / MINUTE_SECONDS % 60)
-        } else {
-            None
-        }
+    /// What is left after all units bigger than `unit` are taken out
+    fn remainder_above(self, unit: Unit) -> u64 {
+        Unit::ALL
+            .iter()
+            .take_while(|u| **u != unit)
+            .fold(self.0, |rest, u| rest % u.seconds())
+    }
+
+    pub fn to_human(self, num_components: u8) -> String {
+        self.format(Components::new(self), num_components)
     }
 
-    pub fn secs(self) -> u64 {
-        self.0 % MINUTE_SECONDS
+    /// Like `to_human` without weeks and years, e.g. `45d 0h`, for messages which always
+    /// looked like this
+    pub fn to_human_in_days(self, num_components: u8) -> String {
+        self.format(Components::from_unit(self, Unit::Day), num_components)
     }
 
-    pub fn to_human(self, num_components: u8) -> String {
-        let mut components = self.components().take(num_components.into());
+    fn format(self, components: Components, num_components: u8) -> String {
+        let mut components = components.take(num_components.into());
         // We code this way to show an alternative style which avoid String allocation.
         // We could also collect to Vec<String> then call .join(" ")
         let capacity = (num_components.saturating_mul(4)).min(16).into();
@@ -60,6 +80,37 @@ impl HumanTime {
         buf
     }
 
+    /// Like `to_human` but round the hidden part instead of dropping it, e.g. 1h 59m with one
+    /// component is `2h` with `Rounding::Nearest`
+    pub fn to_human_with(self, num_components: u8, rounding: Rounding) -> String {
+        let last_unit = self
+            .components()
+            .take(num_components.into())
+            .last()
+            .map(|c| c.unit);
+        let rounded = match last_unit {
+            Some(unit) => self.round(unit, rounding),
+            None => self,
+        };
+        rounded.to_human(num_components)
+    }
+
+    fn round(self, unit: Unit, rounding: Rounding) -> Self {
+        let size = unit.seconds();
+        let hidden = self.remainder_above(unit) % size;
+        let floor = self.0 - hidden;
+        let round_up = match rounding {
+            Rounding::Floor => false,
+            Rounding::Nearest => hidden * 2 >= size,
+            Rounding::Ceil => hidden > 0,
+        };
+        if round_up {
+            HumanTime(floor.saturating_add(size))
+        } else {
+            HumanTime(floor)
+        }
+    }
+
     pub fn components(self) -> Components {
         Components::new(self)
     }
@@ -71,51 +122,16 @@ pub struct Component {
     unit: Unit,
 }
 
-impl Component {
-    fn days(value: u64) -> Self {
-        Self {
-            value,
-            unit: Unit::Day,
-        }
-    }
-
-    fn hours(value: u64) -> Self {
-        Self {
-            value,
-            unit: Unit::Hour,
-        }
-    }
-
-    fn minutes(value: u64) -> Self {
-        Self {
-            value,
-            unit: Unit::Minute,
-        }
-    }
-
-    fn seconds(value: u64) -> Self {
-        Self {
-            value,
-            unit: Unit::Second,
-        }
-    }
-}
-
 impl Display for Component {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use Unit::*;
-        let Self { value, unit } = *self;
-        match unit {
-            Day => write!(f, "{}d", value),
-            Hour => write!(f, "{}h", value),
-            Minute => write!(f, "{}m", value),
-            Second => write!(f, "{}s", value),
-        }
+        write!(f, "{}{}", self.value, self.unit.suffix())
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum Unit {
+    Year,
+    Week,
     Day,
     Hour,
     Minute,
@@ -123,29 +139,76 @@ enum Unit {
 }
 
 impl Unit {
-    pub const BIGGEST: Self = Self::Day;
+    pub const BIGGEST: Self = Self::Year;
+
+    /// Biggest first
+    const ALL: [Unit; 6] = [
+        Unit::Year,
+        Unit::Week,
+        Unit::Day,
+        Unit::Hour,
+        Unit::Minute,
+        Unit::Second,
+    ];
 
     fn next_smaller(self) -> Option<Self> {
         use Unit::*;
         match self {
+            Year => Some(Week),
+            Week => Some(Day),
             Day => Some(Hour),
             Hour => Some(Minute),
             Minute => Some(Second),
             Second => None,
         }
     }
+
+    fn seconds(self) -> u64 {
+        use Unit::*;
+        match self {
+            Year => YEAR_SECONDS,
+            Week => WEEK_SECONDS,
+            Day => DAY_SECONDS,
+            Hour => HOUR_SECONDS,
+            Minute => MINUTE_SECONDS,
+            Second => 1,
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        use Unit::*;
+        match self {
+            Year => "y",
+            Week => "w",
+            Day => "d",
+            Hour => "h",
+            Minute => "m",
+            Second => "s",
+        }
+    }
+
+    fn from_suffix(suffix: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|u| u.suffix() == suffix)
+    }
 }
 
 pub struct Components {
     time: HumanTime,
     unit: Option<Unit>,
+    biggest: Unit,
 }
 
 impl Components {
     fn new(time: HumanTime) -> Self {
+        Self::from_unit(time, Unit::BIGGEST)
+    }
+
+    /// Units bigger than `unit` are not used, their time is counted in `unit`
+    fn from_unit(time: HumanTime, unit: Unit) -> Self {
         Components {
             time,
-            unit: Some(Unit::BIGGEST),
+            unit: Some(unit),
+            biggest: unit,
         }
     }
 }
@@ -157,14 +220,13 @@ impl Iterator for Components {
         loop {
             let unit = self.unit?;
             self.unit = unit.next_smaller();
-            let component = match unit {
-                Unit::Day => self.time.days().map(Component::days),
-                Unit::Hour => self.time.hours().map(Component::hours),
-                Unit::Minute => self.time.minutes().map(Component::minutes),
-                Unit::Second => Some(self.time.secs()).map(Component::seconds),
+            let value = if unit == self.biggest && self.time.0 >= unit.seconds() {
+                Some(self.time.0 / unit.seconds())
+            } else {
+                self.time.value_of(unit)
             };
-            if component.is_some() {
-                break component;
+            if let Some(value) = value {
+                break Some(Component { value, unit });
             }
         }
     }
@@ -176,6 +238,91 @@ impl Display for HumanTime {
     }
 }
 
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseHumanTimeError {
+    input: String,
+    reason: &'static str,
+}
+
+impl Display for ParseHumanTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid duration {:?}: {}", self.input, self.reason)
+    }
+}
+
+impl std::error::Error for ParseHumanTimeError {}
+
+impl FromStr for HumanTime {
+    type Err = ParseHumanTimeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = |reason| ParseHumanTimeError {
+            input: s.to_string(),
+            reason,
+        };
+        let input = s.trim();
+        if input.is_empty() {
+            return Err(err("empty"));
+        }
+        if let Ok(secs) = input.parse() {
+            return Ok(HumanTime(secs));
+        }
+        let mut rest = input;
+        let mut total: u64 = 0;
+        let mut previous: Option<Unit> = None;
+        while !rest.is_empty() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            if digits == 0 {
+                return Err(err("expect a number"));
+            }
+            let value: u64 = rest[..digits]
+                .parse()
+                .map_err(|_| err("number too large"))?;
+            rest = &rest[digits..];
+            let letters = rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len());
+            let unit = Unit::from_suffix(&rest[..letters])
+                .ok_or_else(|| err("expect unit y, w, d, h, m or s"))?;
+            rest = rest[letters..].trim_start();
+            // Units must come biggest first, this catches typos like `1m1h`
+            if previous.is_some_and(|p| p.seconds() <= unit.seconds()) {
+                return Err(err("units must be in order y, w, d, h, m, s"));
+            }
+            previous = Some(unit);
+            total = value
+                .checked_mul(unit.seconds())
+                .and_then(|secs| total.checked_add(secs))
+                .ok_or_else(|| err("duration too large"))?;
+        }
+        Ok(HumanTime(total))
+    }
+}
+
+impl Serialize for HumanTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanTime {
+    /// Accept both `"45d"` and plain seconds
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Secs(u64),
+            Text(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Secs(secs) => Ok(HumanTime(secs)),
+            Repr::Text(s) => s.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
 pub fn seconds_since_build() -> Option<u64> {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -206,5 +353,100 @@ mod tests {
         assert_eq!(HumanTime(DAY_SECONDS - 1).to_human(2), "23h 59m");
         assert_eq!(HumanTime(DAY_SECONDS).to_human(2), "1d 0h");
         assert_eq!(HumanTime(DAY_SECONDS + 1).to_human(2), "1d 0h");
+        assert_eq!(HumanTime(45 * DAY_SECONDS).to_human(2), "6w 3d");
+        assert_eq!(
+            HumanTime(45 * DAY_SECONDS + HOUR_SECONDS).to_human_in_days(2),
+            "45d 1h"
+        );
+        assert_eq!(
+            HumanTime(YEAR_SECONDS + 2 * DAY_SECONDS).to_human_in_days(2),
+            "367d 0h"
+        );
+        assert_eq!(HumanTime(HOUR_SECONDS).to_human_in_days(2), "1h 0m");
+        assert_eq!(
+            HumanTime(YEAR_SECONDS + DAY_SECONDS).to_human(3),
+            "1y 0w 1d"
+        );
+    }
+
+    #[test]
+    fn test_to_human_with_rounding() {
+        let t = HumanTime(HOUR_SECONDS + 59 * MINUTE_SECONDS + 30);
+        assert_eq!(t.to_human_with(1, Rounding::Floor), "1h");
+        assert_eq!(t.to_human_with(1, Rounding::Nearest), "2h");
+        assert_eq!(t.to_human_with(1, Rounding::Ceil), "2h");
+        assert_eq!(t.to_human_with(2, Rounding::Floor), "1h 59m");
+        assert_eq!(t.to_human_with(2, Rounding::Nearest), "2h 0m");
+        assert_eq!(t.to_human_with(2, Rounding::Ceil), "2h 0m");
+
+        let t = HumanTime(HOUR_SECONDS + 29 * MINUTE_SECONDS);
+        assert_eq!(t.to_human_with(1, Rounding::Nearest), "1h");
+        assert_eq!(t.to_human_with(1, Rounding::Ceil), "2h");
+        // carry into bigger unit
+        let t = HumanTime(WEEK_SECONDS - 1);
+        assert_eq!(t.to_human_with(1, Rounding::Nearest), "1w");
+        assert_eq!(HumanTime(0).to_human_with(1, Rounding::Ceil), "0s");
+        assert_eq!(HumanTime(59).to_human_with(2, Rounding::Ceil), "59s");
+    }
+
+    #[test]
+    fn test_parse() {
+        let parse = |s: &str| s.parse::<HumanTime>().map(HumanTime::as_secs);
+        assert_eq!(parse("45d"), Ok(45 * DAY_SECONDS));
+        assert_eq!(parse("2h30m"), Ok(2 * HOUR_SECONDS + 30 * MINUTE_SECONDS));
+        assert_eq!(parse("2h 30m"), Ok(2 * HOUR_SECONDS + 30 * MINUTE_SECONDS));
+        assert_eq!(parse("1w"), Ok(WEEK_SECONDS));
+        assert_eq!(parse("1y"), Ok(YEAR_SECONDS));
+        assert_eq!(parse(" 90 "), Ok(90));
+        assert_eq!(parse("0s"), Ok(0));
+        assert!(parse("").is_err());
+        assert!(parse("d").is_err());
+        assert!(parse("10").is_ok());
+        assert!(parse("10x").is_err());
+        assert!(parse("1m1h").is_err());
+        assert!(parse("1h1h").is_err());
+        assert!(parse("-1d").is_err());
+        assert!(parse("99999999999999999999y").is_err());
+        assert!(parse("9999999999999y").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for input in &["45d", "2h30m", "1w", "1y 2w 3d 4h 5m 6s", "59s", "0s"] {
+            let time: HumanTime = input.parse().unwrap();
+            let displayed = time.to_string();
+            assert_eq!(displayed.parse::<HumanTime>(), Ok(time), "{}", input);
+        }
+        for secs in [
+            0,
+            1,
+            59,
+            3600,
+            86399,
+            45 * DAY_SECONDS,
+            YEAR_SECONDS * 3 + 12345,
+        ]
+        .iter()
+        {
+            let time = HumanTime(*secs);
+            assert_eq!(time.to_string().parse::<HumanTime>(), Ok(time));
+        }
+    }
+
+    #[test]
+    fn test_serde() {
+        #[derive(Deserialize, Serialize)]
+        struct Settings {
+            after: HumanTime,
+        }
+        let settings: Settings = toml::from_str(r#"after = "2h30m""#).unwrap();
+        assert_eq!(settings.after.as_secs(), 9000);
+        assert_eq!(
+            toml::to_string(&settings).unwrap().trim(),
+            r#"after = "2h 30m 0s""#
+        );
+        let settings: Settings = toml::from_str("after = 60").unwrap();
+        assert_eq!(settings.after.as_secs(), 60);
+        assert!(toml::from_str::<Settings>(r#"after = "soon""#).is_err());
     }
 }
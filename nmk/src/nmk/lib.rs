@@ -2,17 +2,25 @@
 #[path = "macros.rs"]
 mod _macros;
 pub mod arch;
+#[cfg(feature = "archive")]
 pub mod backup;
 pub mod bin_name;
 pub mod config;
 pub mod container;
 pub mod env_name;
 pub mod error;
+#[cfg(feature = "network")]
 pub mod gcs;
 pub mod home;
 pub mod human_time;
 pub mod platform;
+pub mod settings;
 pub mod setup;
+pub mod status;
+pub mod terminfo;
+#[cfg(test)]
+mod test_util;
 pub mod tmux;
+pub mod update_cache;
 
 pub type Result<T> = std::result::Result<T, error::Error>;
@@ -1,10 +1,11 @@
-/// Implement From<Error> to convert any error to Error with caller info
+/// Implement From<Error> to convert any error to Error with caller info and error kind
 macro_rules! impl_from_error {
-    ($ty:ty) => {
+    ($ty:ty, $kind:ident) => {
         impl From<$ty> for crate::error::Error {
             #[track_caller]
             fn from(err: $ty) -> Self {
                 Self::new(
+                    crate::error::ErrorKind::$kind,
                     Box::new(err),
                     stringify!($ty),
                     *::std::panic::Location::caller(),
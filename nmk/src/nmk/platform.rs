@@ -1,31 +1,75 @@
 use once_cell::sync::Lazy;
 
-#[derive(Clone, Copy, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum PlatformType {
     Unknown,
     MacOs,
     Linux,
     Arch,
     Alpine,
+    /// Windows Subsystem for Linux, the distribution running in it is given by `distribution`
+    Wsl(Wsl),
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Wsl {
+    /// Syscall translation layer, kernel release ends with `-Microsoft`
+    V1,
+    /// Real linux kernel in a lightweight VM
+    V2,
 }
 
 pub fn is_mac() -> bool {
     *PLATFORM == PlatformType::MacOs
 }
 
+pub fn is_wsl() -> bool {
+    matches!(*PLATFORM, PlatformType::Wsl(_))
+}
+
+/// Platform regardless of WSL, e.g. Alpine under WSL is still Alpine
+pub fn distribution() -> PlatformType {
+    *DISTRIBUTION
+}
+
 impl PlatformType {
     pub fn detect() -> PlatformType {
         *PLATFORM
     }
 }
 
-static PLATFORM: Lazy<PlatformType> = Lazy::new(what_platform);
+static DISTRIBUTION: Lazy<PlatformType> = Lazy::new(what_platform);
+static PLATFORM: Lazy<PlatformType> =
+    Lazy::new(|| what_wsl().map_or(*DISTRIBUTION, PlatformType::Wsl));
+
+/// Detect WSL from kernel release, kernel version and `WSL_DISTRO_NAME`
+///
+/// Custom WSL2 kernels may not mention Microsoft at all, but WSL still sets the distro name.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn detect_wsl(osrelease: &str, version: &str, distro_name: Option<&str>) -> Option<Wsl> {
+    let osrelease = osrelease.to_lowercase();
+    let version = version.to_lowercase();
+    let is_wsl2 = |s: &str| s.contains("wsl2") || s.contains("microsoft-standard");
+    if is_wsl2(&osrelease) || is_wsl2(&version) {
+        Some(Wsl::V2)
+    } else if osrelease.contains("microsoft") || version.contains("microsoft") {
+        Some(Wsl::V1)
+    } else if distro_name.is_some_and(|name| !name.is_empty()) {
+        Some(Wsl::V2)
+    } else {
+        None
+    }
+}
 
 cfg_if::cfg_if! {
     if #[cfg(target_os = "macos")] {
         fn what_platform() -> PlatformType {
             PlatformType::MacOs
         }
+
+        fn what_wsl() -> Option<Wsl> {
+            None
+        }
     } else if #[cfg(target_os = "linux")] {
         fn what_platform() -> PlatformType {
             let exists = |s: &str| std::path::Path::new(s).exists();
@@ -37,9 +81,68 @@ cfg_if::cfg_if! {
                 PlatformType::Linux
             }
         }
+
+        fn what_wsl() -> Option<Wsl> {
+            let read = |s: &str| std::fs::read_to_string(s).unwrap_or_default();
+            detect_wsl(
+                &read("/proc/sys/kernel/osrelease"),
+                &read("/proc/version"),
+                std::env::var("WSL_DISTRO_NAME").ok().as_deref(),
+            )
+        }
     } else {
         fn what_platform() -> PlatformType {
             PlatformType::Unknown
         }
+
+        fn what_wsl() -> Option<Wsl> {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! fixture {
+        ($name:literal) => {
+            (
+                include_str!(concat!("fixtures/wsl/", $name, ".osrelease")),
+                include_str!(concat!("fixtures/wsl/", $name, ".version")),
+            )
+        };
+    }
+
+    #[test]
+    fn test_detect_wsl() {
+        let (osrelease, version) = fixture!("wsl1");
+        assert_eq!(
+            detect_wsl(osrelease, version, Some("Ubuntu")),
+            Some(Wsl::V1)
+        );
+        assert_eq!(detect_wsl(osrelease, version, None), Some(Wsl::V1));
+
+        let (osrelease, version) = fixture!("wsl2");
+        assert_eq!(
+            detect_wsl(osrelease, version, Some("Ubuntu")),
+            Some(Wsl::V2)
+        );
+        assert_eq!(detect_wsl(osrelease, "", None), Some(Wsl::V2));
+        assert_eq!(detect_wsl("", version, None), Some(Wsl::V2));
+
+        let (osrelease, version) = fixture!("wsl2-custom-kernel");
+        assert_eq!(
+            detect_wsl(osrelease, version, Some("Debian")),
+            Some(Wsl::V2)
+        );
+        assert_eq!(detect_wsl(osrelease, version, None), None);
+
+        let (osrelease, version) = fixture!("linux");
+        assert_eq!(detect_wsl(osrelease, version, None), None);
+        assert_eq!(detect_wsl(osrelease, version, Some("")), None);
+
+        // /proc is not readable
+        assert_eq!(detect_wsl("", "", None), None);
     }
 }
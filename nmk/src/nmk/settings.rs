@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::env_name::{NMK_CLIPBOARD, NMK_THEME, NMK_UPDATE_SUGGEST_AFTER};
+use crate::error::Context;
+use crate::home::NmkHome;
+use crate::human_time::{HumanTime, DAY_SECONDS};
+use crate::status::Segment;
+use crate::tmux::clipboard::Backend;
+
+/// User settings from `nmk.toml` in NMK_HOME, durations are written in readable units
+///
+/// ```toml
+/// [update]
+/// suggest-after = "45d"
+///
+/// [tmux]
+/// theme = "nord"
+/// clipboard = "osc52"
+///
+/// [status]
+/// right = ["git", "load", "battery"]
+/// ttl = { battery = "5m" }
+/// ```
+///
+/// Environment variables take precedence over the file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    pub update: UpdateSettings,
+    pub tmux: TmuxSettings,
+    pub status: StatusSettings,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TmuxSettings {
+    /// Colour theme, built-in one or `themes/<name>.toml` in NMK_HOME
+    pub theme: Option<String>,
+    /// Clipboard backend of copy mode, detected when not set
+    pub clipboard: Option<Backend>,
+}
+
+/// Segments of `nmk status`
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct StatusSettings {
+    pub left: Vec<Segment>,
+    pub right: Vec<Segment>,
+    /// Longest wait for segments in milliseconds, late ones show their previous value
+    pub timeout_ms: u64,
+    /// How long a segment value is reused, overrides the default of the segment
+    pub ttl: BTreeMap<Segment, HumanTime>,
+}
+
+impl Default for StatusSettings {
+    fn default() -> Self {
+        Self {
+            left: Vec::new(),
+            right: vec![Segment::Git, Segment::Load],
+            timeout_ms: 300,
+            ttl: BTreeMap::new(),
+        }
+    }
+}
+
+impl StatusSettings {
+    pub fn ttl(&self, segment: Segment) -> Duration {
+        self.ttl.get(&segment).map_or_else(
+            || segment.default_ttl(),
+            |t| Duration::from_secs(t.as_secs()),
+        )
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct UpdateSettings {
+    /// Suggest update when build is older than this and remote metadata is not cached yet
+    pub suggest_after: HumanTime,
+}
+
+impl Default for UpdateSettings {
+    fn default() -> Self {
+        Self {
+            suggest_after: HumanTime::new(45 * DAY_SECONDS),
+        }
+    }
+}
+
+impl Settings {
+    /// Load settings, invalid file or variable is reported and replaced by default
+    ///
+    /// Settings are not worth failing shell startup.
+    pub fn load(nmk_home: &NmkHome) -> Self {
+        let path = nmk_home.nmk_path().settings();
+        let mut settings = match Self::from_file(&path) {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::warn!("{:#}", e);
+                Self::default()
+            }
+        };
+        settings.apply_env(|name| env::var(name).ok());
+        settings
+    }
+
+    fn from_file(path: &Path) -> crate::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&data).with_context(|| format!("invalid settings {}", path.display()))
+    }
+
+    fn apply_env<F: Fn(&str) -> Option<String>>(&mut self, var: F) {
+        if let Some(value) = var(NMK_UPDATE_SUGGEST_AFTER).filter(|v| !v.is_empty()) {
+            match value.parse() {
+                Ok(time) => self.update.suggest_after = time,
+                Err(e) => log::warn!("{}: {}", NMK_UPDATE_SUGGEST_AFTER, e),
+            }
+        }
+        if let Some(theme) = var(NMK_THEME).filter(|v| !v.is_empty()) {
+            self.tmux.theme = Some(theme);
+        }
+        if let Some(value) = var(NMK_CLIPBOARD).filter(|v| !v.is_empty()) {
+            match value.parse() {
+                Ok(backend) => self.tmux.clipboard = Some(backend),
+                Err(e) => log::warn!("{}: {}", NMK_CLIPBOARD, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_settings() {
+        let settings: Settings = toml::from_str("").unwrap();
+        assert_eq!(settings.update.suggest_after.as_secs(), 45 * DAY_SECONDS);
+
+        let settings: Settings = toml::from_str("[update]\nsuggest-after = \"2w\"").unwrap();
+        assert_eq!(settings.update.suggest_after.as_secs(), 14 * DAY_SECONDS);
+
+        assert!(toml::from_str::<Settings>("[update]\nsuggest-after = \"2 weeks\"").is_err());
+        assert!(toml::from_str::<Settings>("[update]\nsuggest_after = \"2w\"").is_err());
+
+        let settings: Settings = toml::from_str("[tmux]\nclipboard = \"wl-copy\"").unwrap();
+        assert_eq!(settings.tmux.clipboard, Some(Backend::WlCopy));
+        assert!(toml::from_str::<Settings>("[tmux]\nclipboard = \"wayland\"").is_err());
+
+        let settings: Settings = toml::from_str(
+            "[status]\nright = [\"cpu-temp\", \"hostname\"]\nttl = { cpu-temp = \"1m\" }",
+        )
+        .unwrap();
+        assert_eq!(
+            settings.status.right,
+            vec![Segment::CpuTemp, Segment::Hostname]
+        );
+        assert_eq!(
+            settings.status.ttl(Segment::CpuTemp),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            settings.status.ttl(Segment::Git),
+            Segment::Git.default_ttl()
+        );
+        assert!(toml::from_str::<Settings>("[status]\nright = [\"clock\"]").is_err());
+    }
+
+    #[test]
+    fn test_env_override() {
+        let only = |name: &'static str, value: &'static str| {
+            move |n: &str| Some(value.to_string()).filter(|_| n == name)
+        };
+        let mut settings = Settings::default();
+        settings.apply_env(only(NMK_UPDATE_SUGGEST_AFTER, "1w 2d"));
+        assert_eq!(settings.update.suggest_after.as_secs(), 9 * DAY_SECONDS);
+
+        // invalid value keeps the previous one
+        settings.apply_env(only(NMK_UPDATE_SUGGEST_AFTER, "tomorrow"));
+        assert_eq!(settings.update.suggest_after.as_secs(), 9 * DAY_SECONDS);
+
+        settings.apply_env(|_| None);
+        assert_eq!(settings.update.suggest_after.as_secs(), 9 * DAY_SECONDS);
+        assert_eq!(settings.tmux.theme, None);
+
+        settings.apply_env(only(NMK_THEME, "nord"));
+        assert_eq!(settings.tmux.theme.as_deref(), Some("nord"));
+
+        settings.apply_env(only(NMK_CLIPBOARD, "osc52"));
+        assert_eq!(settings.tmux.clipboard, Some(Backend::Osc52));
+        settings.apply_env(only(NMK_CLIPBOARD, "clipboard"));
+        assert_eq!(settings.tmux.clipboard, Some(Backend::Osc52));
+    }
+}
@@ -1,23 +1,322 @@
-use std::fs::{File, OpenOptions};
-use std::io;
-use std::io::{Read, Write};
-use std::os::unix::fs::OpenOptionsExt;
-use std::path::Path;
-
-pub fn install<P: AsRef<Path>, R: ?Sized>(reader: &mut R, dst: P) -> io::Result<()>
-where
-    R: Read,
-{
-    let mut file = open_for_install(dst)?;
-    io::copy(reader, &mut file)?;
-    file.flush()
-}
-
-fn open_for_install<P: AsRef<Path>>(dst: P) -> io::Result<File> {
-    OpenOptions::new()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .mode(0o755)
-        .open(dst)
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+const MODE: u32 = 0o755;
+
+/// Install executable from `reader` to `dst` atomically
+///
+/// Data is written to a temporary file next to `dst` then renamed over it, so a running binary
+/// is never written in place (ETXTBSY) and an interrupted install never leaves a truncated one.
+/// Ownership and extended attributes of the replaced file are kept when possible.
+pub fn install<P: AsRef<Path>, R: ?Sized + Read>(reader: &mut R, dst: P) -> io::Result<()> {
+    let dst = resolve_symlink(dst.as_ref())?;
+    let previous = fs::metadata(&dst).ok();
+    let mut temp = TempFile::create(&dst)?;
+    io::copy(reader, &mut temp.file)?;
+    temp.file.flush()?;
+    if let Some(ref previous) = previous {
+        preserve_ownership(&temp.file, previous);
+        xattr::copy(&dst, &temp.path);
+    }
+    temp.file
+        .set_permissions(fs::Permissions::from_mode(MODE))?;
+    temp.file.sync_all()?;
+    temp.persist(&dst)
+}
+
+/// Replace the file a symlink points to rather than the symlink itself
+fn resolve_symlink(dst: &Path) -> io::Result<PathBuf> {
+    match fs::symlink_metadata(dst) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(dst),
+        _ => Ok(dst.to_path_buf()),
+    }
+}
+
+/// Only root can give a file away, so this is best effort
+fn preserve_ownership(file: &File, previous: &fs::Metadata) {
+    if let Err(e) = std::os::unix::fs::fchown(file, Some(previous.uid()), Some(previous.gid())) {
+        log::debug!("Cannot preserve ownership: {}", e);
+    }
+}
+
+/// Temporary file in the same directory as the target, removed unless persisted
+struct TempFile {
+    path: PathBuf,
+    file: File,
+    persisted: bool,
+}
+
+impl TempFile {
+    fn create(dst: &Path) -> io::Result<Self> {
+        let dir = match dst.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let name = dst
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?;
+        let mut attempt = 0;
+        loop {
+            let mut temp_name = OsString::from(".");
+            temp_name.push(name);
+            temp_name.push(format!(".{}.{}.tmp", std::process::id(), attempt));
+            let path = dir.join(temp_name);
+            // Start private, final mode is set after data is written
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+            {
+                Ok(file) => {
+                    return Ok(Self {
+                        path,
+                        file,
+                        persisted: false,
+                    })
+                }
+                // Leftover of an interrupted install with the same pid
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 16 => attempt += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn persist(mut self, dst: &Path) -> io::Result<()> {
+        fs::rename(&self.path, dst)?;
+        self.persisted = true;
+        // Make the rename itself durable
+        if let Some(dir) = self.path.parent() {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            if let Err(e) = File::open(dir).and_then(|d| d.sync_all()) {
+                log::debug!("Cannot sync directory {:?}: {}", dir, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod xattr {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use nix::libc;
+
+    fn c_path(path: &Path) -> io::Result<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    /// Names of extended attributes, fails with ENOTSUP if the filesystem has none
+    pub(super) fn list(path: &Path) -> io::Result<Vec<CString>> {
+        let path = c_path(path)?;
+        // SAFETY: a null buffer with zero size only queries the required size
+        let size = unsafe { libc::listxattr(path.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; size as usize];
+        // SAFETY: buf is valid for buf.len() bytes
+        let size = unsafe { libc::listxattr(path.as_ptr(), buf.as_mut_ptr().cast(), buf.len()) };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(size as usize);
+        Ok(buf
+            .split(|b| *b == 0)
+            .filter(|name| !name.is_empty())
+            .filter_map(|name| CString::new(name).ok())
+            .collect())
+    }
+
+    pub(super) fn get(path: &Path, name: &CString) -> io::Result<Vec<u8>> {
+        let path = c_path(path)?;
+        // SAFETY: a null buffer with zero size only queries the required size
+        let size = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut value = vec![0u8; size as usize];
+        // SAFETY: value is valid for value.len() bytes
+        let size = unsafe {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        value.truncate(size as usize);
+        Ok(value)
+    }
+
+    pub(super) fn set(path: &Path, name: &CString, value: &[u8]) -> io::Result<()> {
+        let path = c_path(path)?;
+        // SAFETY: all pointers are valid for the given sizes
+        let ret = unsafe {
+            libc::setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Copy every attribute we can, some namespaces need privileges we may not have
+    pub(super) fn copy(from: &Path, to: &Path) {
+        let names = match list(from) {
+            Ok(names) => names,
+            Err(e) => {
+                log::debug!("Cannot list extended attributes of {:?}: {}", from, e);
+                return;
+            }
+        };
+        for name in names {
+            if let Err(e) = get(from, &name).and_then(|value| set(to, &name, &value)) {
+                log::debug!("Cannot copy extended attribute {:?}: {}", name, e);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod xattr {
+    use std::path::Path;
+
+    pub(super) fn copy(_from: &Path, _to: &Path) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Fail after some bytes, like a download that dies halfway
+    struct Interrupted<'a>(&'a [u8]);
+
+    impl Read for Interrupted<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::Error::other("connection reset"));
+            }
+            self.0.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_install_new_file() {
+        let dir = TempDir::new("setup-new");
+        let dst = dir.join("nmk");
+        install(&mut &b"new binary"[..], &dst).unwrap();
+        assert_eq!(fs::read(&dst).unwrap(), b"new binary");
+        assert_eq!(fs::metadata(&dst).unwrap().mode() & 0o777, MODE);
+        assert_eq!(entries(&dir), vec!["nmk"]);
+    }
+
+    #[test]
+    fn test_install_replaces_open_file() {
+        let dir = TempDir::new("setup-replace");
+        let dst = dir.join("nmk");
+        fs::write(&dst, b"old binary").unwrap();
+        fs::set_permissions(&dst, fs::Permissions::from_mode(0o700)).unwrap();
+        // A running binary keeps its inode
+        let mut running = File::open(&dst).unwrap();
+
+        install(&mut &b"new binary"[..], &dst).unwrap();
+        assert_eq!(fs::read(&dst).unwrap(), b"new binary");
+        assert_eq!(fs::metadata(&dst).unwrap().mode() & 0o777, MODE);
+        let mut old = String::new();
+        running.read_to_string(&mut old).unwrap();
+        assert_eq!(old, "old binary");
+        assert_eq!(entries(&dir), vec!["nmk"]);
+    }
+
+    #[test]
+    fn test_interrupted_install_keeps_target() {
+        let dir = TempDir::new("setup-interrupted");
+        let dst = dir.join("nmk");
+        fs::write(&dst, b"old binary").unwrap();
+
+        let err = install(&mut Interrupted(b"partial"), &dst).unwrap_err();
+        assert_eq!(err.to_string(), "connection reset");
+        assert_eq!(fs::read(&dst).unwrap(), b"old binary");
+        assert_eq!(entries(&dir), vec!["nmk"]);
+
+        // Leftover of a process that was killed before cleanup
+        let leftover = dir.join(format!(".nmk.{}.0.tmp", std::process::id()));
+        fs::write(&leftover, b"garbage").unwrap();
+        install(&mut &b"new binary"[..], &dst).unwrap();
+        assert_eq!(fs::read(&dst).unwrap(), b"new binary");
+        assert_eq!(fs::read(&leftover).unwrap(), b"garbage");
+    }
+
+    #[test]
+    fn test_install_through_symlink() {
+        let dir = TempDir::new("setup-symlink");
+        let real = dir.join("nmk-real");
+        let link = dir.join("nmk");
+        fs::write(&real, b"old binary").unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        install(&mut &b"new binary"[..], &link).unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read(&real).unwrap(), b"new binary");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_install_preserves_xattr() {
+        use std::ffi::CString;
+
+        let dir = TempDir::new("setup-xattr");
+        let dst = dir.join("nmk");
+        fs::write(&dst, b"old binary").unwrap();
+        let name = CString::new("user.nmk.test").unwrap();
+        if xattr::set(&dst, &name, b"kept").is_err() {
+            // user xattrs are not supported on every filesystem, e.g. tmpfs on old kernels
+            return;
+        }
+        install(&mut &b"new binary"[..], &dst).unwrap();
+        assert!(xattr::list(&dst).unwrap().contains(&name));
+        assert_eq!(xattr::get(&dst, &name).unwrap(), b"kept");
+    }
 }
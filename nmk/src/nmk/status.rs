@@ -0,0 +1,503 @@
+//! Segments of tmux status line, rendered by `nmk status` through `#(...)`
+//!
+//! tmux runs the command every `status-interval`, so each segment keeps its last value in a cache
+//! for its time to live. Segments are computed in parallel and whatever misses the deadline is
+//! replaced by its stale cached value, a slow filesystem or command never blocks the status line.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::container;
+use crate::update_cache::epoch_seconds;
+
+const SEPARATOR: &str = " | ";
+/// Cached values of git branches of panes which are gone are dropped after this
+const MAX_CACHE_AGE: u64 = 24 * 60 * 60;
+
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, strum::Display, strum::EnumString,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Segment {
+    /// Branch or short commit of the repository of pane's directory
+    Git,
+    Load,
+    /// Used memory in percent
+    Memory,
+    Battery,
+    CpuTemp,
+    Hostname,
+    /// Container kind, empty outside container
+    Container,
+}
+
+impl Segment {
+    /// How long a computed value is reused
+    pub fn default_ttl(self) -> Duration {
+        let secs = match self {
+            Segment::Git => 5,
+            Segment::Load | Segment::CpuTemp => 10,
+            Segment::Memory => 15,
+            Segment::Battery => 60,
+            Segment::Hostname | Segment::Container => 60 * 60,
+        };
+        Duration::from_secs(secs)
+    }
+
+    /// Git depends on directory of the pane, others are the same for every pane
+    fn cache_key(self, pane_path: Option<&Path>) -> String {
+        match (self, pane_path) {
+            (Segment::Git, Some(path)) => format!("{}:{}", self, path.display()),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Current value, `None` hides the segment
+    pub fn compute(self, pane_path: Option<&Path>, timeout: Duration) -> Option<String> {
+        match self {
+            Segment::Git => git_head(pane_path?),
+            Segment::Load => load_average().map(|load| format!("load {:.2}", load)),
+            Segment::Memory => fs::read_to_string("/proc/meminfo")
+                .ok()
+                .and_then(|s| memory_used_percent(&s))
+                .map(|used| format!("mem {}%", used)),
+            Segment::Battery => battery(timeout),
+            Segment::CpuTemp => cpu_temp(timeout).map(|t| format!("{:.0}°C", t)),
+            Segment::Hostname => {
+                let uname = nix::sys::utsname::uname();
+                let name = uname.nodename().split('.').next().unwrap_or_default();
+                Some(name.to_string()).filter(|name| !name.is_empty())
+            }
+            Segment::Container => container::detect().map(|c| c.kind.to_string()),
+        }
+    }
+}
+
+// Derived enum can't be a key of TOML table
+impl<'de> Deserialize<'de> for Segment {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|_| de::Error::custom(format!("unknown status segment {:?}", s)))
+    }
+}
+
+impl Serialize for Segment {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Entry {
+    value: Option<String>,
+    /// Epoch seconds when value was computed
+    at: u64,
+}
+
+/// Last values of segments, shared by every tmux client of the user
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct StatusCache {
+    entries: BTreeMap<String, Entry>,
+}
+
+impl StatusCache {
+    pub fn load(path: &Path) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// Written to a temporary file then renamed, tmux may run several of us at once
+    pub fn save(&mut self, path: &Path) -> io::Result<()> {
+        let now = epoch_seconds();
+        self.entries
+            .retain(|_, entry| now.saturating_sub(entry.at) < MAX_CACHE_AGE);
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp, path)
+    }
+
+    fn is_fresh(&self, key: &str, ttl: Duration, now: u64) -> bool {
+        self.entries
+            .get(key)
+            .is_some_and(|entry| now.saturating_sub(entry.at) < ttl.as_secs())
+    }
+}
+
+pub struct Request<'a> {
+    pub segments: &'a [Segment],
+    pub pane_path: Option<&'a Path>,
+    pub ttl: &'a dyn Fn(Segment) -> Duration,
+    /// Deadline of all segments together
+    pub timeout: Duration,
+    /// `Segment::compute`, runs in its own thread
+    pub compute: fn(Segment, Option<&Path>, Duration) -> Option<String>,
+}
+
+/// Render segments in order, returns whether cache has changed
+pub fn render(request: &Request, cache: &mut StatusCache) -> (String, bool) {
+    let now = epoch_seconds();
+    let start = Instant::now();
+    let deadline = start + request.timeout;
+    let (tx, rx) = mpsc::channel();
+    let mut pending = 0;
+    for (i, segment) in request.segments.iter().copied().enumerate() {
+        let key = segment.cache_key(request.pane_path);
+        if cache.is_fresh(&key, (request.ttl)(segment), now) {
+            continue;
+        }
+        let tx = tx.clone();
+        let pane_path = request.pane_path.map(PathBuf::from);
+        let timeout = request.timeout;
+        let compute = request.compute;
+        pending += 1;
+        thread::spawn(move || {
+            let value = compute(segment, pane_path.as_deref(), timeout);
+            // Receiver is gone after deadline
+            let _ = tx.send((i, key, value));
+        });
+    }
+    let mut changed = false;
+    while pending > 0 {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(remaining) {
+            Ok((i, key, value)) => {
+                log::debug!("{} = {:?}", request.segments[i], value);
+                cache.entries.insert(key, Entry { value, at: now });
+                changed = true;
+                pending -= 1;
+            }
+            Err(_) => {
+                log::debug!("{} segments timed out after {:?}", pending, start.elapsed());
+                break;
+            }
+        }
+    }
+    let values: Vec<&str> = request
+        .segments
+        .iter()
+        .filter_map(|segment| {
+            let key = segment.cache_key(request.pane_path);
+            cache.entries.get(&key)?.value.as_deref()
+        })
+        .collect();
+    (values.join(SEPARATOR), changed)
+}
+
+/// Run command and kill it after `timeout`, output is trimmed
+fn output_with_timeout(cmd: &mut Command, timeout: Duration) -> Option<String> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait().ok()? {
+            Some(status) if status.success() => break,
+            Some(_) => return None,
+            None if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            None => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+    let mut output = String::new();
+    child.stdout?.read_to_string(&mut output).ok()?;
+    Some(output.trim().to_string())
+}
+
+/// Read `HEAD` of the repository containing `dir`, without running git
+fn git_head(dir: &Path) -> Option<String> {
+    let git_dir = dir.ancestors().find_map(|d| {
+        let dot_git = d.join(".git");
+        if dot_git.is_dir() {
+            Some(dot_git)
+        } else {
+            // worktree and submodule, `gitdir: <path>`
+            let link = fs::read_to_string(&dot_git).ok()?;
+            let target = link.strip_prefix("gitdir:")?.trim();
+            Some(d.join(target))
+        }
+    })?;
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    parse_head(&head)
+}
+
+fn parse_head(head: &str) -> Option<String> {
+    let head = head.trim();
+    match head.strip_prefix("ref:") {
+        Some(reference) => {
+            let reference = reference.trim();
+            Some(
+                reference
+                    .strip_prefix("refs/heads/")
+                    .unwrap_or(reference)
+                    .to_string(),
+            )
+        }
+        None if head.len() >= 7 && head.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            Some(head[..7].to_string())
+        }
+        None => None,
+    }
+}
+
+fn load_average() -> Option<f64> {
+    let mut load = [0f64; 1];
+    let n = unsafe { nix::libc::getloadavg(load.as_mut_ptr(), 1) };
+    if n == 1 {
+        Some(load[0])
+    } else {
+        None
+    }
+}
+
+/// Used memory in percent from `/proc/meminfo`, page cache counts as available
+fn memory_used_percent(meminfo: &str) -> Option<u64> {
+    let field = |name: &str| -> Option<u64> {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))?
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    };
+    let total = field("MemTotal").filter(|total| *total > 0)?;
+    let available = field("MemAvailable")?;
+    Some(total.saturating_sub(available) * 100 / total)
+}
+
+fn battery(timeout: Duration) -> Option<String> {
+    if cfg!(target_os = "macos") {
+        let output = output_with_timeout(Command::new("pmset").args(["-g", "batt"]), timeout)?;
+        return parse_pmset(&output);
+    }
+    let supplies = fs::read_dir("/sys/class/power_supply").ok()?;
+    supplies
+        .flatten()
+        .map(|entry| entry.path())
+        .find_map(|dir| {
+            let read = |name| fs::read_to_string(dir.join(name)).ok();
+            if read("type")?.trim() != "Battery" {
+                return None;
+            }
+            let capacity = read("capacity")?;
+            let charging = read("status").is_some_and(|s| s.trim() == "Charging");
+            Some(format_battery(capacity.trim(), charging))
+        })
+}
+
+fn format_battery(percent: &str, charging: bool) -> String {
+    format!("bat {}%{}", percent, if charging { "+" } else { "" })
+}
+
+/// `-InternalBattery-0 (id=123)<tab>87%; charging; 1:02 remaining present: true`
+fn parse_pmset(output: &str) -> Option<String> {
+    let line = output
+        .lines()
+        .find(|line| line.contains("InternalBattery"))?;
+    let mut fields = line.split('\t').nth(1)?.split(';').map(str::trim);
+    let percent = fields.next()?.strip_suffix('%')?;
+    let charging = fields.next() == Some("charging");
+    Some(format_battery(percent, charging))
+}
+
+/// Thermal zone of CPU, Raspberry Pi firmware as a fallback
+fn cpu_temp(timeout: Duration) -> Option<f64> {
+    let zones: Vec<PathBuf> = fs::read_dir("/sys/class/thermal")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("thermal_zone"))
+        })
+        .collect();
+    let is_cpu = |zone: &PathBuf| {
+        fs::read_to_string(zone.join("type")).is_ok_and(|kind| {
+            let kind = kind.trim();
+            ["cpu", "x86_pkg_temp", "soc", "k10temp"]
+                .iter()
+                .any(|name| kind.contains(name))
+        })
+    };
+    let zone = zones.iter().find(|zone| is_cpu(zone)).or(zones.first());
+    if let Some(temp) = zone.and_then(|zone| fs::read_to_string(zone.join("temp")).ok()) {
+        return temp.trim().parse::<f64>().ok().map(|milli| milli / 1000.0);
+    }
+    let output = output_with_timeout(Command::new("vcgencmd").arg("measure_temp"), timeout)?;
+    parse_vcgencmd(&output)
+}
+
+/// `temp=48.3'C`
+fn parse_vcgencmd(output: &str) -> Option<f64> {
+    output
+        .trim()
+        .strip_prefix("temp=")?
+        .strip_suffix("'C")?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_segment_names() {
+        assert_eq!(Segment::CpuTemp.to_string(), "cpu-temp");
+        assert_eq!("cpu-temp".parse(), Ok(Segment::CpuTemp));
+        assert_eq!("git".parse(), Ok(Segment::Git));
+        assert!("clock".parse::<Segment>().is_err());
+    }
+
+    #[test]
+    fn test_parse_head() {
+        assert_eq!(
+            parse_head("ref: refs/heads/main\n"),
+            Some("main".to_string())
+        );
+        assert_eq!(
+            parse_head("ref: refs/heads/feature/status\n"),
+            Some("feature/status".to_string())
+        );
+        assert_eq!(
+            parse_head("0123456789abcdef0123456789abcdef01234567\n"),
+            Some("0123456".to_string())
+        );
+        assert_eq!(parse_head(""), None);
+    }
+
+    #[test]
+    fn test_git_head() {
+        let root = TempDir::new("status");
+        fs::create_dir_all(root.join("repo/.git")).unwrap();
+        fs::create_dir_all(root.join("repo/src/deep")).unwrap();
+        fs::create_dir_all(root.join("main/.git/worktrees/wt")).unwrap();
+        fs::create_dir_all(root.join("wt")).unwrap();
+        fs::write(root.join("repo/.git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(
+            root.join("main/.git/worktrees/wt/HEAD"),
+            "ref: refs/heads/topic\n",
+        )
+        .unwrap();
+        fs::write(root.join("wt/.git"), "gitdir: ../main/.git/worktrees/wt\n").unwrap();
+
+        assert_eq!(
+            git_head(&root.join("repo/src/deep")),
+            Some("main".to_string())
+        );
+        assert_eq!(git_head(&root.join("wt")), Some("topic".to_string()));
+    }
+
+    #[test]
+    fn test_memory_used_percent() {
+        let meminfo = "MemTotal:       16000000 kB\nMemFree:         1000000 kB\nMemAvailable:    4000000 kB\n";
+        assert_eq!(memory_used_percent(meminfo), Some(75));
+        assert_eq!(
+            memory_used_percent("MemTotal: 0 kB\nMemAvailable: 0 kB"),
+            None
+        );
+        assert_eq!(memory_used_percent("MemTotal: 100 kB\n"), None);
+    }
+
+    #[test]
+    fn test_parse_battery() {
+        let output = "Now drawing from 'AC Power'\n -InternalBattery-0 (id=4653155)\t87%; charging; 1:02 remaining present: true\n";
+        assert_eq!(parse_pmset(output), Some("bat 87%+".to_string()));
+        let output =
+            " -InternalBattery-0 (id=4653155)\t42%; discharging; 3:10 remaining present: true\n";
+        assert_eq!(parse_pmset(output), Some("bat 42%".to_string()));
+        assert_eq!(parse_pmset("Now drawing from 'AC Power'\n"), None);
+    }
+
+    #[test]
+    fn test_parse_vcgencmd() {
+        assert_eq!(parse_vcgencmd("temp=48.3'C\n"), Some(48.3));
+        assert_eq!(parse_vcgencmd("error"), None);
+    }
+
+    #[test]
+    fn test_output_with_timeout() {
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            output_with_timeout(Command::new("sh").args(["-c", "echo ok"]), timeout),
+            Some("ok".to_string())
+        );
+        assert_eq!(
+            output_with_timeout(Command::new("sh").args(["-c", "exit 1"]), timeout),
+            None
+        );
+        let start = Instant::now();
+        assert_eq!(
+            output_with_timeout(
+                Command::new("sh").args(["-c", "sleep 5"]),
+                Duration::from_millis(50)
+            ),
+            None
+        );
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_render_uses_cache() {
+        let mut cache = StatusCache::default();
+        let now = epoch_seconds();
+        cache.entries.insert(
+            "hostname".to_string(),
+            Entry {
+                value: Some("cached".to_string()),
+                at: now,
+            },
+        );
+        cache.entries.insert(
+            "container".to_string(),
+            Entry {
+                value: Some("stale".to_string()),
+                at: now - 2 * 60 * 60,
+            },
+        );
+        let request = Request {
+            segments: &[Segment::Hostname, Segment::Container],
+            pane_path: None,
+            ttl: &Segment::default_ttl,
+            // nothing finishes in time, stale value is better than nothing
+            timeout: Duration::from_millis(10),
+            compute: |_, _, _| loop {
+                thread::park();
+            },
+        };
+        let (rendered, changed) = render(&request, &mut cache);
+        assert!(!changed);
+        assert_eq!(rendered, "cached | stale");
+
+        let request = Request {
+            timeout: Duration::from_secs(5),
+            compute: |segment, _, _| Some(format!("new {}", segment)),
+            ..request
+        };
+        let (rendered, changed) = render(&request, &mut cache);
+        assert!(changed);
+        assert_eq!(rendered, "cached | new container");
+        assert_ne!(cache.entries["container"].at, now - 2 * 60 * 60);
+    }
+}
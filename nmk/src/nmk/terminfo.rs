@@ -0,0 +1,378 @@
+//! Reader of compiled terminfo entries, see term(5)
+//!
+//! Only what we need to decide colour support is kept: terminal names, `colors` and the
+//! extended capabilities, which is where `RGB`, `Tc` and `setrgbf` live.
+
+use std::env;
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 16-bit numbers
+const MAGIC_LEGACY: u16 = 0o432;
+/// 32-bit numbers, ncurses 6.1 and later
+const MAGIC_32BIT: u16 = 0o1036;
+/// Position of `colors` in the standard numeric capabilities
+const COLORS_INDEX: usize = 13;
+/// Number of `colors` of direct colour terminals like xterm-direct
+const DIRECT_COLORS: i32 = 1 << 24;
+
+/// Common locations of ncurses and other terminfo databases
+const SYSTEM_DIRS: &[&str] = &[
+    "/etc/terminfo",
+    "/lib/terminfo",
+    "/usr/share/terminfo",
+    "/usr/lib/terminfo",
+    "/usr/local/share/terminfo",
+    "/opt/homebrew/share/terminfo",
+];
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum TerminfoError {
+    BadMagic(u16),
+    Truncated,
+}
+
+impl Display for TerminfoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TerminfoError::BadMagic(magic) => write!(f, "not a terminfo file, magic {:#o}", magic),
+            TerminfoError::Truncated => f.write_str("truncated terminfo file"),
+        }
+    }
+}
+
+impl std::error::Error for TerminfoError {}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Terminfo {
+    /// Primary name first, then aliases and description
+    pub names: Vec<String>,
+    pub colors: Option<i32>,
+    ext_booleans: Vec<String>,
+    ext_numbers: Vec<(String, i32)>,
+    ext_strings: Vec<(String, Vec<u8>)>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], TerminfoError> {
+        let end = self.pos.checked_add(len).ok_or(TerminfoError::Truncated)?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or(TerminfoError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn i16(&mut self) -> Result<i16, TerminfoError> {
+        let b = self.bytes(2)?;
+        Ok(i16::from_le_bytes([b[0], b[1]]))
+    }
+
+    /// Counts and sizes, negative means absent
+    fn count(&mut self) -> Result<usize, TerminfoError> {
+        Ok(self.i16()?.max(0) as usize)
+    }
+
+    fn number(&mut self, wide: bool) -> Result<i32, TerminfoError> {
+        if wide {
+            let b = self.bytes(4)?;
+            Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        } else {
+            self.i16().map(i32::from)
+        }
+    }
+
+    fn numbers(&mut self, count: usize, wide: bool) -> Result<Vec<i32>, TerminfoError> {
+        (0..count).map(|_| self.number(wide)).collect()
+    }
+
+    fn offsets(&mut self, count: usize) -> Result<Vec<i16>, TerminfoError> {
+        (0..count).map(|_| self.i16()).collect()
+    }
+
+    /// Sections start at even offsets
+    fn align(&mut self) {
+        self.pos += self.pos % 2;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+/// NUL terminated string at `offset` of `table`, negative offset means absent or cancelled
+fn string_at(table: &[u8], offset: i16) -> Option<&[u8]> {
+    if offset < 0 {
+        return None;
+    }
+    let rest = table.get(offset as usize..)?;
+    let end = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+    Some(&rest[..end])
+}
+
+impl Terminfo {
+    pub fn parse(data: &[u8]) -> Result<Self, TerminfoError> {
+        let mut r = Reader { data, pos: 0 };
+        let magic = r.i16()? as u16;
+        let wide = match magic {
+            MAGIC_LEGACY => false,
+            MAGIC_32BIT => true,
+            _ => return Err(TerminfoError::BadMagic(magic)),
+        };
+        let names_size = r.count()?;
+        let bool_count = r.count()?;
+        let num_count = r.count()?;
+        let str_count = r.count()?;
+        let str_table_size = r.count()?;
+
+        let names = r.bytes(names_size)?;
+        let names = String::from_utf8_lossy(names.split(|b| *b == 0).next().unwrap_or_default());
+        let mut info = Terminfo {
+            names: names.split('|').map(String::from).collect(),
+            ..Terminfo::default()
+        };
+        r.bytes(bool_count)?;
+        r.align();
+        let numbers = r.numbers(num_count, wide)?;
+        info.colors = numbers.get(COLORS_INDEX).copied().filter(|n| *n >= 0);
+        r.offsets(str_count)?;
+        r.bytes(str_table_size)?;
+
+        r.align();
+        if !r.is_empty() {
+            info.parse_extended(&mut r, wide)?;
+        }
+        Ok(info)
+    }
+
+    fn parse_extended(&mut self, r: &mut Reader, wide: bool) -> Result<(), TerminfoError> {
+        let bool_count = r.count()?;
+        let num_count = r.count()?;
+        let str_count = r.count()?;
+        let _table_items = r.count()?;
+        let table_size = r.count()?;
+
+        let booleans = r.bytes(bool_count)?;
+        r.align();
+        let numbers = r.numbers(num_count, wide)?;
+        let str_offsets = r.offsets(str_count)?;
+        let name_offsets = r.offsets(bool_count + num_count + str_count)?;
+        let table = r.bytes(table_size)?;
+
+        // Names follow the last string value
+        let names_start = str_offsets
+            .iter()
+            .filter_map(|offset| string_at(table, *offset).map(|s| *offset as usize + s.len() + 1))
+            .max()
+            .unwrap_or(0);
+        let names_table = table.get(names_start..).unwrap_or_default();
+        let mut names = name_offsets.iter().map(|offset| {
+            string_at(names_table, *offset)
+                .map(|s| String::from_utf8_lossy(s).into_owned())
+                .ok_or(TerminfoError::Truncated)
+        });
+
+        for value in booleans {
+            let name = names.next().ok_or(TerminfoError::Truncated)??;
+            if *value == 1 {
+                self.ext_booleans.push(name);
+            }
+        }
+        for value in numbers {
+            let name = names.next().ok_or(TerminfoError::Truncated)??;
+            if value >= 0 {
+                self.ext_numbers.push((name, value));
+            }
+        }
+        for offset in str_offsets {
+            let name = names.next().ok_or(TerminfoError::Truncated)??;
+            if let Some(value) = string_at(table, offset) {
+                self.ext_strings.push((name, value.to_vec()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn name(&self) -> &str {
+        self.names.first().map_or("", String::as_str)
+    }
+
+    pub fn ext_boolean(&self, name: &str) -> bool {
+        self.ext_booleans.iter().any(|n| n == name)
+    }
+
+    pub fn ext_number(&self, name: &str) -> Option<i32> {
+        self.ext_numbers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| *value)
+    }
+
+    pub fn ext_string(&self, name: &str) -> Option<&[u8]> {
+        self.ext_strings
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// 24-bit colour, `RGB` can be boolean, number or string, `Tc` is the tmux extension
+    pub fn has_rgb(&self) -> bool {
+        self.ext_boolean("RGB")
+            || self.ext_number("RGB").is_some()
+            || self.ext_string("RGB").is_some()
+            || self.ext_boolean("Tc")
+            || (self.ext_string("setrgbf").is_some() && self.ext_string("setrgbb").is_some())
+            || self.colors.is_some_and(|n| n >= DIRECT_COLORS)
+    }
+
+    /// Load compiled entry of `term` from the standard search directories
+    pub fn load(term: &str) -> Option<Self> {
+        let path = find_in(term, &search_dirs())?;
+        let data = fs::read(&path).ok()?;
+        match Self::parse(&data) {
+            Ok(info) => Some(info),
+            Err(e) => {
+                log::debug!("Failed to parse {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+}
+
+/// Check if terminfo database has an entry of `term`
+pub fn exists(term: &str) -> bool {
+    find_in(term, &search_dirs()).is_some()
+}
+
+/// Same order as ncurses, `$TERMINFO`, `~/.terminfo`, `$TERMINFO_DIRS` then system directories
+///
+/// An empty entry in `$TERMINFO_DIRS` stands for the system directories.
+fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    dirs.extend(env::var_os("TERMINFO").map(PathBuf::from));
+    dirs.extend(dirs::home_dir().map(|home| home.join(".terminfo")));
+    let system = || SYSTEM_DIRS.iter().map(PathBuf::from);
+    match env::var_os("TERMINFO_DIRS") {
+        Some(list) => {
+            for dir in env::split_paths(&list) {
+                if dir.as_os_str().is_empty() {
+                    dirs.extend(system());
+                } else {
+                    dirs.push(dir);
+                }
+            }
+        }
+        None => dirs.extend(system()),
+    }
+    dirs
+}
+
+/// Entries are `<first letter>/<name>`, or `<hex of first letter>/<name>` on macOS
+pub fn find_in(term: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let first = term.chars().next()?;
+    if term.contains('/') || term.starts_with('.') {
+        return None;
+    }
+    let subdirs = [first.to_string(), format!("{:x}", u32::from(first))];
+    dirs.iter()
+        .flat_map(|dir| subdirs.iter().map(move |sub| dir.join(sub).join(term)))
+        .find(|path| Path::is_file(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("src/nmk/fixtures/terminfo")
+    }
+
+    fn fixture(term: &str) -> Terminfo {
+        let path = find_in(term, &[fixture_dir()]).unwrap();
+        Terminfo::parse(&fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_legacy_format() {
+        let info = fixture("dumb");
+        assert_eq!(info.name(), "dumb");
+        assert_eq!(info.colors, None);
+        assert!(!info.has_rgb());
+
+        // nmk-legacy.src compiled by tic -x
+        let info = fixture("nmk-legacy");
+        assert_eq!(
+            info.names,
+            vec!["nmk-legacy", "legacy format with extended capabilities"]
+        );
+        assert_eq!(info.colors, Some(256));
+        assert!(info.ext_boolean("Tc"));
+        assert!(info.ext_boolean("RGB"));
+        assert_eq!(
+            info.ext_string("setrgbf"),
+            Some(&b"\x1b[38;2;%p1%d;%p2%d;%p3%dm"[..])
+        );
+        assert!(info.has_rgb());
+    }
+
+    #[test]
+    fn test_32bit_format() {
+        // nmk-test.src, pairs#0x10000 forces 32-bit numbers, stored in hex directory
+        let info = fixture("nmk-test");
+        assert_eq!(info.colors, Some(256));
+        assert!(info.ext_boolean("Tc"));
+        assert!(!info.ext_boolean("RGB"));
+        assert!(info.has_rgb());
+
+        let info = fixture("xterm-256color");
+        assert_eq!(info.name(), "xterm-256color");
+        assert_eq!(info.colors, Some(256));
+        assert!(info.ext_boolean("XT"));
+        assert!(info.ext_string("kUP5").is_some());
+        assert!(!info.has_rgb());
+
+        let info = fixture("xterm-direct");
+        assert_eq!(info.colors, Some(DIRECT_COLORS));
+        assert!(info.ext_boolean("RGB"));
+        assert!(info.has_rgb());
+
+        let info = fixture("tmux-256color");
+        assert_eq!(info.colors, Some(256));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(Terminfo::parse(b""), Err(TerminfoError::Truncated));
+        assert_eq!(
+            Terminfo::parse(b"\x7fELF\x02\x01"),
+            Err(TerminfoError::BadMagic(0x457f))
+        );
+        let data = fs::read(fixture_dir().join("x/xterm-256color")).unwrap();
+        assert_eq!(Terminfo::parse(&data[..100]), Err(TerminfoError::Truncated));
+        // cut inside extended section
+        assert_eq!(
+            Terminfo::parse(&data[..data.len() - 10]),
+            Err(TerminfoError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_find_in() {
+        let dirs = [PathBuf::from("/nonexistent"), fixture_dir()];
+        assert_eq!(find_in("dumb", &dirs), Some(fixture_dir().join("d/dumb")));
+        assert_eq!(
+            find_in("nmk-test", &dirs),
+            Some(fixture_dir().join("6e/nmk-test"))
+        );
+        assert_eq!(find_in("xterm-kitty", &dirs), None);
+        assert_eq!(find_in("../x/xterm-256color", &dirs), None);
+        assert_eq!(find_in("", &dirs), None);
+    }
+}
@@ -0,0 +1,39 @@
+//! Helpers for unit tests of the library and the binaries
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+/// Empty directory in temp directory, removed with its content when dropped, even by a failing
+/// assertion
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `name` keeps directories of tests running at the same time apart
+    pub fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("nmk-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,172 @@
+//! Rendered config is reused while its inputs stay the same
+//!
+//! The key is a hash of the context, tmux version, platform and nmk build, plus size and
+//! modification time of user files which the renderer reads. It is written as the first line of
+//! the rendered file, so checking a cached config costs one small read.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::platform::PlatformType;
+
+use super::config::Context;
+use super::version::Version;
+
+const KEY_PREFIX: &str = "# nmk-cache-key: ";
+
+/// Identify the nmk binary, a rebuilt renderer may render differently
+pub fn build_id() -> String {
+    format!(
+        "{} {} {}",
+        env!("CARGO_PKG_VERSION"),
+        env!("EPOCHSECONDS"),
+        option_env!("GIT_SHORT_SHA").unwrap_or_default()
+    )
+}
+
+fn hash_file<H: Hasher>(path: &Path, h: &mut H) {
+    path.hash(h);
+    match path.metadata() {
+        Ok(metadata) => {
+            metadata.len().hash(h);
+            metadata.is_dir().hash(h);
+            let mtime = metadata.modified().ok();
+            mtime
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .hash(h);
+        }
+        Err(_) => "missing".hash(h),
+    }
+}
+
+/// Entries of `dir` down to `depth` levels, sorted so the order of read_dir doesn't matter
+fn hash_tree<H: Hasher>(dir: &Path, depth: usize, h: &mut H) {
+    hash_file(dir, h);
+    if depth == 0 {
+        return;
+    }
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        hash_tree(&path, depth - 1, h);
+    }
+}
+
+pub fn key(c: &Context, v: Version, platform: PlatformType, build_id: &str) -> String {
+    let mut h = DefaultHasher::new();
+    c.hash(&mut h);
+    v.hash(&mut h);
+    platform.hash(&mut h);
+    build_id.hash(&mut h);
+    for path in &c.key_binding_files {
+        hash_file(path, &mut h);
+    }
+    // overlays and their version-named subdirectories
+    for dir in &c.overlay_dirs {
+        hash_tree(dir, 2, &mut h);
+    }
+    format!("{:016x}", h.finish())
+}
+
+/// Key of a file by its path, size and modification time
+pub fn file_key(path: &Path) -> String {
+    let mut h = DefaultHasher::new();
+    hash_file(path, &mut h);
+    format!("{:016x}", h.finish())
+}
+
+/// Key which the config at `path` was rendered with
+pub fn read_key(path: &Path) -> Option<String> {
+    let mut line = String::new();
+    BufReader::new(File::open(path).ok()?)
+        .read_line(&mut line)
+        .ok()?;
+    line.strip_prefix(KEY_PREFIX)
+        .map(|key| key.trim_end().to_string())
+}
+
+pub fn is_fresh(path: &Path, key: &str) -> bool {
+    read_key(path).is_some_and(|cached| cached == key)
+}
+
+/// Write key and config, through a temporary file since another tmux may be reading it
+pub fn write(path: &Path, key: &str, contents: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension(format!("conf.{}.tmp", std::process::id()));
+    let mut f = File::create(&tmp)?;
+    writeln!(f, "{}{}", KEY_PREFIX, key)?;
+    f.write_all(contents)?;
+    drop(f);
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use crate::tmux::clipboard::Backend;
+
+    #[test]
+    fn test_key() {
+        let root = TempDir::new("cache-key");
+        fs::create_dir_all(root.join("tmux.d/3.2")).unwrap();
+        let mut c = Context {
+            key_binding_files: vec![root.join("tmux-keys.toml")],
+            overlay_dirs: vec![root.join("tmux.d")],
+            ..Context::default()
+        };
+        let key = |c: &Context| key(c, Version::V32, PlatformType::Linux, "1.0");
+        let original = key(&c);
+        assert_eq!(key(&c), original);
+        assert_ne!(
+            super::key(&c, Version::V31c, PlatformType::Linux, "1.0"),
+            original
+        );
+        assert_ne!(
+            super::key(&c, Version::V32, PlatformType::MacOs, "1.0"),
+            original
+        );
+        assert_ne!(
+            super::key(&c, Version::V32, PlatformType::Linux, "1.1"),
+            original
+        );
+
+        c.clipboard = Backend::Osc52;
+        assert_ne!(key(&c), original);
+        c.clipboard = Backend::Off;
+        assert_eq!(key(&c), original);
+
+        // user files are part of the key
+        fs::write(root.join("tmux-keys.toml"), "").unwrap();
+        let with_keys = key(&c);
+        assert_ne!(with_keys, original);
+        fs::write(root.join("tmux.d/3.2/popup.conf"), "").unwrap();
+        assert_ne!(key(&c), with_keys);
+    }
+
+    #[test]
+    fn test_read_write() {
+        let dir = TempDir::new("cache-read-write");
+        let path = dir.join("nmk.tmux.conf");
+        assert_eq!(read_key(&path), None);
+        write(&path, "0123abcd", b"# Tmux 3.2 configuration\n").unwrap();
+        assert_eq!(read_key(&path), Some("0123abcd".to_string()));
+        assert!(is_fresh(&path, "0123abcd"));
+        assert!(!is_fresh(&path, "ffff"));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# nmk-cache-key: 0123abcd\n# Tmux 3.2 configuration\n"
+        );
+        // rendered by older nmk
+        fs::write(&path, "# Tmux 3.2 configuration\n").unwrap();
+        assert_eq!(read_key(&path), None);
+    }
+}
@@ -0,0 +1,147 @@
+//! Features of tmux which depend on its version
+//!
+//! Rendering and starting tmux ask whether the version supports a feature instead of comparing
+//! versions, so the release which brought a feature is written down once.
+
+use super::version::Version;
+
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, strum::Display, strum::EnumString,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Feature {
+    /// `choose-tree -Z` zooms the pane while choosing
+    ChooseTreeZoom,
+    /// `display-menu`
+    DisplayMenu,
+    /// `new-session` without `-s` gets a name which `-A` attaches to next time
+    NewSessionDefaultName,
+    /// `display-popup`
+    Popup,
+    /// `{ }` nested in arguments of `display-menu` and `command-prompt`, as nmk menus are written
+    CommandBlocks,
+    /// `extended-keys` option, modified keys like `C-Enter` are passed to applications
+    ExtendedKeys,
+    /// `copy-command` option used by `copy-pipe` without argument
+    CopyCommand,
+    /// `terminal-features` option, which replaces most of `terminal-overrides`
+    TerminalFeatures,
+    /// `-e` of `new-session`, `new-window` and `split-window` to set environment of new panes
+    SpawnEnvironment,
+}
+
+/// Versions from `since` until before `until`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Range {
+    pub since: Version,
+    pub until: Option<Version>,
+}
+
+impl Range {
+    const fn since(since: Version) -> Self {
+        Range { since, until: None }
+    }
+
+    pub fn contains(&self, v: Version) -> bool {
+        v >= self.since && self.until.is_none_or(|until| v < until)
+    }
+}
+
+const FEATURES: &[(Feature, Range)] = &[
+    (Feature::ChooseTreeZoom, Range::since(Version::V27)),
+    (Feature::DisplayMenu, Range::since(Version::V30)),
+    (Feature::NewSessionDefaultName, Range::since(Version::V31)),
+    (Feature::Popup, Range::since(Version::V32)),
+    (Feature::CommandBlocks, Range::since(Version::V32)),
+    (Feature::ExtendedKeys, Range::since(Version::V32)),
+    (Feature::CopyCommand, Range::since(Version::V32)),
+    (Feature::TerminalFeatures, Range::since(Version::V32)),
+    (Feature::SpawnEnvironment, Range::since(Version::V32)),
+];
+
+impl Feature {
+    pub fn range(self) -> Range {
+        FEATURES
+            .iter()
+            .find(|(feature, _)| *feature == self)
+            .map(|(_, range)| *range)
+            .expect("every feature has a range")
+    }
+}
+
+impl Version {
+    pub fn supports(self, feature: Feature) -> bool {
+        feature.range().contains(self)
+    }
+}
+
+/// Versions where support of some feature changes, with the release before each
+pub fn boundaries() -> Vec<Version> {
+    let mut versions = vec![Version::OLDEST, Version::NEWEST];
+    for (_, range) in FEATURES {
+        let edges = std::iter::once(range.since).chain(range.until);
+        for edge in edges {
+            versions.push(edge);
+            let before = Version::RELEASES.iter().rev().find(|v| **v < edge);
+            versions.extend(before);
+        }
+    }
+    versions.sort();
+    versions.dedup();
+    versions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supports() {
+        assert!(!Version::V26.supports(Feature::ChooseTreeZoom));
+        assert!(Version::V27.supports(Feature::ChooseTreeZoom));
+        assert!(!Version::V29a.supports(Feature::DisplayMenu));
+        assert!(Version::V30.supports(Feature::DisplayMenu));
+        assert!(!Version::V30a.supports(Feature::NewSessionDefaultName));
+        assert!(Version::V31.supports(Feature::NewSessionDefaultName));
+        assert!(!Version::V31c.supports(Feature::Popup));
+        assert!(Version::V32.supports(Feature::Popup));
+        // pre-releases are not trusted with features of their release
+        let rc: Version = "3.2-rc3".parse().unwrap();
+        assert!(!rc.supports(Feature::CopyCommand));
+        // unknown newer versions have everything
+        let future: Version = "4.0".parse().unwrap();
+        assert!(FEATURES.iter().all(|(f, _)| future.supports(*f)));
+
+        let removed = Range {
+            since: Version::V30,
+            until: Some(Version::V32),
+        };
+        assert!(removed.contains(Version::V31c));
+        assert!(!removed.contains(Version::V32));
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(Feature::ChooseTreeZoom.to_string(), "choose-tree-zoom");
+        assert_eq!("popup".parse(), Ok(Feature::Popup));
+        assert!("popups".parse::<Feature>().is_err());
+    }
+
+    #[test]
+    fn test_boundaries() {
+        assert_eq!(
+            boundaries(),
+            vec![
+                Version::V26,
+                Version::V27,
+                Version::V29a,
+                Version::V30,
+                Version::V30a,
+                Version::V31,
+                Version::V31c,
+                Version::V32,
+                Version::V35a,
+            ]
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Validate rendered config against real tmux
+//!
+//! A throwaway server is started on a private socket without any config, then the config is
+//! sourced into it. tmux reports parse errors with line numbers and stops, otherwise it runs every
+//! command and reports the ones which fail, like unknown options or invalid styles.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::{Context, Error, ErrorKind};
+
+use super::config::{self, Context as ConfigContext};
+use super::version::Version;
+
+static SERVER_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// tmux server which is killed and cleaned up on drop
+struct Server {
+    tmux: PathBuf,
+    socket: PathBuf,
+}
+
+impl Server {
+    fn start(tmux: &Path) -> crate::Result<Self> {
+        let id = format!(
+            "nmk-check-{}-{}",
+            std::process::id(),
+            SERVER_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let server = Server {
+            tmux: tmux.to_path_buf(),
+            socket: env::temp_dir().join(id),
+        };
+        // `cat` keeps the session alive without depending on user's shell
+        let output = server
+            .command()
+            .args(["-f", "/dev/null", "new-session", "-d", "cat"])
+            .output()
+            .with_context(|| format!("failed to run {}", tmux.display()))?;
+        if !output.status.success() {
+            return Err(Error::msg(
+                ErrorKind::Other,
+                format!(
+                    "failed to start tmux server: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ));
+        }
+        Ok(server)
+    }
+
+    fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.tmux);
+        cmd.arg("-S").arg(&self.socket).env_remove("TMUX");
+        cmd.stdin(Stdio::null());
+        cmd
+    }
+
+    fn source(&self, config: &Path) -> crate::Result<Output> {
+        self.command()
+            .arg("source-file")
+            .arg(config)
+            .output()
+            .with_context(|| format!("failed to run {}", self.tmux.display()))
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self
+            .command()
+            .arg("kill-server")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        let _ = fs::remove_file(&self.socket);
+    }
+}
+
+/// Source config file into a throwaway server, returns errors which tmux reports
+pub fn check_file(tmux: &Path, config: &Path) -> crate::Result<Vec<String>> {
+    let server = Server::start(tmux)?;
+    let output = server.source(config)?;
+    let mut errors = Vec::new();
+    for out in &[&output.stdout, &output.stderr] {
+        let out = String::from_utf8_lossy(out);
+        errors.extend(
+            out.lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(String::from),
+        );
+    }
+    if errors.is_empty() && !output.status.success() {
+        return Ok(vec![format!("tmux exited with {}", output.status)]);
+    }
+    Ok(errors)
+}
+
+/// Render config for `v` and check it with `tmux`, which should be of the same version
+pub fn check(tmux: &Path, c: &ConfigContext, v: Version) -> crate::Result<Vec<String>> {
+    let mut buf = Vec::with_capacity(8192);
+    config::render(&mut buf, c, v)?;
+    let path = env::temp_dir().join(format!(
+        "nmk-check-{}-{}.tmux.conf",
+        std::process::id(),
+        SERVER_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&path, &buf).with_context(|| format!("failed to write {}", path.display()))?;
+    let result = check_file(tmux, &path);
+    let _ = fs::remove_file(&path);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_check_file() {
+        let tmux = match which::which("tmux") {
+            Ok(tmux) => tmux,
+            Err(_) => return eprintln!("tmux not found, skipped"),
+        };
+        let dir = TempDir::new("check");
+        let (good, bad) = (dir.join("good.conf"), dir.join("bad.conf"));
+        fs::write(&good, "set-option -g mouse on\n").unwrap();
+        fs::write(&bad, "set-option -g mouse on\nno-such-command\n").unwrap();
+        assert_eq!(check_file(&tmux, &good).unwrap(), Vec::<String>::new());
+        let errors = check_file(&tmux, &bad).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains(":2:"), "{:?}", errors);
+    }
+}
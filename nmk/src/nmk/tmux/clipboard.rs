@@ -0,0 +1,377 @@
+//! System clipboard integration of copy mode
+//!
+//! Local backends are commands which read the selection from stdin. Over SSH without a reachable
+//! display, tmux sends the selection to the outer terminal with OSC 52 instead.
+
+use std::env;
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::platform::PlatformType;
+
+use super::capability::Feature;
+use super::version::Version;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Backend {
+    Pbcopy,
+    WlCopy,
+    Xclip,
+    Xsel,
+    Win32yank,
+    ClipExe,
+    /// Let tmux set the clipboard of the outer terminal by escape sequence
+    Osc52,
+    /// Copy into tmux buffers only
+    Off,
+}
+
+const NAMES: &[(Backend, &str)] = &[
+    (Backend::Pbcopy, "pbcopy"),
+    (Backend::WlCopy, "wl-copy"),
+    (Backend::Xclip, "xclip"),
+    (Backend::Xsel, "xsel"),
+    (Backend::Win32yank, "win32yank"),
+    (Backend::ClipExe, "clip.exe"),
+    (Backend::Osc52, "osc52"),
+    (Backend::Off, "off"),
+];
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        NAMES
+            .iter()
+            .find(|(backend, _)| *backend == self)
+            .map(|(_, name)| *name)
+            .expect("every backend has a name")
+    }
+
+    fn bin(self) -> Option<&'static str> {
+        match self {
+            Backend::Pbcopy => Some("pbcopy"),
+            Backend::WlCopy => Some("wl-copy"),
+            Backend::Xclip => Some("xclip"),
+            Backend::Xsel => Some("xsel"),
+            // win32yank handles unicode and line endings better than clip.exe
+            Backend::Win32yank => Some("win32yank.exe"),
+            Backend::ClipExe => Some("clip.exe"),
+            Backend::Osc52 | Backend::Off => None,
+        }
+    }
+
+    /// Command which reads stdin into system clipboard
+    pub fn command(self) -> Option<&'static str> {
+        match self {
+            Backend::Xclip => Some("xclip -in -selection clipboard"),
+            Backend::Xsel => Some("xsel --input --clipboard"),
+            Backend::Win32yank => Some("win32yank.exe -i --crlf"),
+            _ => self.bin(),
+        }
+    }
+
+    pub fn render(self, w: &mut dyn Write, v: Version) -> io::Result<()> {
+        if self == Backend::Osc52 {
+            writeln!(w, "set-option -s set-clipboard on")?;
+            // tmux only knows the capability of xterm-like terminals
+            if v.supports(Feature::TerminalFeatures) {
+                writeln!(w, r#"set-option -sa terminal-features ",*:clipboard""#)?;
+            } else {
+                writeln!(
+                    w,
+                    r"set-option -sa terminal-overrides ',*:Ms=\E]52;%p1%s;%p2%s\007'"
+                )?;
+            }
+            return writeln!(
+                w,
+                "bind-key -T copy-mode-vi y send-keys -X copy-selection-and-cancel"
+            );
+        }
+        let cmd = match self.command() {
+            Some(cmd) => cmd,
+            None => return Ok(()),
+        };
+        if v.supports(Feature::CopyCommand) {
+            // copy-command also applies to mouse selection and other copy-pipe bindings
+            writeln!(w, r#"set-option -s copy-command "{}""#, cmd)?;
+            writeln!(
+                w,
+                "bind-key -T copy-mode-vi y send-keys -X copy-pipe-and-cancel"
+            )
+        } else {
+            writeln!(
+                w,
+                r#"bind-key -T copy-mode-vi y send-keys -X copy-pipe-and-cancel "{}""#,
+                cmd
+            )
+        }
+    }
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NAMES
+            .iter()
+            .find(|(_, name)| *name == s)
+            .map(|(backend, _)| *backend)
+            .ok_or_else(|| {
+                let names: Vec<_> = NAMES.iter().map(|(_, name)| *name).collect();
+                format!(
+                    "unknown clipboard {:?}, expect one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl<'de> Deserialize<'de> for Backend {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Backend {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// Display servers and remote session of the environment
+#[derive(Clone, Copy, Debug, Default)]
+struct Session {
+    wayland: bool,
+    x11: bool,
+    /// Only checked over SSH, a forwarded display is often gone while DISPLAY stays
+    x11_reachable: bool,
+    ssh: bool,
+}
+
+impl Session {
+    fn from_env() -> Self {
+        let is_set = |name| env::var_os(name).is_some_and(|v| !v.is_empty());
+        let display = env::var("DISPLAY").unwrap_or_default();
+        let ssh = is_set("SSH_CONNECTION") || is_set("SSH_TTY");
+        Session {
+            wayland: is_set("WAYLAND_DISPLAY"),
+            x11: !display.is_empty(),
+            x11_reachable: ssh && is_display_reachable(&display),
+            ssh,
+        }
+    }
+}
+
+/// Where X server of DISPLAY listens, `:0` is a socket, `localhost:10.0` is forwarded by SSH
+#[derive(Debug, Eq, PartialEq)]
+enum DisplayAddress {
+    Socket(PathBuf),
+    Tcp(SocketAddr),
+}
+
+/// Host names other than `localhost` are not resolved, this runs on every start and a slow
+/// resolver would hold up the login
+fn display_address(display: &str) -> Option<DisplayAddress> {
+    let (host, rest) = display.rsplit_once(':')?;
+    let number: u16 = rest.split('.').next()?.parse().ok()?;
+    let ip = match host {
+        "" | "unix" => {
+            return Some(DisplayAddress::Socket(
+                Path::new("/tmp/.X11-unix").join(format!("X{}", number)),
+            ))
+        }
+        "localhost" => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        _ => host.parse().ok()?,
+    };
+    Some(DisplayAddress::Tcp(SocketAddr::new(
+        ip,
+        6000u16.checked_add(number)?,
+    )))
+}
+
+fn is_display_reachable(display: &str) -> bool {
+    match display_address(display) {
+        Some(DisplayAddress::Socket(path)) => path.exists(),
+        Some(DisplayAddress::Tcp(addr)) => {
+            TcpStream::connect_timeout(&addr, Duration::from_millis(100)).is_ok()
+        }
+        None => false,
+    }
+}
+
+/// Pick the first usable backend in order of preference
+fn select<F>(platform: PlatformType, session: Session, is_available: F) -> Backend
+where
+    F: Fn(Backend) -> bool,
+{
+    let candidates: &[Backend] = match platform {
+        PlatformType::MacOs => return Backend::Pbcopy,
+        PlatformType::Wsl(_) => &[Backend::Win32yank, Backend::ClipExe],
+        _ => {
+            let mut candidates = Vec::new();
+            if session.wayland {
+                candidates.push(Backend::WlCopy);
+            }
+            if session.x11 && (!session.ssh || session.x11_reachable) {
+                candidates.extend_from_slice(&[Backend::Xclip, Backend::Xsel]);
+            }
+            let local = candidates
+                .into_iter()
+                .find(|backend| is_available(*backend));
+            return match local {
+                Some(backend) => backend,
+                None if session.ssh => Backend::Osc52,
+                None => Backend::Off,
+            };
+        }
+    };
+    candidates
+        .iter()
+        .copied()
+        .find(|backend| is_available(*backend))
+        .unwrap_or(Backend::Off)
+}
+
+/// Display tools are only candidates when their display is set, finding the binary is enough
+fn is_available(backend: Backend) -> bool {
+    backend.bin().is_none_or(|bin| which::which(bin).is_ok())
+}
+
+/// Detect the backend of current environment
+pub fn detect() -> Backend {
+    select(PlatformType::detect(), Session::from_env(), is_available)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Wsl;
+
+    fn render(backend: Backend, v: Version) -> String {
+        let mut buf = Vec::new();
+        backend.render(&mut buf, v).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_select() {
+        let all = |_| true;
+        let none = |_| false;
+        let local = Session::default();
+        let ssh = Session {
+            ssh: true,
+            ..Session::default()
+        };
+        let x11 = Session {
+            x11: true,
+            ..Session::default()
+        };
+        let wayland = Session {
+            wayland: true,
+            x11: true,
+            ..Session::default()
+        };
+        assert_eq!(select(PlatformType::MacOs, ssh, none), Backend::Pbcopy);
+        assert_eq!(select(PlatformType::Linux, wayland, all), Backend::WlCopy);
+        assert_eq!(
+            select(PlatformType::Linux, wayland, |b| b != Backend::WlCopy),
+            Backend::Xclip
+        );
+        assert_eq!(select(PlatformType::Linux, x11, all), Backend::Xclip);
+        assert_eq!(
+            select(PlatformType::Arch, x11, |b| b == Backend::Xsel),
+            Backend::Xsel
+        );
+        assert_eq!(select(PlatformType::Linux, x11, none), Backend::Off);
+        // tools without display are useless
+        assert_eq!(select(PlatformType::Linux, local, all), Backend::Off);
+        assert_eq!(select(PlatformType::Alpine, ssh, all), Backend::Osc52);
+        // X11 forwarding is preferred over OSC 52
+        let forwarded = Session {
+            x11: true,
+            x11_reachable: true,
+            ..ssh
+        };
+        assert_eq!(select(PlatformType::Linux, forwarded, all), Backend::Xclip);
+        assert_eq!(select(PlatformType::Linux, forwarded, none), Backend::Osc52);
+        // DISPLAY left over from a closed forwarding
+        let gone = Session {
+            x11_reachable: false,
+            ..forwarded
+        };
+        assert_eq!(select(PlatformType::Linux, gone, all), Backend::Osc52);
+        for wsl in [Wsl::V1, Wsl::V2].iter().copied() {
+            let platform = PlatformType::Wsl(wsl);
+            assert_eq!(select(platform, x11, all), Backend::Win32yank);
+            assert_eq!(
+                select(platform, local, |b| b == Backend::ClipExe),
+                Backend::ClipExe
+            );
+            // xclip doesn't work without X server, don't bother
+            assert_eq!(select(platform, x11, |b| b == Backend::Xclip), Backend::Off);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        for (backend, name) in NAMES {
+            assert_eq!(name.parse::<Backend>(), Ok(*backend));
+            assert_eq!(backend.to_string(), *name);
+        }
+        assert!("clipboard".parse::<Backend>().is_err());
+        assert!("".parse::<Backend>().is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render(Backend::Xclip, Version::V31c),
+            "bind-key -T copy-mode-vi y send-keys -X copy-pipe-and-cancel \"xclip -in -selection clipboard\"\n"
+        );
+        assert_eq!(
+            render(Backend::WlCopy, Version::V32),
+            "set-option -s copy-command \"wl-copy\"\nbind-key -T copy-mode-vi y send-keys -X copy-pipe-and-cancel\n"
+        );
+
+        let old = render(Backend::Osc52, Version::V26);
+        assert!(old.contains("set-option -s set-clipboard on\n"));
+        assert!(old.contains("terminal-overrides ',*:Ms=\\E]52;"));
+        assert!(old.contains("copy-selection-and-cancel\n"));
+        assert!(!old.contains("copy-pipe"));
+        let new = render(Backend::Osc52, Version::V32);
+        assert!(new.contains("terminal-features \",*:clipboard\"\n"));
+        assert!(!new.contains("terminal-overrides"));
+
+        assert_eq!(render(Backend::Off, Version::V32), "");
+    }
+
+    #[test]
+    fn test_display_address() {
+        let socket = |n: &str| Some(DisplayAddress::Socket(Path::new("/tmp/.X11-unix").join(n)));
+        assert_eq!(display_address(":0"), socket("X0"));
+        assert_eq!(display_address(":1.0"), socket("X1"));
+        assert_eq!(display_address("unix:2"), socket("X2"));
+        let tcp = |addr: &str| Some(DisplayAddress::Tcp(addr.parse().unwrap()));
+        assert_eq!(display_address("localhost:10.0"), tcp("127.0.0.1:6010"));
+        assert_eq!(display_address("192.168.1.5:0"), tcp("192.168.1.5:6000"));
+        assert_eq!(display_address("::1:1"), tcp("[::1]:6001"));
+        // never resolved
+        assert_eq!(display_address("workstation.example.com:0"), None);
+        assert_eq!(display_address(""), None);
+        assert_eq!(display_address(":x"), None);
+        assert!(!is_display_reachable(""));
+    }
+}
@@ -1,69 +1,39 @@
 use std::io;
 use std::io::Write;
 use std::path::PathBuf;
-use std::process::{Command, Stdio};
 
 use indoc::indoc;
 
 use crate::config::on_off;
 use crate::env_name::NMK_HOME;
-use crate::platform::is_mac;
 
+use super::capability::Feature;
+use super::clipboard::Backend;
+use super::keybinding::KeyTable;
+use super::overlay;
+use super::theme::{ColorDepth, Theme};
 use super::version::Version;
 
 const COPY_MODE: &str = "copy-mode -u";
 const COPY_MODE_BOTTOM_EXIT: &str = "copy-mode -eu";
-const CWD: &str = "#{pane_current_path}";
-const F12_TABLE: &str = "F12";
-const LAST_SESSION: &str = "switch-client -l";
-const NEXT_PANE: &str = r#"select-pane -t :.+ \; display-panes"#;
 const NO_ENTER_COPY_MODE: &str = r##"#{?pane_in_mode,1,}#{?alternate_on,1,}"##;
 
 pub fn render(w: &mut dyn Write, c: &Context, v: Version) -> io::Result<()> {
     writeln!(w, "# Tmux {} configuration", v)?;
-    section(w, c, "Tmux Options", render_options)?;
-    section(w, c, "Prefix Keys", |w, _| {
-        writeln!(w, "bind-key -r C-b send-prefix")
-    })?;
-    writeln!(w, "bind-key -r o {}", NEXT_PANE)?;
-    writeln!(w, "bind-key -r C-o rotate-window")?;
-    writeln!(w, "bind-key C-c command-prompt")?;
-    writeln!(w, "bind-key C-l {}", LAST_SESSION)?;
-    #[allow(clippy::write_literal)]
-    writeln!(w, "{}", "bind-key C-t display-message '#{pane_tty}'")?;
-    section(w, c, "Function Key Binding", |w, _| {
-        for n in 1..=12 {
-            writeln!(w, "bind-key -n S-F{n} send-keys F{n}", n = n)?;
+    section(w, c, "Tmux Options", |w, c| {
+        render_options(w, c)?;
+        // Only applications which ask for modified keys like C-Enter get them
+        if v.supports(Feature::ExtendedKeys) {
+            writeln!(w, "set-option -s extended-keys on")?;
         }
-        writeln!(w, "bind-key -n F1 {}", NEXT_PANE)?;
-        writeln!(w, "bind-key -n F2 last-window")?;
-        writeln!(w, "bind-key -n F3 previous-window")?;
-        writeln!(w, "bind-key -n F4 next-window")?;
-        writeln!(w, "bind-key -n F5 resize-pane -Z")?;
-        writeln!(w, "bind-key -n F6 {}", choose_tree(v))?;
-        writeln!(w, "bind-key -n F8 switch-client -n")
+        Ok(())
     })?;
-    section(w, c, "F12 Key Table", |w, _| {
-        writeln!(w, "bind-key F12 send-keys F12")?;
-        writeln!(w, "bind-key -n F12 switch-client -T {}", F12_TABLE)?;
-        for n in 1..=9 {
-            writeln!(
-                w,
-                "bind-key -T {} {n} select-window -t {n}",
-                F12_TABLE,
-                n = n
-            )?;
-        }
-        for n in 1..=11 {
-            writeln!(w, "bind-key -T {} F{n} send-keys F{n}", F12_TABLE, n = n)?;
-        }
-        writeln!(w, "bind-key -T {} F12 detach-client", F12_TABLE)?;
-        writeln!(w, "bind-key -T {} -r Space next-layout", F12_TABLE)
+    section(w, c, "Key Bindings", |w, c| {
+        KeyTable::load(&c.key_binding_files).render(w, v)
     })?;
-    section(w, c, "Pane Current Path", pane_current_path)?;
-    section(w, c, "Copy Mode", |w, _| {
+    section(w, c, "Copy Mode", |w, c| {
         writeln!(w, "bind-key C-u {}", COPY_MODE)?;
-        copy_to_system_clipboard(w)?;
+        c.clipboard.render(w, v)?;
         // PageUp and PageDown special behaviors
         //  If the condition is match, PageUp should enter copy mode
         //  see https://www.reddit.com/r/tmux/comments/3paqoi/tmux_21_has_been_released/
@@ -76,13 +46,20 @@ pub fn render(w: &mut dyn Write, c: &Context, v: Version) -> io::Result<()> {
     })?;
     // Colors
     section(w, c, "Colors", |w, c| {
-        let color_config = if c.support_256_color {
-            include_str!("256color.conf")
-        } else {
-            include_str!("8color.conf")
-        };
-        writeln!(w, "{}", color_config)
-    })
+        c.theme
+            .render(w, c.color_depth(), c.truecolor_term.as_deref(), v)
+    })?;
+    for overlay in overlay::collect(&c.overlay_dirs, v) {
+        let name = format!("Overlay {}", overlay.path.display());
+        section(w, c, &name, |w, _| {
+            write!(w, "{}", overlay.contents)?;
+            if !overlay.contents.ends_with('\n') {
+                writeln!(w)?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(())
 }
 
 fn render_options(w: &mut dyn Write, c: &Context) -> io::Result<()> {
@@ -93,10 +70,14 @@ fn render_options(w: &mut dyn Write, c: &Context) -> io::Result<()> {
         set-option -g status-keys emacs
         set-option -g status-left-length 20
         set-option -g status-right-length 60
-        set-option -g status-right "#{?client_prefix,^B ,}'#[fg=colour51]#{=40:pane_title}#[default]' %H:%M %Z %a, %d"
-        set-window-option -g mode-keys vi
     "##};
     write!(w, "{}", options)?;
+    writeln!(
+        w,
+        r##"set-option -g status-right "#{{?client_prefix,^B ,}}'{}#{{=40:pane_title}}#[default]' #(nmk status right --pane #{{pane_id}}) %H:%M %Z %a, %d""##,
+        c.theme.title_style(c.color_depth())
+    )?;
+    writeln!(w, "set-window-option -g mode-keys vi")?;
     writeln!(
         w,
         r#"set-option -g default-shell "{}""#,
@@ -134,55 +115,6 @@ fn write_end_section(c: &mut dyn Write, name: &str) -> io::Result<()> {
     writeln!(c, "# {:-^120}", label)
 }
 
-fn pane_current_path(w: &mut dyn Write, _: &Context) -> io::Result<()> {
-    let key_binding = &[
-        ("%", "split-window -h "),
-        ("|", "split-window -h "),
-        ("_", "split-window"),
-        ("c", "new-window"),
-        ("'\"'", "split-window"),
-    ];
-    for (key, binding) in key_binding {
-        writeln!(w, "bind-key {} {} -c '{}'", key, binding, CWD)?;
-    }
-    writeln!(
-        w,
-        r#"bind-key C command-prompt "new-session -c '{}' -s '%%'""#,
-        CWD
-    )
-}
-
-fn choose_tree(v: Version) -> String {
-    let mut vec = Vec::with_capacity(4);
-    vec.extend_from_slice(&["choose-tree", "-s"]);
-    if v >= Version::V27 {
-        vec.push("-Z");
-    }
-    vec.join(" ")
-}
-
-fn is_system_clipboard_available() -> bool {
-    let mut cmd = Command::new("xclip");
-    cmd.arg("-o").stdout(Stdio::null()).stderr(Stdio::null());
-    cmd.output().map_or(false, |output| output.status.success())
-}
-
-fn copy_to_system_clipboard(w: &mut dyn Write) -> io::Result<()> {
-    fn write_bind_config(w: &mut dyn Write, cmd: &str) -> io::Result<()> {
-        writeln!(
-            w,
-            r#"bind-key -T copy-mode-vi y send-keys -X copy-pipe-and-cancel "{}""#,
-            cmd
-        )
-    }
-    if is_mac() {
-        write_bind_config(w, "pbcopy")?;
-    } else if is_system_clipboard_available() {
-        write_bind_config(w, "xclip -selection clipboard")?;
-    }
-    Ok(())
-}
-
 fn half_pageup_pagedown(w: &mut dyn Write) -> io::Result<()> {
     let key_binding = &[("PageUp", "halfpage-up"), ("PageDown", "halfpage-down")];
     key_binding.iter().try_for_each(|(key, binding)| {
@@ -194,11 +126,32 @@ fn half_pageup_pagedown(w: &mut dyn Write) -> io::Result<()> {
     })
 }
 
+#[derive(Hash)]
 pub struct Context {
     pub detach_on_destroy: bool,
     pub support_256_color: bool,
+    /// Pattern of terminals which support 24-bit colour, e.g. `xterm-kitty`
+    pub truecolor_term: Option<String>,
+    pub theme: Theme,
+    pub clipboard: Backend,
     pub default_shell: PathBuf,
     pub default_term: String,
+    /// User key binding files, merged in order over the built-in table
+    pub key_binding_files: Vec<PathBuf>,
+    /// `tmux.d` directories, appended in order after the rendered config
+    pub overlay_dirs: Vec<PathBuf>,
+}
+
+impl Context {
+    pub fn color_depth(&self) -> ColorDepth {
+        if self.truecolor_term.is_some() {
+            ColorDepth::TrueColor
+        } else if self.support_256_color {
+            ColorDepth::Color256
+        } else {
+            ColorDepth::Ansi8
+        }
+    }
 }
 
 impl Default for Context {
@@ -206,8 +159,70 @@ impl Default for Context {
         Context {
             detach_on_destroy: false,
             support_256_color: false,
+            truecolor_term: None,
+            theme: Theme::default_theme(),
+            clipboard: Backend::Off,
             default_shell: PathBuf::from("/bin/zsh"),
             default_term: String::from("screen"),
+            key_binding_files: Vec::new(),
+            overlay_dirs: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+    use crate::tmux::capability;
+
+    /// Compare with `snapshots/<version>.conf`, `NMK_UPDATE_SNAPSHOTS=1` writes them instead
+    #[test]
+    fn test_snapshot_at_version_boundaries() {
+        let c = Context {
+            support_256_color: true,
+            truecolor_term: Some(String::from("xterm-kitty")),
+            clipboard: Backend::Xclip,
+            ..Context::default()
+        };
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/nmk/tmux/snapshots");
+        let update = env::var_os("NMK_UPDATE_SNAPSHOTS").is_some();
+        for v in capability::boundaries() {
+            let mut buf = Vec::new();
+            render(&mut buf, &c, v).unwrap();
+            let rendered = String::from_utf8(buf).unwrap();
+            let path = dir.join(format!("{}.conf", v));
+            if update {
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(&path, &rendered).unwrap();
+                continue;
+            }
+            let expected =
+                fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            assert_eq!(rendered, expected, "snapshot of tmux {} differs", v);
+        }
+    }
+
+    /// Themes are downgraded to 8 colours on purpose, the old `8color.conf` had no colour at all
+    #[test]
+    fn test_snapshot_8_colors() {
+        let c = Context {
+            clipboard: Backend::Xclip,
+            ..Context::default()
+        };
+        let mut buf = Vec::new();
+        render(&mut buf, &c, Version::V32).unwrap();
+        let rendered = String::from_utf8(buf).unwrap();
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/nmk/tmux/snapshots/8color.conf");
+        if env::var_os("NMK_UPDATE_SNAPSHOTS").is_some() {
+            fs::write(&path, &rendered).unwrap();
+            return;
         }
+        let expected =
+            fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        assert_eq!(rendered, expected, "8 colour snapshot differs");
     }
 }
@@ -0,0 +1,346 @@
+//! Key bindings as data, see keybindings.toml for the format
+
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Deserializer};
+
+use crate::error::Context;
+
+use super::capability::Feature;
+use super::version::Version;
+
+const BUILT_IN: &str = include_str!("keybindings.toml");
+const PREFIX_TABLE: &str = "prefix";
+const ROOT_TABLE: &str = "root";
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Binding {
+    pub key: String,
+    #[serde(default = "prefix_table")]
+    pub table: String,
+    #[serde(default)]
+    pub repeat: bool,
+    pub command: String,
+    #[serde(default, deserialize_with = "deserialize_version")]
+    pub min_version: Option<Version>,
+    #[serde(default, deserialize_with = "deserialize_feature")]
+    pub requires: Option<Feature>,
+}
+
+impl Binding {
+    fn applies(&self, v: Version) -> bool {
+        self.min_version.is_none_or(|min| v >= min)
+            && self.requires.is_none_or(|feature| v.supports(feature))
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Unbind {
+    pub key: String,
+    #[serde(default = "prefix_table")]
+    pub table: String,
+}
+
+fn prefix_table() -> String {
+    PREFIX_TABLE.to_string()
+}
+
+fn deserialize_version<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Version>, D::Error> {
+    let s = String::deserialize(d)?;
+    Version::from_version(&s)
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("unknown tmux version {:?}", s)))
+}
+
+fn deserialize_feature<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Feature>, D::Error> {
+    let s = String::deserialize(d)?;
+    s.parse()
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("unknown tmux feature {:?}", s)))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyFile {
+    #[serde(default)]
+    binding: Vec<Binding>,
+    #[serde(default)]
+    unbind: Vec<Unbind>,
+}
+
+/// Bindings in order, plus keys which user wants removed
+#[derive(Debug)]
+pub struct KeyTable {
+    bindings: Vec<Binding>,
+    unbinds: Vec<Unbind>,
+}
+
+impl KeyTable {
+    pub fn built_in() -> Self {
+        let file: KeyFile = toml::from_str(BUILT_IN).expect("invalid built-in keybindings.toml");
+        Self {
+            bindings: file.binding,
+            unbinds: file.unbind,
+        }
+    }
+
+    /// Built-in bindings merged with user files in order, broken files are skipped with warning
+    pub fn load<P: AsRef<Path>>(user_files: &[P]) -> Self {
+        let mut table = Self::built_in();
+        for path in user_files.iter().map(AsRef::as_ref).filter(|p| p.exists()) {
+            if let Err(e) = table.merge_file(path) {
+                log::warn!("{:#}", e);
+            }
+        }
+        table
+    }
+
+    fn merge_file(&mut self, path: &Path) -> crate::Result<()> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        self.merge_str(&data)
+            .with_context(|| format!("invalid key bindings {}", path.display()))
+    }
+
+    fn merge_str(&mut self, data: &str) -> crate::Result<()> {
+        let file: KeyFile = toml::from_str(data)?;
+        self.merge(file);
+        Ok(())
+    }
+
+    /// User binding replaces the one with the same key, table and condition, and unbind
+    /// removes every binding of the key
+    fn merge(&mut self, file: KeyFile) {
+        for unbind in file.unbind {
+            self.bindings
+                .retain(|b| !(b.key == unbind.key && b.table == unbind.table));
+            self.unbinds.push(unbind);
+        }
+        for binding in file.binding {
+            let existing = self.bindings.iter_mut().find(|b| {
+                b.key == binding.key
+                    && b.table == binding.table
+                    && b.min_version == binding.min_version
+                    && b.requires == binding.requires
+            });
+            match existing {
+                Some(existing) => *existing = binding,
+                None => {
+                    // A user file may bind again what an earlier one removed
+                    self.unbinds
+                        .retain(|u| !(u.key == binding.key && u.table == binding.table));
+                    self.bindings.push(binding)
+                }
+            }
+        }
+    }
+
+    /// Bindings which apply to `v`, the last applicable binding of a key wins
+    fn resolve(&self, v: Version) -> Vec<&Binding> {
+        let applicable: Vec<&Binding> = self.bindings.iter().filter(|b| b.applies(v)).collect();
+        applicable
+            .iter()
+            .enumerate()
+            .filter(|(i, b)| {
+                !applicable[i + 1..]
+                    .iter()
+                    .any(|later| later.key == b.key && later.table == b.table)
+            })
+            .map(|(_, b)| *b)
+            .collect()
+    }
+
+    pub fn render(&self, w: &mut dyn Write, v: Version) -> io::Result<()> {
+        for unbind in &self.unbinds {
+            writeln!(
+                w,
+                "unbind-key {}{}",
+                table_flag(&unbind.table),
+                quote_key(&unbind.key)
+            )?;
+        }
+        for binding in self.resolve(v) {
+            let repeat = if binding.repeat { "-r " } else { "" };
+            writeln!(
+                w,
+                "bind-key {}{}{} {}",
+                repeat,
+                table_flag(&binding.table),
+                quote_key(&binding.key),
+                binding.command
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Keys are written plain in files, like `"` or `#`, and quoted here when tmux would read them
+/// as syntax. Keys quoted by hand are kept as they are.
+fn quote_key(key: &str) -> Cow<'_, str> {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_%|+=,.:/!?@*^&()<>[]".contains(c);
+    let quoted_by_hand = key.len() >= 2
+        && ((key.starts_with('\'') && key.ends_with('\''))
+            || (key.starts_with('"') && key.ends_with('"')));
+    if key.chars().all(is_plain) || quoted_by_hand {
+        Cow::Borrowed(key)
+    } else if !key.contains('\'') {
+        Cow::Owned(format!("'{}'", key))
+    } else {
+        let escaped: String = key
+            .chars()
+            .flat_map(|c| match c {
+                '"' | '\\' | '$' => vec!['\\', c],
+                _ => vec![c],
+            })
+            .collect();
+        Cow::Owned(format!("\"{}\"", escaped))
+    }
+}
+
+fn table_flag(table: &str) -> String {
+    match table {
+        PREFIX_TABLE => String::new(),
+        ROOT_TABLE => "-n ".to_string(),
+        _ => format!("-T {} ", table),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(table: &KeyTable, v: Version) -> String {
+        let mut buf = Vec::new();
+        table.render(&mut buf, v).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_built_in() {
+        let table = KeyTable::built_in();
+        let old = render(&table, Version::V26);
+        assert!(old.contains("bind-key -r C-b send-prefix\n"));
+        assert!(old.contains("bind-key -n F6 choose-tree -s\n"));
+        assert!(!old.contains("choose-tree -s -Z"));
+        assert!(old.contains("bind-key -r -T F12 Space next-layout\n"));
+        assert!(old.contains("bind-key '\"' split-window -c '#{pane_current_path}'\n"));
+        assert!(!old.contains("unbind-key"));
+        assert!(!old.contains("display-menu"));
+        assert!(!old.contains("display-popup"));
+
+        // menus are kept from 3.0 and 3.1, which parse `{ }` of their items differently
+        let menu = render(&table, Version::V31c);
+        assert!(!menu.contains("display-menu"));
+        assert!(!menu.contains("display-popup"));
+
+        let new = render(&table, Version::V32);
+        assert!(new.contains("bind-key -n F6 choose-tree -s -Z\n"));
+        assert_eq!(new.matches("-n F6 ").count(), 1);
+        // function keys in root table stay with applications like mc and htop
+        assert!(new.contains("bind-key F7 display-popup -E "));
+        assert!(new.contains("bind-key F9 display-menu "));
+        assert!(new.contains("bind-key -n MouseDown3Pane if-shell "));
+        assert!(!new.contains("-n F7 ") && !new.contains("-n F9 "));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut table = KeyTable::built_in();
+        table
+            .merge_str(
+                r#"
+                [[binding]]
+                key = "F2"
+                table = "root"
+                command = "new-window"
+
+                [[binding]]
+                key = "M-Left"
+                table = "root"
+                command = "previous-window"
+
+                [[unbind]]
+                key = "F3"
+                table = "root"
+
+                [[unbind]]
+                key = "C-z"
+                "#,
+            )
+            .unwrap();
+        let rendered = render(&table, Version::V32);
+        assert!(rendered.contains("bind-key -n F2 new-window\n"));
+        assert!(!rendered.contains("last-window"));
+        assert!(rendered.contains("bind-key -n M-Left previous-window\n"));
+        assert!(rendered.contains("unbind-key -n F3\n"));
+        assert!(!rendered.contains("bind-key -n F3 "));
+        assert!(rendered.contains("unbind-key C-z\n"));
+        assert!(rendered.starts_with("unbind-key"));
+
+        // user can override only newer tmux
+        table
+            .merge_str(
+                r#"
+                [[binding]]
+                key = "F6"
+                table = "root"
+                command = "choose-tree -w -Z"
+                requires = "choose-tree-zoom"
+                "#,
+            )
+            .unwrap();
+        assert!(render(&table, Version::V26).contains("bind-key -n F6 choose-tree -s\n"));
+        assert!(render(&table, Version::V32).contains("bind-key -n F6 choose-tree -w -Z\n"));
+
+        // bind again after unbind
+        table
+            .merge_str("[[binding]]\nkey = \"F3\"\ntable = \"root\"\ncommand = \"kill-pane\"")
+            .unwrap();
+        let rendered = render(&table, Version::V32);
+        assert!(rendered.contains("bind-key -n F3 kill-pane\n"));
+        assert!(!rendered.contains("unbind-key -n F3"));
+    }
+
+    #[test]
+    fn test_invalid_user_file() {
+        let mut table = KeyTable::built_in();
+        let count = table.bindings.len();
+        assert!(table.merge_str("[[binding]]\nkey = \"F1\"").is_err());
+        assert!(table
+            .merge_str("[[binding]]\nkey = \"F1\"\ncommand = \"x\"\nmin-version = \"newest\"")
+            .is_err());
+        assert!(table
+            .merge_str("[[binding]]\nkey = \"F1\"\ncommand = \"x\"\nrequires = \"menu\"")
+            .is_err());
+        assert!(table.merge_str("[[bind]]\nkey = \"F1\"").is_err());
+        assert_eq!(table.bindings.len(), count);
+    }
+
+    #[test]
+    fn test_quote_key() {
+        for plain in &["C-b", "M-Left", "%", "|", "_", "F12", "MouseDown3Pane"] {
+            assert_eq!(quote_key(plain), *plain);
+        }
+        assert_eq!(quote_key("\""), "'\"'");
+        assert_eq!(quote_key("#"), "'#'");
+        assert_eq!(quote_key("M-;"), "'M-;'");
+        assert_eq!(quote_key("{"), "'{'");
+        assert_eq!(quote_key("'"), "\"'\"");
+        assert_eq!(quote_key("C-'"), "\"C-'\"");
+        // written the old way
+        assert_eq!(quote_key("'\"'"), "'\"'");
+
+        let mut table = KeyTable::built_in();
+        table
+            .merge_str("[[unbind]]\nkey = '#'\n[[binding]]\nkey = ';'\ncommand = 'last-pane'")
+            .unwrap();
+        let rendered = render(&table, Version::V32);
+        assert!(rendered.contains("unbind-key '#'\n"));
+        assert!(rendered.contains("bind-key ';' last-pane\n"));
+    }
+}
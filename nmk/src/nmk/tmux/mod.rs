@@ -1,2 +1,10 @@
+pub mod cache;
+pub mod capability;
+pub mod check;
+pub mod clipboard;
 pub mod config;
+pub mod keybinding;
+pub mod overlay;
+pub mod theme;
 pub mod version;
+pub mod workspace;
@@ -0,0 +1,240 @@
+//! User tmux configuration appended after the rendered one
+//!
+//! Overlays are `*.conf` files in `tmux.d` directories. A file applies to every tmux version
+//! unless its first line is a version condition:
+//!
+//! ```text
+//! # nmk: tmux >= 3.0, < 3.2
+//! ```
+//!
+//! Files in a version-named subdirectory like `tmux.d/3.2/` only apply to that version and later.
+
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::version::Version;
+
+const CONDITION_PREFIX: &str = "# nmk: tmux ";
+
+pub struct Overlay {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Op {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Requirement {
+    op: Op,
+    version: Version,
+}
+
+impl Requirement {
+    fn matches(&self, v: Version) -> bool {
+        let ordering = v.cmp(&self.version);
+        match self.op {
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ge => ordering != Ordering::Less,
+            Op::Gt => ordering == Ordering::Greater,
+        }
+    }
+}
+
+/// Parse `>= 3.0, < 3.2`, every requirement must match
+fn parse_condition(s: &str) -> Result<Vec<Requirement>, String> {
+    s.split(',')
+        .map(|part| {
+            let part = part.trim();
+            let (op, version) = [
+                (">=", Op::Ge),
+                ("<=", Op::Le),
+                ("==", Op::Eq),
+                (">", Op::Gt),
+                ("<", Op::Lt),
+                ("=", Op::Eq),
+            ]
+            .iter()
+            .find_map(|(prefix, op)| part.strip_prefix(prefix).map(|rest| (*op, rest)))
+            .ok_or_else(|| format!("expect one of >=, >, <=, <, = in {:?}", part))?;
+            let version = Version::from_version(version.trim())
+                .map_err(|_| format!("unknown tmux version in {:?}", part))?;
+            Ok(Requirement { op, version })
+        })
+        .collect()
+}
+
+/// Check the condition header of an overlay, file without header always applies
+fn is_applicable(contents: &str, v: Version) -> Result<bool, String> {
+    let first_line = contents.lines().next().unwrap_or_default();
+    match first_line.strip_prefix(CONDITION_PREFIX) {
+        Some(condition) => Ok(parse_condition(condition)?
+            .iter()
+            .all(|requirement| requirement.matches(v))),
+        None => Ok(true),
+    }
+}
+
+/// `*.conf` files of `dir` sorted by name
+fn conf_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "conf") && path.is_file())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Version-named subdirectories of `dir` which apply to `v`, oldest first
+fn version_dirs(dir: &Path, v: Version) -> Vec<PathBuf> {
+    let mut dirs: Vec<(Version, PathBuf)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?;
+            match Version::from_version(name) {
+                Ok(version) => Some((version, path)),
+                Err(_) => {
+                    log::warn!("Ignored tmux overlay directory {:?}: unknown version", path);
+                    None
+                }
+            }
+        })
+        .filter(|(version, _)| v >= *version)
+        .collect();
+    dirs.sort();
+    dirs.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Collect overlays of `dirs` in order, later ones can override earlier ones
+///
+/// Broken overlays are skipped with a warning, they should not prevent tmux from starting.
+pub fn collect(dirs: &[PathBuf], v: Version) -> Vec<Overlay> {
+    let mut overlays = Vec::new();
+    for dir in dirs {
+        let files = conf_files(dir).into_iter().chain(
+            version_dirs(dir, v)
+                .into_iter()
+                .flat_map(|d| conf_files(&d)),
+        );
+        for path in files {
+            let contents = match fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    log::warn!("Failed to read tmux overlay {:?}: {}", path, e);
+                    continue;
+                }
+            };
+            match is_applicable(&contents, v) {
+                Ok(true) => overlays.push(Overlay { path, contents }),
+                Ok(false) => log::debug!("Skipped tmux overlay {:?} for tmux {}", path, v),
+                Err(e) => log::warn!("Ignored tmux overlay {:?}: {}", path, e),
+            }
+        }
+    }
+    overlays
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_parse_condition() {
+        assert_eq!(
+            parse_condition(">= 3.0, < 3.2"),
+            Ok(vec![
+                Requirement {
+                    op: Op::Ge,
+                    version: Version::V30
+                },
+                Requirement {
+                    op: Op::Lt,
+                    version: Version::V32
+                },
+            ])
+        );
+        assert_eq!(
+            parse_condition("=3.1a"),
+            Ok(vec![Requirement {
+                op: Op::Eq,
+                version: Version::V31a
+            }])
+        );
+        assert!(parse_condition("~ 3.0").is_err());
+        assert!(parse_condition(">= 1.8").is_err());
+        assert!(parse_condition("").is_err());
+    }
+
+    #[test]
+    fn test_is_applicable() {
+        let contents = "# nmk: tmux >= 3.0, < 3.2\nset -g mouse on\n";
+        assert_eq!(is_applicable(contents, Version::V29a), Ok(false));
+        assert_eq!(is_applicable(contents, Version::V30), Ok(true));
+        assert_eq!(is_applicable(contents, Version::V31c), Ok(true));
+        assert_eq!(is_applicable(contents, Version::V32), Ok(false));
+        assert_eq!(is_applicable("set -g mouse on\n", Version::V26), Ok(true));
+        assert_eq!(is_applicable("", Version::V26), Ok(true));
+        // other comments are not conditions
+        assert_eq!(is_applicable("# tmux >= 3.2\n", Version::V26), Ok(true));
+        assert!(is_applicable("# nmk: tmux newest\n", Version::V26).is_err());
+    }
+
+    #[test]
+    fn test_collect() {
+        let root = TempDir::new("overlay");
+        let (system, user) = (root.join("system/tmux.d"), root.join("user/tmux.d"));
+        fs::create_dir_all(system.join("3.0")).unwrap();
+        fs::create_dir_all(user.join("3.2")).unwrap();
+        fs::create_dir_all(user.join("next")).unwrap();
+        fs::write(system.join("keys.conf"), "bind-key a\n").unwrap();
+        fs::write(system.join("3.0/popup.conf"), "bind-key p\n").unwrap();
+        fs::write(user.join("b.conf"), "bind-key b\n").unwrap();
+        fs::write(user.join("a.conf"), "# nmk: tmux < 3.0\nbind-key c\n").unwrap();
+        fs::write(user.join("notes.txt"), "not an overlay\n").unwrap();
+        fs::write(user.join("3.2/menu.conf"), "bind-key m\n").unwrap();
+        fs::write(user.join("next/x.conf"), "bind-key x\n").unwrap();
+
+        let dirs = vec![system.clone(), user.clone(), root.join("missing")];
+        let names = |v| -> Vec<PathBuf> {
+            collect(&dirs, v)
+                .into_iter()
+                .map(|o| o.path.strip_prefix(&root).unwrap().to_path_buf())
+                .collect()
+        };
+        assert_eq!(
+            names(Version::V29),
+            vec![
+                PathBuf::from("system/tmux.d/keys.conf"),
+                PathBuf::from("user/tmux.d/a.conf"),
+                PathBuf::from("user/tmux.d/b.conf"),
+            ]
+        );
+        assert_eq!(
+            names(Version::V32),
+            vec![
+                PathBuf::from("system/tmux.d/keys.conf"),
+                PathBuf::from("system/tmux.d/3.0/popup.conf"),
+                PathBuf::from("user/tmux.d/b.conf"),
+                PathBuf::from("user/tmux.d/3.2/menu.conf"),
+            ]
+        );
+    }
+}
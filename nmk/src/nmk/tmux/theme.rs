@@ -0,0 +1,427 @@
+//! Colour themes of tmux status line, borders and messages
+//!
+//! A theme is a TOML file of colours, each one is `default`, one of 8 colour names, `colourN`
+//! or `#rrggbb`. Colours are converted down to what the terminal supports.
+
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer};
+
+use crate::error::Context;
+
+use super::capability::Feature;
+use super::version::Version;
+
+pub const DEFAULT_THEME: &str = "nmk";
+
+const BUILT_IN: &[(&str, &str)] = &[
+    ("nmk", include_str!("themes/nmk.toml")),
+    ("gruvbox", include_str!("themes/gruvbox.toml")),
+    ("nord", include_str!("themes/nord.toml")),
+    ("solarized-dark", include_str!("themes/solarized-dark.toml")),
+];
+
+const NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// xterm default values of the first 16 colours
+const ANSI_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ColorDepth {
+    Ansi8,
+    Color256,
+    TrueColor,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Color {
+    Default,
+    /// 0 to 255, the first 8 are rendered by name
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn to_rgb(self) -> Option<(u8, u8, u8)> {
+        match self {
+            Color::Default => None,
+            Color::Rgb(r, g, b) => Some((r, g, b)),
+            Color::Indexed(n @ 0..=15) => Some(ANSI_RGB[usize::from(n)]),
+            Color::Indexed(n @ 16..=231) => {
+                let n = n - 16;
+                let level = |i: u8| CUBE_LEVELS[usize::from(i)];
+                Some((level(n / 36), level(n / 6 % 6), level(n % 6)))
+            }
+            Color::Indexed(n) => {
+                let gray = 8 + 10 * (n - 232);
+                Some((gray, gray, gray))
+            }
+        }
+    }
+
+    /// Convert to what `depth` can show
+    pub fn downgrade(self, depth: ColorDepth) -> Self {
+        match (self, depth) {
+            (Color::Default, _) | (_, ColorDepth::TrueColor) => self,
+            (Color::Indexed(_), ColorDepth::Color256) => self,
+            (Color::Rgb(r, g, b), ColorDepth::Color256) => {
+                Color::Indexed(nearest(16..=255, (r, g, b)))
+            }
+            (Color::Indexed(n), ColorDepth::Ansi8) if n < 8 => self,
+            (Color::Indexed(n), ColorDepth::Ansi8) if n < 16 => Color::Indexed(n - 8),
+            (_, ColorDepth::Ansi8) => match self.to_rgb() {
+                Some(rgb) => Color::Indexed(nearest(0..=7, rgb)),
+                None => self,
+            },
+        }
+    }
+}
+
+/// Index of the closest colour by squared distance
+fn nearest<I: Iterator<Item = u8>>(indexes: I, (r, g, b): (u8, u8, u8)) -> u8 {
+    let distance = |n: &u8| {
+        let (r2, g2, b2) = Color::Indexed(*n).to_rgb().unwrap_or_default();
+        let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2);
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+    indexes.min_by_key(distance).unwrap_or_default()
+}
+
+impl Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Color::Default => f.write_str("default"),
+            Color::Indexed(n) if n < 8 => f.write_str(NAMES[usize::from(n)]),
+            Color::Indexed(n) => write!(f, "colour{}", n),
+            Color::Rgb(r, g, b) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid colour {:?}", s);
+        if s == "default" {
+            return Ok(Color::Default);
+        }
+        if let Some(n) = NAMES.iter().position(|name| *name == s) {
+            return Ok(Color::Indexed(n as u8));
+        }
+        if let Some(n) = s.strip_prefix("colour").or_else(|| s.strip_prefix("color")) {
+            return n.parse().map(Color::Indexed).map_err(|_| err());
+        }
+        match s.strip_prefix('#') {
+            Some(hex) if hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                let channel = |i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| err());
+                Ok(Color::Rgb(channel(0)?, channel(2)?, channel(4)?))
+            }
+            _ => Err(err()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Hash, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+}
+
+impl Style {
+    fn render(&self, depth: ColorDepth) -> Option<String> {
+        let parts: Vec<_> = [("fg", self.fg), ("bg", self.bg)]
+            .iter()
+            .filter_map(|(name, color)| color.map(|c| format!("{}={}", name, c.downgrade(depth))))
+            .collect();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(","))
+        }
+    }
+}
+
+/// Missing entries are left to tmux defaults
+#[derive(Clone, Debug, Default, Deserialize, Hash, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Theme {
+    pub status: Style,
+    pub window_status: Style,
+    pub window_status_current: Style,
+    pub pane_border: Style,
+    pub pane_active_border: Style,
+    pub message: Style,
+    pub display_panes_active: Option<Color>,
+    pub display_panes: Option<Color>,
+    pub clock: Option<Color>,
+    /// Pane title on the right of status line
+    pub title: Option<Color>,
+}
+
+impl Theme {
+    pub fn built_in(name: &str) -> Option<Self> {
+        BUILT_IN
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, data)| toml::from_str(data).expect("invalid built-in theme"))
+    }
+
+    pub fn built_in_names() -> impl Iterator<Item = &'static str> {
+        BUILT_IN.iter().map(|(name, _)| *name)
+    }
+
+    pub fn from_file(path: &Path) -> crate::Result<Self> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&data).with_context(|| format!("invalid theme {}", path.display()))
+    }
+
+    /// Find theme `name` in `dirs` first then in built-in themes
+    ///
+    /// Unknown or broken theme is reported and replaced by the default one.
+    pub fn load<P: AsRef<Path>>(name: &str, dirs: &[P]) -> Self {
+        let file_name = format!("{}.toml", name);
+        let user_file = dirs
+            .iter()
+            .map(|dir| dir.as_ref().join(&file_name))
+            .find(|path| path.exists());
+        let theme = match user_file {
+            Some(path) => Self::from_file(&path).map_err(|e| format!("{:#}", e)),
+            None => Self::built_in(name).ok_or_else(|| format!("unknown theme {:?}", name)),
+        };
+        theme.unwrap_or_else(|e| {
+            log::warn!("{}, using {} theme", e, DEFAULT_THEME);
+            Self::default_theme()
+        })
+    }
+
+    pub fn default_theme() -> Self {
+        Self::built_in(DEFAULT_THEME).expect("missing default theme")
+    }
+
+    /// Render styles, `truecolor_term` is the pattern of terminals which get RGB capability
+    pub fn render(
+        &self,
+        w: &mut dyn Write,
+        depth: ColorDepth,
+        truecolor_term: Option<&str>,
+        v: Version,
+    ) -> io::Result<()> {
+        if let (ColorDepth::TrueColor, Some(term)) = (depth, truecolor_term) {
+            if v.supports(Feature::TerminalFeatures) {
+                writeln!(w, r#"set-option -sa terminal-features ",{}:RGB""#, term)?;
+            } else {
+                writeln!(w, r#"set-option -sa terminal-overrides ",{}:Tc""#, term)?;
+            }
+        }
+        let styles = [
+            ("set-option", "status-style", &self.status),
+            (
+                "set-window-option",
+                "window-status-style",
+                &self.window_status,
+            ),
+            (
+                "set-window-option",
+                "window-status-current-style",
+                &self.window_status_current,
+            ),
+            ("set-option", "pane-border-style", &self.pane_border),
+            (
+                "set-option",
+                "pane-active-border-style",
+                &self.pane_active_border,
+            ),
+            ("set-option", "message-style", &self.message),
+        ];
+        for (command, option, style) in styles.iter() {
+            if let Some(style) = style.render(depth) {
+                writeln!(w, "{} -g {} {}", command, option, style)?;
+            }
+        }
+        let colours = [
+            (
+                "set-option",
+                "display-panes-active-colour",
+                self.display_panes_active,
+            ),
+            ("set-option", "display-panes-colour", self.display_panes),
+            ("set-window-option", "clock-mode-colour", self.clock),
+        ];
+        for (command, option, color) in colours.iter() {
+            if let Some(color) = color {
+                writeln!(w, "{} -g {} {}", command, option, color.downgrade(depth))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Style which `status-right` puts before the pane title
+    pub fn title_style(&self, depth: ColorDepth) -> String {
+        match self.title {
+            Some(color) => format!("#[fg={}]", color.downgrade(depth)),
+            None => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn render(theme: &Theme, depth: ColorDepth, v: Version) -> String {
+        let mut buf = Vec::new();
+        theme
+            .render(&mut buf, depth, Some("xterm-kitty"), v)
+            .unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!("default".parse(), Ok(Color::Default));
+        assert_eq!("black".parse(), Ok(Color::Indexed(0)));
+        assert_eq!("white".parse(), Ok(Color::Indexed(7)));
+        assert_eq!("colour254".parse(), Ok(Color::Indexed(254)));
+        assert_eq!("color8".parse(), Ok(Color::Indexed(8)));
+        assert_eq!("#88C0d0".parse(), Ok(Color::Rgb(0x88, 0xc0, 0xd0)));
+        assert!("colour256".parse::<Color>().is_err());
+        assert!("#88c0d".parse::<Color>().is_err());
+        assert!("#88c0dz".parse::<Color>().is_err());
+        assert!("purple".parse::<Color>().is_err());
+        for s in &["default", "red", "colour51", "#0a0b0c"] {
+            assert_eq!(s.parse::<Color>().unwrap().to_string(), *s);
+        }
+    }
+
+    #[test]
+    fn test_downgrade() {
+        use ColorDepth::*;
+        let rgb = Color::Rgb(0x00, 0xff, 0xff);
+        assert_eq!(rgb.downgrade(TrueColor), rgb);
+        assert_eq!(rgb.downgrade(Color256), Color::Indexed(51));
+        assert_eq!(rgb.downgrade(Ansi8), Color::Indexed(6));
+        // grayscale ramp is closer than the cube
+        assert_eq!(
+            Color::Rgb(0x3a, 0x3a, 0x3a).downgrade(Color256),
+            Color::Indexed(237)
+        );
+        assert_eq!(Color::Indexed(236).downgrade(Ansi8), Color::Indexed(0));
+        assert_eq!(Color::Indexed(254).downgrade(Ansi8), Color::Indexed(7));
+        assert_eq!(Color::Indexed(166).downgrade(Ansi8), Color::Indexed(1));
+        assert_eq!(Color::Indexed(9).downgrade(Ansi8), Color::Indexed(1));
+        assert_eq!(Color::Indexed(3).downgrade(Ansi8), Color::Indexed(3));
+        assert_eq!(Color::Default.downgrade(Ansi8), Color::Default);
+    }
+
+    #[test]
+    fn test_built_in_themes() {
+        for name in Theme::built_in_names() {
+            let theme = Theme::built_in(name).unwrap();
+            assert!(theme.status.fg.is_some(), "{}", name);
+            for depth in &[ColorDepth::Ansi8, ColorDepth::Color256] {
+                // no hex colour left
+                let rendered = render(&theme, *depth, Version::V32) + &theme.title_style(*depth);
+                assert!(
+                    !rendered.contains("=#") && !rendered.contains(" #"),
+                    "{}",
+                    name
+                );
+                assert!(!rendered.contains("terminal-"));
+            }
+        }
+        assert!(Theme::built_in("missing").is_none());
+    }
+
+    /// Default theme renders the same as the original 256 colour configuration, colour7 is white
+    #[test]
+    fn test_default_theme() {
+        let rendered = render(&Theme::default_theme(), ColorDepth::Color256, Version::V32);
+        let expected = "\
+set-option -g status-style fg=colour254,bg=colour236
+set-window-option -g window-status-style fg=white,bg=default
+set-window-option -g window-status-current-style fg=colour51,bg=default
+set-option -g pane-border-style fg=colour33
+set-option -g pane-active-border-style fg=colour166
+set-option -g message-style fg=black,bg=colour116
+set-option -g display-panes-active-colour colour44
+set-option -g display-panes-colour white
+set-window-option -g clock-mode-colour colour154
+";
+        assert_eq!(rendered, expected);
+        let title = Theme::default_theme().title_style(ColorDepth::Color256);
+        assert_eq!(title, "#[fg=colour51]");
+    }
+
+    #[test]
+    fn test_truecolor() {
+        let theme = Theme::built_in("nord").unwrap();
+        let rendered = render(&theme, ColorDepth::TrueColor, Version::V32);
+        assert!(rendered.starts_with("set-option -sa terminal-features \",xterm-kitty:RGB\"\n"));
+        assert!(rendered.contains("status-style fg=#d8dee9,bg=#3b4252\n"));
+        let rendered = render(&theme, ColorDepth::TrueColor, Version::V31c);
+        assert!(rendered.starts_with("set-option -sa terminal-overrides \",xterm-kitty:Tc\"\n"));
+        let mut buf = Vec::new();
+        theme
+            .render(&mut buf, ColorDepth::TrueColor, None, Version::V32)
+            .unwrap();
+        assert!(!String::from_utf8(buf).unwrap().contains("terminal-"));
+    }
+
+    #[test]
+    fn test_user_theme() {
+        let dir = TempDir::new("theme");
+        fs::write(dir.join("mine.toml"), "status = { fg = \"#ffffff\" }\n").unwrap();
+        fs::write(dir.join("broken.toml"), "status = \"red\"\n").unwrap();
+        // user theme overrides built-in one of the same name
+        fs::write(dir.join("nord.toml"), "clock = \"red\"\n").unwrap();
+        let dirs = [dir.to_path_buf()];
+
+        let theme = Theme::load("mine", &dirs);
+        assert_eq!(theme.status.fg, Some(Color::Rgb(255, 255, 255)));
+        assert_eq!(theme.clock, None);
+        assert_eq!(Theme::load("nord", &dirs).clock, Some(Color::Indexed(1)));
+        assert_eq!(Theme::load("broken", &dirs), Theme::default_theme());
+        assert_eq!(Theme::load("missing", &dirs), Theme::default_theme());
+        assert_eq!(
+            Theme::load("gruvbox", &dirs),
+            Theme::built_in("gruvbox").unwrap()
+        );
+    }
+}
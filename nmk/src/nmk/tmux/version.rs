@@ -1,40 +1,30 @@
-#[derive(
-    Clone,
-    Copy,
-    Debug,
-    Eq,
-    Ord,
-    PartialEq,
-    PartialOrd,
-    strum::AsStaticStr,
-    strum::Display,
-    strum::EnumString,
-)]
-pub enum Version {
-    #[strum(to_string = "2.6")]
-    V26,
-    #[strum(to_string = "2.7")]
-    V27,
-    #[strum(to_string = "2.8")]
-    V28,
-    #[strum(to_string = "2.9")]
-    V29,
-    #[strum(to_string = "2.9a")]
-    V29a,
-    #[strum(to_string = "3.0")]
-    V30,
-    #[strum(to_string = "3.0a")]
-    V30a,
-    #[strum(to_string = "3.1")]
-    V31,
-    #[strum(to_string = "3.1a")]
-    V31a,
-    #[strum(to_string = "3.1b")]
-    V31b,
-    #[strum(to_string = "3.1c")]
-    V31c,
-    #[strum(to_string = "3.2")]
-    V32,
+//! Version of tmux as printed by `tmux -V`
+//!
+//! Releases look like `3.1c`, release candidates like `3.2-rc2` and builds from the development
+//! branch like `next-3.4`. OpenBSD ships tmux in base as `openbsd-7.4`, which is mapped to the
+//! tmux release of that time. Versions newer than what nmk knows compare greater than every known
+//! one, so they get every feature.
+
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+    /// Letter of bug fix release, `a` of `3.3a`
+    pub patch: Option<char>,
+    pub pre: Option<PreRelease>,
+}
+
+/// Pre-release of the next minor version
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum PreRelease {
+    /// Development branch, `next-3.4`
+    Next,
+    /// Release candidate, `3.4-rc` is `Rc(0)`, `3.2-rc2` is `Rc(2)`
+    Rc(u8),
 }
 
 #[derive(Debug)]
@@ -43,7 +33,81 @@ pub enum TmuxVersionError {
     Unsupported(String),
 }
 
+/// OpenBSD releases and tmux releases they are close to
+const OPENBSD: &[((u16, u16), Version)] = &[
+    ((6, 4), Version::V28),
+    ((6, 5), Version::V29),
+    ((6, 6), Version::V30),
+    ((6, 7), Version::V31),
+    ((6, 8), Version::V31c),
+    ((6, 9), Version::V32),
+    ((7, 0), Version::V32a),
+    ((7, 1), Version::V33),
+    ((7, 2), Version::V33a),
+    ((7, 3), Version::V33a),
+    ((7, 4), Version::V34),
+    ((7, 5), Version::V34),
+    ((7, 6), Version::V35),
+    ((7, 7), Version::V35a),
+];
+
+// Named like releases, `V31c` is 3.1c
+#[allow(non_upper_case_globals)]
 impl Version {
+    pub const V26: Version = Version::release(2, 6, None);
+    pub const V27: Version = Version::release(2, 7, None);
+    pub const V28: Version = Version::release(2, 8, None);
+    pub const V29: Version = Version::release(2, 9, None);
+    pub const V29a: Version = Version::release(2, 9, Some('a'));
+    pub const V30: Version = Version::release(3, 0, None);
+    pub const V30a: Version = Version::release(3, 0, Some('a'));
+    pub const V31: Version = Version::release(3, 1, None);
+    pub const V31a: Version = Version::release(3, 1, Some('a'));
+    pub const V31b: Version = Version::release(3, 1, Some('b'));
+    pub const V31c: Version = Version::release(3, 1, Some('c'));
+    pub const V32: Version = Version::release(3, 2, None);
+    pub const V32a: Version = Version::release(3, 2, Some('a'));
+    pub const V33: Version = Version::release(3, 3, None);
+    pub const V33a: Version = Version::release(3, 3, Some('a'));
+    pub const V34: Version = Version::release(3, 4, None);
+    pub const V35: Version = Version::release(3, 5, None);
+    pub const V35a: Version = Version::release(3, 5, Some('a'));
+
+    /// Known releases in order
+    pub const RELEASES: &'static [Version] = &[
+        Version::V26,
+        Version::V27,
+        Version::V28,
+        Version::V29,
+        Version::V29a,
+        Version::V30,
+        Version::V30a,
+        Version::V31,
+        Version::V31a,
+        Version::V31b,
+        Version::V31c,
+        Version::V32,
+        Version::V32a,
+        Version::V33,
+        Version::V33a,
+        Version::V34,
+        Version::V35,
+        Version::V35a,
+    ];
+
+    pub const OLDEST: Version = Version::V26;
+    /// Newest release nmk knows about, unknown builds are assumed to be at least this
+    pub const NEWEST: Version = Version::V35a;
+
+    pub const fn release(major: u16, minor: u16, patch: Option<char>) -> Self {
+        Version {
+            major,
+            minor,
+            patch,
+            pre: None,
+        }
+    }
+
     // Parse `tmux -V` output
     pub fn from_version_output(output: &[u8]) -> Result<Self, TmuxVersionError> {
         let output = String::from_utf8_lossy(output);
@@ -54,19 +118,124 @@ impl Version {
     }
 
     pub fn from_version(s: &str) -> Result<Self, TmuxVersionError> {
-        s.parse()
-            .map_err(|_| TmuxVersionError::Unsupported(s.to_string()))
+        let unsupported = || TmuxVersionError::Unsupported(s.to_string());
+        let version: Version = s.parse().map_err(|_| unsupported())?;
+        if version < Version::OLDEST {
+            return Err(unsupported());
+        }
+        Ok(version)
+    }
+
+    /// Newer than every release nmk knows about
+    pub fn is_unknown_newer(&self) -> bool {
+        *self > Version::NEWEST
+    }
+
+    /// Release is greater than its pre-releases, then bug fix releases follow
+    fn sort_key(&self) -> (u16, u16, u16, Option<char>) {
+        let pre = match self.pre {
+            Some(PreRelease::Next) => 0,
+            Some(PreRelease::Rc(n)) => 1 + u16::from(n),
+            None => u16::MAX,
+        };
+        (self.major, self.minor, pre, self.patch)
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.pre == Some(PreRelease::Next) {
+            f.write_str("next-")?;
+        }
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if let Some(patch) = self.patch {
+            write!(f, "{}", patch)?;
+        }
+        match self.pre {
+            Some(PreRelease::Rc(0)) => f.write_str("-rc"),
+            Some(PreRelease::Rc(n)) => write!(f, "-rc{}", n),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `3.3`, `3.3a`, `3.2-rc2`, `next-3.4`, `openbsd-7.4` or `master`
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid tmux version {:?}", s);
+        if s == "master" {
+            return Ok(Version::NEWEST);
+        }
+        if let Some(release) = s.strip_prefix("openbsd-") {
+            let openbsd = parse_major_minor(release).ok_or_else(err)?;
+            if openbsd > OPENBSD[OPENBSD.len() - 1].0 {
+                return Ok(Version::NEWEST);
+            }
+            return OPENBSD
+                .iter()
+                .find(|(known, _)| *known == openbsd)
+                .map(|(_, version)| *version)
+                .ok_or_else(err);
+        }
+        if let Some(next) = s.strip_prefix("next-") {
+            let (major, minor) = parse_major_minor(next).ok_or_else(err)?;
+            return Ok(Version {
+                pre: Some(PreRelease::Next),
+                ..Version::release(major, minor, None)
+            });
+        }
+        let (release, pre) = match s.split_once("-rc") {
+            Some((release, "")) => (release, Some(PreRelease::Rc(0))),
+            Some((release, n)) => {
+                let n = n.parse().map_err(|_| err())?;
+                (release, Some(PreRelease::Rc(n)))
+            }
+            None => (s, None),
+        };
+        let patch = release.chars().last().filter(char::is_ascii_lowercase);
+        let number = match patch {
+            Some(_) => &release[..release.len() - 1],
+            None => release,
+        };
+        let (major, minor) = parse_major_minor(number).ok_or_else(err)?;
+        Ok(Version {
+            pre,
+            ..Version::release(major, minor, patch)
+        })
     }
+}
 
-    pub fn as_str(&self) -> &'static str {
-        strum::AsStaticRef::as_static(self)
+fn parse_major_minor(s: &str) -> Option<(u16, u16)> {
+    let (major, minor) = s.split_once('.')?;
+    let is_number = |n: &str| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit());
+    if !is_number(major) || !is_number(minor) {
+        return None;
     }
+    Some((major.parse().ok()?, minor.parse().ok()?))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn v(s: &str) -> Version {
+        Version::from_version(s).unwrap()
+    }
+
     #[test]
     fn test_parse_version() {
         let actual = Version::from_version_output(b"tmux 3.1b");
@@ -75,5 +244,76 @@ mod tests {
         assert!(matches!(actual, Ok(Version::V31c)));
         let actual = Version::from_version_output(b"tmux 3.2");
         assert!(matches!(actual, Ok(Version::V32)));
+        let actual = Version::from_version_output(b"tmux 3.3a\n");
+        assert!(matches!(actual, Ok(Version::V33a)));
+        let actual = Version::from_version_output(b"tmux next-3.4");
+        assert!(matches!(
+            actual,
+            Ok(Version {
+                major: 3,
+                minor: 4,
+                patch: None,
+                pre: Some(PreRelease::Next)
+            })
+        ));
+        let actual = Version::from_version_output(b"tmux openbsd-7.4");
+        assert!(matches!(actual, Ok(Version::V34)));
+        let actual = Version::from_version_output(b"tmux openbsd-9.1");
+        assert!(matches!(actual, Ok(Version::NEWEST)));
+        let actual = Version::from_version_output(b"tmux master");
+        assert!(matches!(actual, Ok(Version::NEWEST)));
+
+        assert!(matches!(
+            Version::from_version_output(b"tmux"),
+            Err(TmuxVersionError::BadOutput(_))
+        ));
+        for unsupported in &[
+            "2.5",
+            "1.8",
+            "openbsd-5.9",
+            "3",
+            "3.x",
+            "3.2-rcx",
+            "next",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    Version::from_version(unsupported),
+                    Err(TmuxVersionError::Unsupported(_))
+                ),
+                "{}",
+                unsupported
+            );
+        }
+    }
+
+    #[test]
+    fn test_ordering() {
+        let ordered = [
+            "2.6", "2.9", "2.9a", "3.0", "3.1c", "3.2-rc", "3.2-rc2", "3.2-rc3", "3.2", "3.2a",
+            "3.3a", "next-3.4", "3.4-rc", "3.4", "3.5a", "next-3.6", "3.6", "3.10", "4.0",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(v(pair[0]) < v(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+        assert!(v("3.6").is_unknown_newer());
+        assert!(v("next-3.6").is_unknown_newer());
+        assert!(!Version::V35a.is_unknown_newer());
+        assert!(Version::RELEASES.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(Version::RELEASES.first(), Some(&Version::OLDEST));
+        assert_eq!(Version::RELEASES.last(), Some(&Version::NEWEST));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // NMK_TMUX_VERSION is exported with Display and parsed back
+        for s in &[
+            "2.6", "3.1c", "3.2-rc", "3.2-rc2", "next-3.4", "3.10", "4.0a",
+        ] {
+            assert_eq!(v(s).to_string(), *s);
+        }
+        let openbsd = v("openbsd-7.2");
+        assert_eq!(v(&openbsd.to_string()), openbsd);
     }
 }
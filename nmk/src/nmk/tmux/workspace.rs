@@ -0,0 +1,355 @@
+//! Sessions built from workspace files
+//!
+//! A workspace is `workspaces/<name>.toml` under NMK_HOME, listing windows and their panes. The
+//! session is built one tmux command at a time, each new pane is targeted by the id which tmux
+//! prints, so user options like `base-index` don't matter.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::error::{Context, Error, ErrorKind};
+
+use super::capability::Feature;
+use super::version::Version;
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Workspace {
+    /// Session name, name of the file by default
+    pub session: Option<String>,
+    /// Starting directory of panes, relative one is under home directory
+    pub root: Option<PathBuf>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default, rename = "window")]
+    pub windows: Vec<Window>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Window {
+    pub name: Option<String>,
+    /// Relative to root of workspace
+    pub root: Option<PathBuf>,
+    /// Layout name like `main-vertical` or layout string, applied after every pane is created
+    pub layout: Option<String>,
+    #[serde(default, rename = "pane")]
+    pub panes: Vec<Pane>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Pane {
+    /// How the pane is split from the previous one, ignored for the first pane
+    #[serde(default)]
+    pub split: Split,
+    /// Relative to root of window
+    pub root: Option<PathBuf>,
+    /// Typed into the shell, so the pane stays when the command exits
+    pub command: Option<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    /// Side by side, `split-window -h`
+    Horizontal,
+    /// Top and bottom, `split-window -v`
+    #[default]
+    Vertical,
+}
+
+impl Split {
+    fn flag(self) -> &'static str {
+        match self {
+            Split::Horizontal => "-h",
+            Split::Vertical => "-v",
+        }
+    }
+}
+
+/// Workspace name is a file name, session name is what tmux keeps as is
+fn check_name(kind: &str, name: &str, forbidden: &[char]) -> crate::Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains(forbidden) {
+        return Err(Error::msg(
+            ErrorKind::Parse,
+            format!("invalid {} name {:?}", kind, name),
+        ));
+    }
+    Ok(())
+}
+
+/// `~` is home directory, relative path is under `base`
+fn resolve(path: &Path, base: Option<&Path>) -> PathBuf {
+    if let Ok(rest) = path.strip_prefix("~") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest);
+        }
+    }
+    match base {
+        Some(base) if path.is_relative() => base.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn env_args(args: &mut Vec<String>, env: &BTreeMap<String, String>) {
+    for (key, value) in env {
+        args.push("-e".to_string());
+        args.push(format!("{}={}", key, value));
+    }
+}
+
+impl Workspace {
+    pub fn parse(name: &str, data: &str) -> crate::Result<Self> {
+        let mut workspace: Workspace = toml::from_str(data)?;
+        let session = workspace.session.get_or_insert_with(|| name.to_string());
+        check_name("session", session, &[':', '.'])?;
+        Ok(workspace)
+    }
+
+    /// Find `name` in `dirs`, the first one wins
+    pub fn load<P: AsRef<Path>>(name: &str, dirs: &[P]) -> crate::Result<Self> {
+        check_name("workspace", name, &['/'])?;
+        let file_name = format!("{}.toml", name);
+        let path = dirs
+            .iter()
+            .map(|dir| dir.as_ref().join(&file_name))
+            .find(|path| path.exists())
+            .ok_or_else(|| {
+                Error::msg(
+                    ErrorKind::Other,
+                    format!("workspace {:?} not found, create {}", name, file_name),
+                )
+            })?;
+        let data = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(name, &data).with_context(|| format!("invalid workspace {}", path.display()))
+    }
+
+    pub fn session(&self) -> &str {
+        self.session.as_deref().unwrap_or_default()
+    }
+
+    fn has_env(&self) -> bool {
+        !self.env.is_empty()
+            || self
+                .windows
+                .iter()
+                .flat_map(|w| &w.panes)
+                .any(|p| !p.env.is_empty())
+    }
+
+    /// Create the session with `run`, which runs tmux with the arguments and returns its output
+    pub fn build<F>(&self, v: Version, mut run: F) -> crate::Result<()>
+    where
+        F: FnMut(&[String]) -> crate::Result<String>,
+    {
+        if self.has_env() && !v.supports(Feature::SpawnEnvironment) {
+            return Err(Error::msg(
+                ErrorKind::Unsupported,
+                format!(
+                    "env of workspace needs tmux {} or later",
+                    Feature::SpawnEnvironment.range().since
+                ),
+            ));
+        }
+        let session = self.session();
+        let root = self
+            .root
+            .as_deref()
+            .map(|root| resolve(root, dirs::home_dir().as_deref()));
+        let default_window = [Window::default()];
+        let windows = if self.windows.is_empty() {
+            &default_window[..]
+        } else {
+            &self.windows
+        };
+        let mut first_pane = None;
+        for window in windows {
+            let window_root = match &window.root {
+                Some(path) => Some(resolve(path, root.as_deref())),
+                None => root.clone(),
+            };
+            let default_pane = [Pane::default()];
+            let panes = if window.panes.is_empty() {
+                &default_pane[..]
+            } else {
+                &window.panes
+            };
+            let mut previous: Option<String> = None;
+            for pane in panes {
+                let mut args: Vec<String> = match (&first_pane, &previous) {
+                    (None, _) => vec![
+                        "new-session".into(),
+                        "-d".into(),
+                        "-s".into(),
+                        session.into(),
+                    ],
+                    (Some(_), None) => {
+                        vec!["new-window".into(), "-t".into(), format!("={}:", session)]
+                    }
+                    (_, Some(previous)) => vec![
+                        "split-window".into(),
+                        pane.split.flag().into(),
+                        "-t".into(),
+                        previous.clone(),
+                    ],
+                };
+                if let (None, Some(name)) = (&previous, &window.name) {
+                    args.push("-n".into());
+                    args.push(name.clone());
+                }
+                let pane_root = match &pane.root {
+                    Some(path) => Some(resolve(path, window_root.as_deref())),
+                    None => window_root.clone(),
+                };
+                if let Some(dir) = pane_root {
+                    args.push("-c".into());
+                    args.push(dir.to_string_lossy().into_owned());
+                }
+                if first_pane.is_none() {
+                    env_args(&mut args, &self.env);
+                }
+                env_args(&mut args, &pane.env);
+                args.extend(["-P", "-F", "#{pane_id}"].iter().map(|s| s.to_string()));
+                let id = run(&args)?.trim().to_string();
+                if let Some(command) = &pane.command {
+                    run(&[
+                        "send-keys".into(),
+                        "-t".into(),
+                        id.clone(),
+                        "-l".into(),
+                        command.clone(),
+                    ])?;
+                    run(&["send-keys".into(), "-t".into(), id.clone(), "Enter".into()])?;
+                }
+                first_pane.get_or_insert_with(|| id.clone());
+                previous = Some(id);
+            }
+            if let (Some(layout), Some(pane)) = (&window.layout, &previous) {
+                run(&[
+                    "select-layout".into(),
+                    "-t".into(),
+                    pane.clone(),
+                    layout.clone(),
+                ])?;
+            }
+        }
+        if let Some(pane) = first_pane {
+            run(&["select-window".into(), "-t".into(), pane.clone()])?;
+            run(&["select-pane".into(), "-t".into(), pane])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    const API: &str = r#"
+        root = "/srv/api"
+        env = { RUST_LOG = "debug" }
+
+        [[window]]
+        name = "editor"
+
+        [[window.pane]]
+        command = "nvim"
+
+        [[window.pane]]
+        split = "horizontal"
+        root = "tests"
+        command = "cargo watch -x test"
+        env = { RUST_BACKTRACE = "1" }
+
+        [[window]]
+        name = "logs"
+        root = "/var/log"
+        layout = "even-vertical"
+
+        [[window.pane]]
+
+        [[window.pane]]
+    "#;
+
+    fn build(workspace: &Workspace, v: Version) -> crate::Result<Vec<String>> {
+        let mut commands = Vec::new();
+        let mut panes = 0;
+        workspace.build(v, |args| {
+            commands.push(args.join(" "));
+            if args.iter().any(|arg| arg == "#{pane_id}") {
+                panes += 1;
+                return Ok(format!("%{}\n", panes - 1));
+            }
+            Ok(String::new())
+        })?;
+        Ok(commands)
+    }
+
+    #[test]
+    fn test_parse() {
+        let workspace = Workspace::parse("api", API).unwrap();
+        assert_eq!(workspace.session(), "api");
+        assert_eq!(workspace.windows.len(), 2);
+        assert_eq!(workspace.windows[0].panes[1].split, Split::Horizontal);
+        assert_eq!(workspace.windows[1].panes[0].split, Split::Vertical);
+
+        let named = Workspace::parse("api", "session = \"backend\"").unwrap();
+        assert_eq!(named.session(), "backend");
+        assert!(Workspace::parse("api", "session = \"a.b\"").is_err());
+        assert!(Workspace::parse("api", "[[window]]\ncommand = \"ls\"").is_err());
+        assert!(Workspace::parse("api", "[[window.pane]]\nsplit = \"diagonal\"").is_err());
+    }
+
+    #[test]
+    fn test_build() {
+        let workspace = Workspace::parse("api", API).unwrap();
+        let commands = build(&workspace, Version::V32).unwrap();
+        assert_eq!(
+            commands,
+            vec![
+                "new-session -d -s api -n editor -c /srv/api -e RUST_LOG=debug -P -F #{pane_id}",
+                "send-keys -t %0 -l nvim",
+                "send-keys -t %0 Enter",
+                "split-window -h -t %0 -c /srv/api/tests -e RUST_BACKTRACE=1 -P -F #{pane_id}",
+                "send-keys -t %1 -l cargo watch -x test",
+                "send-keys -t %1 Enter",
+                "new-window -t =api: -n logs -c /var/log -P -F #{pane_id}",
+                "split-window -v -t %2 -c /var/log -P -F #{pane_id}",
+                "select-layout -t %3 even-vertical",
+                "select-window -t %0",
+                "select-pane -t %0",
+            ]
+        );
+        assert!(build(&workspace, Version::V31c).is_err());
+    }
+
+    #[test]
+    fn test_build_empty() {
+        let workspace = Workspace::parse("scratch", "").unwrap();
+        assert_eq!(
+            build(&workspace, Version::V26).unwrap(),
+            vec![
+                "new-session -d -s scratch -P -F #{pane_id}",
+                "select-window -t %0",
+                "select-pane -t %0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = TempDir::new("workspace");
+        fs::write(dir.join("api.toml"), API).unwrap();
+        assert!(Workspace::load("api", &[&dir]).is_ok());
+        assert!(Workspace::load("web", &[&dir]).is_err());
+        assert!(Workspace::load("../api", &[&dir]).is_err());
+    }
+}
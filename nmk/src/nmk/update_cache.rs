@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::home::NmkHome;
+
+pub const DOTFILES: &str = "dotfiles";
+pub const ENTRYPOINT: &str = "entrypoint";
+
+/// Minimum interval between two metadata refreshes
+pub const REFRESH_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+/// Remote metadata cached on disk
+///
+/// nmkup writes this file whenever it fetches metadata from remote storage, the entrypoint only
+/// reads it, so shell startup never waits on the network.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct UpdateCache {
+    /// Epoch seconds of the last successful refresh
+    pub checked_at: u64,
+    /// Epoch seconds of the last refresh attempt, this is used to rate limit background refresh
+    pub attempted_at: u64,
+    /// Latest remote generation of each component
+    pub generations: BTreeMap<String, String>,
+}
+
+/// The only field we need from installed metadata
+#[derive(Deserialize)]
+struct InstalledMeta {
+    generation: String,
+}
+
+impl UpdateCache {
+    pub fn load(nmk_home: &NmkHome) -> Option<Self> {
+        let data = fs::read(nmk_home.nmk_path().update_cache()).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    pub fn save(&self, nmk_home: &NmkHome) -> crate::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        fs::write(nmk_home.nmk_path().update_cache(), data)?;
+        Ok(())
+    }
+
+    pub fn is_checked(&self) -> bool {
+        self.checked_at > 0
+    }
+
+    /// Check if it is time to refresh metadata, failed attempts count toward rate limit
+    pub fn should_refresh(&self, now: u64) -> bool {
+        let last = self.checked_at.max(self.attempted_at);
+        now.saturating_sub(last) >= REFRESH_INTERVAL_SECS
+    }
+
+    /// Return components that have newer generation than the installed one
+    pub fn outdated_components(&self, nmk_home: &NmkHome) -> Vec<&'static str> {
+        let nmk_path = nmk_home.nmk_path();
+        let installed = [
+            (DOTFILES, nmk_path.dotfiles_meta()),
+            (ENTRYPOINT, nmk_path.entrypoint_meta()),
+        ];
+        IntoIterator::into_iter(installed)
+            .filter_map(|(component, path)| {
+                let installed = read_installed_generation(&path)?;
+                let remote = self.generations.get(component)?;
+                is_newer(remote, &installed).then_some(component)
+            })
+            .collect()
+    }
+}
+
+fn read_installed_generation(path: &Path) -> Option<String> {
+    let data = fs::read(path).ok()?;
+    let meta: InstalledMeta = serde_json::from_slice(&data).ok()?;
+    Some(meta.generation)
+}
+
+/// Object generation is a timestamp in microseconds, compare numerically when possible
+fn is_newer(remote: &str, installed: &str) -> bool {
+    match (remote.parse::<u64>(), installed.parse::<u64>()) {
+        (Ok(remote), Ok(installed)) => remote > installed,
+        _ => remote != installed,
+    }
+}
+
+pub fn epoch_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_refresh() {
+        let cache = UpdateCache::default();
+        assert!(cache.should_refresh(REFRESH_INTERVAL_SECS));
+
+        let cache = UpdateCache {
+            checked_at: 1000,
+            ..Default::default()
+        };
+        assert!(!cache.should_refresh(1000 + REFRESH_INTERVAL_SECS - 1));
+        assert!(cache.should_refresh(1000 + REFRESH_INTERVAL_SECS));
+
+        let cache = UpdateCache {
+            checked_at: 1000,
+            attempted_at: 5000,
+            ..Default::default()
+        };
+        assert!(!cache.should_refresh(1000 + REFRESH_INTERVAL_SECS));
+    }
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("1620000000000002", "1620000000000001"));
+        assert!(!is_newer("1620000000000001", "1620000000000001"));
+        assert!(!is_newer("1620000000000000", "1620000000000001"));
+        assert!(is_newer("b", "a"));
+    }
+}
@@ -0,0 +1,67 @@
+//! Check rendered config of every supported tmux version with a matching tmux binary
+//!
+//! tmux in PATH is always tried, more binaries can be given in `NMK_TEST_TMUX`, separated by
+//! colons like PATH. Versions without a matching binary are skipped.
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+use nmk::tmux::check::check;
+use nmk::tmux::clipboard::Backend;
+use nmk::tmux::config::Context;
+use nmk::tmux::version::Version;
+
+fn tmux_binaries() -> Vec<PathBuf> {
+    let mut binaries: Vec<PathBuf> = env::var_os("NMK_TEST_TMUX")
+        .map(|list| env::split_paths(&list).collect())
+        .unwrap_or_default();
+    binaries.extend(which::which("tmux").ok());
+    binaries
+}
+
+fn version_of(tmux: &PathBuf) -> Option<Version> {
+    let output = Command::new(tmux).arg("-V").output().ok()?;
+    Version::from_version_output(&output.stdout).ok()
+}
+
+fn contexts() -> Vec<Context> {
+    let base = || Context {
+        support_256_color: true,
+        default_shell: PathBuf::from("/bin/sh"),
+        ..Context::default()
+    };
+    vec![
+        base(),
+        Context {
+            truecolor_term: Some("xterm-kitty".to_string()),
+            clipboard: Backend::Osc52,
+            ..base()
+        },
+        Context {
+            support_256_color: false,
+            clipboard: Backend::Xsel,
+            ..base()
+        },
+    ]
+}
+
+#[test]
+fn test_rendered_config_is_accepted() {
+    let mut checked = Vec::new();
+    for tmux in tmux_binaries() {
+        let v = match version_of(&tmux) {
+            Some(v) => v,
+            None => {
+                eprintln!("{}: unsupported tmux version, skipped", tmux.display());
+                continue;
+            }
+        };
+        for c in contexts() {
+            let errors = check(&tmux, &c, v).unwrap();
+            assert!(errors.is_empty(), "tmux {}: {:#?}", v, errors);
+        }
+        checked.push(v.to_string());
+    }
+    eprintln!("checked tmux versions: {}", checked.join(", "));
+}
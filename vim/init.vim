@@ -6,7 +6,8 @@
 " This must be first, because it changes other options as a side effect.
 set nocompatible
 
-let g:nmk_config_dir = expand('$NMK_HOME/vim')
+" init.vim may be sourced from system-wide installation
+let g:nmk_config_dir = expand('<sfile>:p:h')
 let g:nmk_256color = $TERM =~ '256color'
 
 let s:cache_dir  = expand('$NMK_HOME/.cache')